@@ -0,0 +1,242 @@
+//! 区块导出文件
+//!
+//! 用于把已有的链导出后在新环境中导入。文件以头部开始，之后是按高度升序排列的主链区块：
+//!
+//! ```text
+//! magic(8) | version(u32) | genesis_hash(32) | start(u64) | count(u64)
+//! len(u32) | block ... 共 count 个
+//! ```
+//!
+//! 整数都是大端序，区块用链的编码器编码。头部中的创世 hash 用来拒绝其他链的导出文件，
+//! 读写都是流式的，不需要把整个文件放进内存。
+
+use crate::error::ChainError;
+use latte_codec::codec::Codec;
+use latte_primitives::hash::Hash256;
+use latte_types::block::Block;
+use std::io::{self, Read, Write};
+
+pub const ARCHIVE_MAGIC: &[u8; 8] = b"LATTECHN";
+pub const ARCHIVE_VERSION: u32 = 1;
+/// 单个区块编码后的长度上限，防止损坏的长度前缀导致超大的内存分配
+pub const MAX_ARCHIVED_BLOCK_SIZE: u32 = 64 * 1024 * 1024;
+
+/// 导出文件的头部
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArchiveHeader {
+    pub genesis_hash: Hash256,
+    /// 第一个区块的高度
+    pub start: u64,
+    /// 区块数量
+    pub count: u64,
+}
+
+impl ArchiveHeader {
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(ARCHIVE_MAGIC)?;
+        writer.write_all(&ARCHIVE_VERSION.to_be_bytes())?;
+        writer.write_all(&self.genesis_hash.0)?;
+        writer.write_all(&self.start.to_be_bytes())?;
+        writer.write_all(&self.count.to_be_bytes())
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> Result<Self, ChainError> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != ARCHIVE_MAGIC {
+            return Err(ChainError::InvalidArchive("bad magic".to_string()));
+        }
+        let version = u32::from_be_bytes(read_array(reader)?);
+        if version != ARCHIVE_VERSION {
+            return Err(ChainError::InvalidArchive(format!(
+                "unsupported version {version}"
+            )));
+        }
+        let header = Self {
+            genesis_hash: Hash256(read_array(reader)?),
+            start: u64::from_be_bytes(read_array(reader)?),
+            count: u64::from_be_bytes(read_array(reader)?),
+        };
+        header.check_range()?;
+        Ok(header)
+    }
+
+    /// 头部来自不可信的文件，start + count 不能溢出
+    fn check_range(&self) -> Result<(), ChainError> {
+        if self.start.checked_add(self.count).is_none() {
+            return Err(ChainError::InvalidArchive(format!(
+                "block range {} + {} overflows",
+                self.start, self.count
+            )));
+        }
+        Ok(())
+    }
+
+    /// 第 offset 个区块的高度
+    fn number_at(&self, offset: u64) -> Result<u64, ChainError> {
+        self.start
+            .checked_add(offset)
+            .ok_or_else(|| ChainError::InvalidArchive(format!("block offset {offset} overflows")))
+    }
+}
+
+/// 按顺序写入区块，写完头部声明的数量后调用 [`ArchiveWriter::finish`]
+pub struct ArchiveWriter<W: Write> {
+    writer: W,
+    header: ArchiveHeader,
+    written: u64,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    pub fn new(mut writer: W, header: ArchiveHeader) -> Result<Self, ChainError> {
+        header.check_range()?;
+        header.write_to(&mut writer)?;
+        Ok(Self {
+            writer,
+            header,
+            written: 0,
+        })
+    }
+
+    pub fn write_block<C: Codec>(&mut self, block: &Block, codec: &C) -> Result<(), ChainError> {
+        let expected = self.header.number_at(self.written)?;
+        if self.written >= self.header.count || block.header.number != expected {
+            return Err(ChainError::InvalidArchive(format!(
+                "unexpected block {} (expected {expected})",
+                block.header.number
+            )));
+        }
+        let bytes = codec.encode(block).map_err(ChainError::InvalidArchive)?;
+        let len = u32::try_from(bytes.len())
+            .ok()
+            .filter(|len| *len <= MAX_ARCHIVED_BLOCK_SIZE)
+            .ok_or_else(|| ChainError::InvalidArchive(format!("block {expected} is too large")))?;
+        self.writer.write_all(&len.to_be_bytes())?;
+        self.writer.write_all(&bytes)?;
+        self.written += 1;
+        Ok(())
+    }
+
+    /// 检查区块数量与头部一致并刷新底层的 writer
+    pub fn finish(mut self) -> Result<W, ChainError> {
+        if self.written != self.header.count {
+            return Err(ChainError::InvalidArchive(format!(
+                "wrote {} of {} blocks",
+                self.written, self.header.count
+            )));
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// 逐个读出区块，同时检查高度连续
+pub struct ArchiveReader<R: Read> {
+    reader: R,
+    header: ArchiveHeader,
+    read: u64,
+}
+
+impl<R: Read> ArchiveReader<R> {
+    pub fn new(mut reader: R) -> Result<Self, ChainError> {
+        let header = ArchiveHeader::read_from(&mut reader)?;
+        Ok(Self {
+            reader,
+            header,
+            read: 0,
+        })
+    }
+
+    pub fn header(&self) -> &ArchiveHeader {
+        &self.header
+    }
+
+    /// 读出下一个区块，全部读完后返回 None
+    pub fn read_block<C: Codec>(&mut self, codec: &C) -> Result<Option<Block>, ChainError> {
+        if self.read >= self.header.count {
+            return Ok(None);
+        }
+        let expected = self.header.number_at(self.read)?;
+        let len = u32::from_be_bytes(read_array(&mut self.reader)?);
+        if len > MAX_ARCHIVED_BLOCK_SIZE {
+            return Err(ChainError::InvalidArchive(format!(
+                "block {expected} is too large"
+            )));
+        }
+        let mut bytes = vec![0u8; len as usize];
+        self.reader.read_exact(&mut bytes)?;
+        let block: Block = codec.decode(&bytes).map_err(ChainError::InvalidArchive)?;
+        if block.header.number != expected {
+            return Err(ChainError::InvalidArchive(format!(
+                "unexpected block {} (expected {expected})",
+                block.header.number
+            )));
+        }
+        self.read += 1;
+        Ok(Some(block))
+    }
+}
+
+/// 导入进度，每接入一个区块报告一次
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImportProgress {
+    pub hash: Hash256,
+    pub number: u64,
+    /// 已处理的区块数（包括之前已经在链上的区块）
+    pub imported: u64,
+    pub total: u64,
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N], ChainError> {
+    let mut buf = [0u8; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_roundtrip() {
+        let header = ArchiveHeader {
+            genesis_hash: Hash256([3; 32]),
+            start: 5,
+            count: 7,
+        };
+        let mut bytes = Vec::new();
+        header.write_to(&mut bytes).unwrap();
+        assert_eq!(
+            ArchiveHeader::read_from(&mut bytes.as_slice()).unwrap(),
+            header
+        );
+
+        // 截断的头部
+        assert!(matches!(
+            ArchiveHeader::read_from(&mut &bytes[..20]),
+            Err(ChainError::Io(_))
+        ));
+        // start + count 溢出
+        let overflowing = ArchiveHeader {
+            start: u64::MAX,
+            count: 2,
+            ..header
+        };
+        let mut overflow_bytes = Vec::new();
+        overflowing.write_to(&mut overflow_bytes).unwrap();
+        assert!(matches!(
+            ArchiveHeader::read_from(&mut overflow_bytes.as_slice()),
+            Err(ChainError::InvalidArchive(_))
+        ));
+        assert!(matches!(
+            ArchiveWriter::new(Vec::new(), overflowing),
+            Err(ChainError::InvalidArchive(_))
+        ));
+
+        bytes[0] = b'X';
+        assert!(matches!(
+            ArchiveHeader::read_from(&mut bytes.as_slice()),
+            Err(ChainError::InvalidArchive(_))
+        ));
+    }
+}
@@ -0,0 +1,341 @@
+//! 出块
+//!
+//! [`BlockAssembler`] 从交易池中挑选交易组成新区块：
+//! 1. 按发送方分组，每个发送方的交易按 nonce 排序，只有 nonce 连续的交易才可能执行
+//! 2. 每个发送方当前可执行的交易按 gas_price 从高到低竞争，保证手续费高的交易优先
+//! 3. 在 head 状态之上的覆盖层中逐笔试执行，失败的交易被跳过，同一发送方之后的交易也不再尝试
+//! 4. 累计 gas 达到区块上限后停止，填好各个根后得到完整的区块
+//!
+//! 试执行不会修改链上的状态。
+
+use crate::block_executor::{self, receipt_for};
+use crate::builder::BlockBuilder;
+use crate::canonical::CanonicalEncode;
+use crate::error::ChainError;
+use crate::mempool::TxPool;
+use latte_codec::codec::Codec;
+use latte_primitives::address::Address;
+use latte_primitives::hash::Hash256;
+use latte_state::account_db::{AccountDb, AccountReader};
+use latte_state::executor::Executor;
+use latte_state::overlay::StateOverlay;
+use latte_state::state::WorldState;
+use latte_state::vm::VmEngine;
+use latte_types::block::Block;
+use latte_types::header::BlockHeader;
+use latte_types::receipt::Receipt;
+use latte_types::transaction::Transaction;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+/// 协议没有限制区块 gas 时使用的默认上限
+pub const DEFAULT_BLOCK_GAS_LIMIT: u64 = 10_000_000;
+
+/// 出块参数
+#[derive(Clone, Debug)]
+pub struct BlockAssembler {
+    beneficiary: Address,
+    pub(crate) gas_limit: u64,
+    pub(crate) timestamp: Option<u64>,
+    difficulty: Option<u64>,
+    block_reward: u128,
+    extra_data: Vec<u8>,
+}
+
+/// 组装好的区块，以及因执行失败被跳过的交易
+#[derive(Debug)]
+pub struct AssembledBlock {
+    pub block: Block,
+    pub receipts: Vec<Receipt>,
+    // 区块中交易的手续费总和
+    pub fees: u128,
+    // 执行失败的交易，调用方可以把它们从交易池中移除
+    pub skipped: Vec<(Hash256, ChainError)>,
+}
+
+impl BlockAssembler {
+    pub fn new(beneficiary: Address) -> Self {
+        Self {
+            beneficiary,
+            gas_limit: DEFAULT_BLOCK_GAS_LIMIT,
+            timestamp: None,
+            difficulty: None,
+            block_reward: 0,
+            extra_data: Vec::new(),
+        }
+    }
+
+    pub fn beneficiary(&self) -> Address {
+        self.beneficiary
+    }
+
+    pub fn gas_limit(mut self, gas_limit: u64) -> Self {
+        self.gas_limit = gas_limit;
+        self
+    }
+
+    pub fn timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    pub fn difficulty(mut self, difficulty: u64) -> Self {
+        self.difficulty = Some(difficulty);
+        self
+    }
+
+    pub fn block_reward(mut self, block_reward: u128) -> Self {
+        self.block_reward = block_reward;
+        self
+    }
+
+    pub fn extra_data(mut self, extra_data: Vec<u8>) -> Self {
+        self.extra_data = extra_data;
+        self
+    }
+
+    /// 以 parent 为父区块、parent_state 为父区块执行后的状态组装区块
+    pub fn assemble<C: Codec, V: VmEngine>(
+        &self,
+        parent: &BlockHeader,
+        parent_state: &WorldState,
+        executor: &Executor<'_, V>,
+        pool: &TxPool,
+        codec: &C,
+    ) -> Result<AssembledBlock, ChainError> {
+        let mut state = StateOverlay::new(parent_state);
+        let mut queues = sender_queues(pool, &state);
+        let mut ready: BinaryHeap<Candidate> = queues
+            .values_mut()
+            .filter_map(|queue| queue.pop_front().map(Candidate::new))
+            .collect();
+
+        let mut transactions = Vec::new();
+        let mut receipts = Vec::new();
+        let mut skipped = Vec::new();
+        let mut fees: u128 = 0;
+        let mut gas_used: u64 = 0;
+        while let Some(Candidate { tx, hash }) = ready.pop() {
+            let remaining = self.gas_limit - gas_used;
+            if remaining == 0 {
+                break;
+            }
+            // 放不下的交易留在交易池中，同一发送方之后的交易也要等下一个区块
+            if tx.gas_limit > remaining {
+                continue;
+            }
+
+            // apply_tx 在检查点内执行，失败时覆盖层已经回到执行前的样子，直接跳过即可
+            if let Err(source) = executor.apply_tx(&mut state, tx) {
+                skipped.push((
+                    hash,
+                    ChainError::TxFailed {
+                        index: transactions.len(),
+                        source,
+                    },
+                ));
+                continue;
+            }
+            gas_used += tx.gas_limit;
+            fees += tx.max_fee();
+            receipts.push(receipt_for(tx));
+            transactions.push(tx.clone());
+
+            if let Some(next) = queues.get_mut(&tx.from).and_then(VecDeque::pop_front) {
+                ready.push(Candidate::new(next));
+            }
+        }
+
+        block_executor::pay_beneficiary(&mut state, &self.beneficiary, self.block_reward + fees);
+        let mut builder = BlockBuilder::child_of(parent, codec)?
+            .beneficiary(self.beneficiary)
+            .extra_data(self.extra_data.clone())
+            .transactions(transactions)
+            .receipts(receipts.clone())
+            .state_root(state.state_root());
+        if let Some(timestamp) = self.timestamp {
+            builder = builder.timestamp(timestamp);
+        }
+        if let Some(difficulty) = self.difficulty {
+            builder = builder.difficulty(difficulty);
+        }
+
+        Ok(AssembledBlock {
+            block: builder.build(),
+            receipts,
+            fees,
+            skipped,
+        })
+    }
+}
+
+/// 交易池中每个发送方从当前 nonce 开始连续的交易，nonce 有空缺时后面的交易暂不打包
+fn sender_queues<'a, S: AccountReader>(
+    pool: &'a TxPool,
+    state: &S,
+) -> HashMap<Address, VecDeque<&'a Transaction>> {
+    let mut by_sender: HashMap<Address, Vec<&Transaction>> = HashMap::new();
+    for (_, tx) in pool.iter() {
+        by_sender.entry(tx.from).or_default().push(tx);
+    }
+
+    by_sender
+        .into_iter()
+        .map(|(sender, mut txs)| {
+            // 同一 nonce 有多笔交易时保留 gas_price 最高的一笔
+            txs.sort_by(|a, b| a.nonce.cmp(&b.nonce).then(b.gas_price.cmp(&a.gas_price)));
+            let mut next_nonce = state.get(&sender).map_or(0, |account| account.nonce);
+            let mut queue = VecDeque::new();
+            for tx in txs {
+                if tx.nonce == next_nonce {
+                    queue.push_back(tx);
+                    next_nonce += 1;
+                }
+            }
+            (sender, queue)
+        })
+        .collect()
+}
+
+/// 按 gas_price 排序的候选交易，价格相同时按交易 hash 排序，保证结果确定
+struct Candidate<'a> {
+    tx: &'a Transaction,
+    hash: Hash256,
+}
+
+impl<'a> Candidate<'a> {
+    fn new(tx: &'a Transaction) -> Self {
+        Self {
+            tx,
+            hash: tx.canonical_hash(),
+        }
+    }
+
+    fn key(&self) -> (u64, Reverse<[u8; 32]>) {
+        (self.tx.gas_price, Reverse(self.hash.0))
+    }
+}
+
+impl PartialEq for Candidate<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate<'_> {}
+
+impl PartialOrd for Candidate<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{CHAIN_ID, Fixture};
+    use latte_primitives::crypto::Keypair;
+    use latte_state::error::StateError;
+    use latte_types::builder::TransactionBuilder;
+
+    fn transfer(keypair: &Keypair, nonce: u64, gas_price: u64) -> Transaction {
+        TransactionBuilder::new()
+            .chain_id(CHAIN_ID)
+            .to(Address([7; 20]))
+            .value(1)
+            .nonce(nonce)
+            .gas_limit(1)
+            .gas_price(gas_price)
+            .sign(keypair)
+    }
+
+    #[test]
+    fn test_sender_queues_follow_nonce() {
+        let keypair = Keypair::generate();
+        let sender = Address::from_pubkey(keypair.verifying.as_bytes());
+        let mut state = WorldState::default();
+        state.get_or_create_account_mut(&sender).nonce = 1;
+
+        let mut pool = TxPool::new();
+        // nonce 0 已经用过，nonce 4 前面有空缺
+        for (nonce, gas_price) in [(0, 9), (2, 1), (1, 1), (1, 5), (4, 9)] {
+            pool.insert(transfer(&keypair, nonce, gas_price)).unwrap();
+        }
+        let queues = sender_queues(&pool, &StateOverlay::new(&state));
+        let queue: Vec<(u64, u64)> = queues[&sender]
+            .iter()
+            .map(|tx| (tx.nonce, tx.gas_price))
+            .collect();
+        assert_eq!(queue, vec![(1, 5), (2, 1)]);
+    }
+
+    #[test]
+    fn test_candidate_priority() {
+        let keypair = Keypair::generate();
+        let (cheap, expensive) = (transfer(&keypair, 0, 1), transfer(&keypair, 1, 2));
+        let mut ready: BinaryHeap<Candidate> = [Candidate::new(&cheap), Candidate::new(&expensive)]
+            .into_iter()
+            .collect();
+        assert_eq!(ready.pop().unwrap().tx.gas_price, 2);
+        assert_eq!(ready.pop().unwrap().tx.gas_price, 1);
+    }
+
+    #[test]
+    fn test_assemble_stops_at_gas_limit() {
+        let fixture = Fixture::new();
+        let mut chain = fixture.chain();
+        for nonce in 0..3 {
+            chain.submit_transaction(fixture.transfer(nonce, 1)).unwrap();
+        }
+
+        let assembler = BlockAssembler::new(Address([9; 20])).gas_limit(2);
+        let assembled = chain.assemble_block(&assembler).unwrap();
+        let nonces: Vec<u64> = assembled.block.transactions.iter().map(|tx| tx.nonce).collect();
+        assert_eq!(nonces, vec![0, 1]);
+        assert_eq!((assembled.fees, assembled.receipts.len()), (2, 2));
+        assert!(assembled.skipped.is_empty());
+
+        let hash = chain.append_block(assembled.block).unwrap();
+        assert_eq!(chain.head(), hash);
+        assert_eq!(chain.state().get_account(&Address([9; 20])).unwrap().balance, 2);
+        // 放不下的交易留在交易池中等下一个区块
+        assert_eq!(chain.mempool().len(), 1);
+    }
+
+    #[test]
+    fn test_assemble_skips_failing_tx() {
+        let fixture = Fixture::new();
+        let mut chain = fixture.chain();
+        // 发送方没有账户，执行失败，但手续费更高会先被尝试；
+        // submit_transaction 会拒绝它，直接放进交易池模拟入池后余额被花掉的交易
+        let failing = transfer(&Keypair::generate(), 0, 5);
+        chain.mempool_mut().insert(failing.clone()).unwrap();
+        chain.submit_transaction(fixture.transfer(0, 1)).unwrap();
+
+        let assembled = chain
+            .assemble_block(&BlockAssembler::new(Address([9; 20])))
+            .unwrap();
+        assert_eq!(assembled.block.transactions.len(), 1);
+        assert_eq!(assembled.skipped.len(), 1);
+        assert_eq!(assembled.skipped[0].0, failing.canonical_hash());
+        assert!(matches!(
+            assembled.skipped[0].1,
+            ChainError::TxFailed {
+                index: 0,
+                source: StateError::AccountNotFound
+            }
+        ));
+        assert_eq!(
+            chain.state().state_root(),
+            fixture.state_after(&[]).state_root()
+        );
+        chain.append_block(assembled.block).unwrap();
+        assert_eq!(chain.height(), 1);
+    }
+}
@@ -0,0 +1,78 @@
+//! 无效区块记录
+//!
+//! 校验失败的区块头连同失败原因一起持久化，之后再收到同一个区块或它的子区块时直接拒绝，
+//! 不再重复校验；P2P 模块根据 [`ChainError::KnownBadBlock`](crate::error::ChainError::KnownBadBlock)
+//! 和 [`ChainEvent::BadBlock`](crate::events::ChainEvent::BadBlock) 处罚发送这些区块的节点。
+//!
+//! 区块 hash 只覆盖区块头，只有由区块头决定的失败（见 [`ChainError::is_header_fault`](crate::error::ChainError::is_header_fault)）才会登记，
+//! 否则篡改区块体就能让正确的区块被拒绝；工作量证明链上还要求区块完成了父区块要求的工作量，
+//! 免费伪造的区块不会占用记录。记录数量有上限，超出时淘汰最久没有命中的记录。
+
+use crate::error::ChainError;
+use latte_primitives::hash::Hash256;
+use latte_types::header::BlockHeader;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// 默认最多保留的无效区块记录数
+pub const DEFAULT_MAX_BAD_BLOCKS: usize = 1024;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BadBlock {
+    pub hash: Hash256,
+    pub header: BlockHeader,
+    /// 校验失败的原因
+    pub reason: String,
+    /// 发现时的本地时间
+    pub detected_at: u64,
+}
+
+impl BadBlock {
+    pub fn new(hash: Hash256, header: &BlockHeader, reason: &ChainError, detected_at: u64) -> Self {
+        Self {
+            hash,
+            header: header.clone(),
+            reason: reason.to_string(),
+            detected_at,
+        }
+    }
+
+    /// 导出为 JSON，便于离线调试
+    pub fn dump(&self) -> Result<String, ChainError> {
+        serde_json::to_string_pretty(self).map_err(|e| ChainError::InvalidBlock(e.to_string()))
+    }
+}
+
+/// 已登记的无效区块 hash，按最近命中的顺序排列，记录本身保存在存储中
+#[derive(Debug, Default)]
+pub(crate) struct BadBlockIndex {
+    // 队首是最久没有命中的记录
+    order: VecDeque<Hash256>,
+}
+
+impl BadBlockIndex {
+    /// 命中时移到队尾
+    pub(crate) fn touch(&mut self, hash: &Hash256) -> bool {
+        match self.order.iter().position(|h| h == hash) {
+            Some(position) => {
+                self.order.remove(position);
+                self.order.push_back(*hash);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 再登记一个新的 hash 时需要淘汰的记录，调用方在同一批写入中从存储删除它们
+    pub(crate) fn excess(&self, capacity: usize) -> Vec<Hash256> {
+        let excess = (self.order.len() + 1).saturating_sub(capacity);
+        self.order.iter().take(excess).copied().collect()
+    }
+
+    /// 登记新的 hash，并从队首淘汰 [`BadBlockIndex::excess`] 返回的记录
+    pub(crate) fn insert(&mut self, hash: Hash256, capacity: usize) {
+        self.order.push_back(hash);
+        let excess = self.order.len().saturating_sub(capacity);
+        self.order.drain(..excess);
+    }
+}
@@ -0,0 +1,228 @@
+//! 区块缓存
+//!
+//! 区块头和区块体分别按最近最少使用（LRU）淘汰，容量独立配置：
+//! 区块头小且访问频繁（时间戳中位数、难度调整都要向前遍历祖先），可以多缓存一些。
+//! 缓存未命中时由 [`Blockchain`](crate::blockchain::Blockchain) 从 [`BlockStorage`](crate::storage::BlockStorage) 加载后回填。
+//!
+//! 区块以 `Arc<Block>` 共享，调用方拿到的区块不受之后的淘汰影响
+
+use latte_primitives::hash::Hash256;
+use latte_types::block::Block;
+use latte_types::header::BlockHeader;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub const DEFAULT_BLOCK_CACHE_SIZE: usize = 1024;
+pub const DEFAULT_HEADER_CACHE_SIZE: usize = 8192;
+
+/// 固定容量的 LRU 表，每次访问分配一个递增的序号，淘汰序号最小的条目
+struct Lru<V> {
+    capacity: usize,
+    entries: HashMap<Hash256, (V, u64)>,
+    // 访问序号 -> key，按序号升序即从最久未使用到最近使用
+    order: BTreeMap<u64, Hash256>,
+    tick: u64,
+}
+
+impl<V: Clone> Lru<V> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    fn get(&mut self, key: &Hash256) -> Option<V> {
+        let tick = self.next_tick();
+        let (value, last) = self.entries.get_mut(key)?;
+        self.order.remove(last);
+        self.order.insert(tick, *key);
+        *last = tick;
+        Some(value.clone())
+    }
+
+    fn insert(&mut self, key: Hash256, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        let tick = self.next_tick();
+        if let Some((_, last)) = self.entries.insert(key, (value, tick)) {
+            self.order.remove(&last);
+        }
+        self.order.insert(tick, key);
+        while self.entries.len() > self.capacity {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+
+    fn remove(&mut self, key: &Hash256) {
+        if let Some((_, last)) = self.entries.remove(key) {
+            self.order.remove(&last);
+        }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+/// 缓存命中情况的快照
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheMetrics {
+    pub block_hits: u64,
+    pub block_misses: u64,
+    pub header_hits: u64,
+    pub header_misses: u64,
+    // 当前缓存的区块数和区块头数
+    pub blocks: usize,
+    pub headers: usize,
+}
+
+pub struct BlockCache {
+    blocks: Mutex<Lru<Arc<Block>>>,
+    headers: Mutex<Lru<BlockHeader>>,
+    block_hits: AtomicU64,
+    block_misses: AtomicU64,
+    header_hits: AtomicU64,
+    header_misses: AtomicU64,
+}
+
+impl Default for BlockCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_BLOCK_CACHE_SIZE, DEFAULT_HEADER_CACHE_SIZE)
+    }
+}
+
+impl BlockCache {
+    pub fn new(block_capacity: usize, header_capacity: usize) -> Self {
+        Self {
+            blocks: Mutex::new(Lru::new(block_capacity)),
+            headers: Mutex::new(Lru::new(header_capacity)),
+            block_hits: AtomicU64::new(0),
+            block_misses: AtomicU64::new(0),
+            header_hits: AtomicU64::new(0),
+            header_misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get_block(&self, hash: &Hash256) -> Option<Arc<Block>> {
+        let block = self.blocks.lock().ok()?.get(hash);
+        let counter = if block.is_some() {
+            &self.block_hits
+        } else {
+            &self.block_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        block
+    }
+
+    /// 先查区块头缓存，再查区块缓存，命中区块缓存时回填区块头
+    pub fn get_header(&self, hash: &Hash256) -> Option<BlockHeader> {
+        let mut header = self.headers.lock().ok()?.get(hash);
+        if header.is_none() {
+            let block = self.blocks.lock().ok()?.get(hash);
+            if let Some(block) = block {
+                self.insert_header(*hash, block.header.clone());
+                header = Some(block.header.clone());
+            }
+        }
+        let counter = if header.is_some() {
+            &self.header_hits
+        } else {
+            &self.header_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        header
+    }
+
+    /// 缓存区块，同时缓存它的区块头
+    pub fn insert(&self, hash: Hash256, block: Arc<Block>) {
+        self.insert_header(hash, block.header.clone());
+        if let Ok(mut blocks) = self.blocks.lock() {
+            blocks.insert(hash, block);
+        }
+    }
+
+    pub fn insert_header(&self, hash: Hash256, header: BlockHeader) {
+        if let Ok(mut headers) = self.headers.lock() {
+            headers.insert(hash, header);
+        }
+    }
+
+    pub fn remove(&self, hash: &Hash256) {
+        if let Ok(mut blocks) = self.blocks.lock() {
+            blocks.remove(hash);
+        }
+        if let Ok(mut headers) = self.headers.lock() {
+            headers.remove(hash);
+        }
+    }
+
+    pub fn metrics(&self) -> CacheMetrics {
+        CacheMetrics {
+            block_hits: self.block_hits.load(Ordering::Relaxed),
+            block_misses: self.block_misses.load(Ordering::Relaxed),
+            header_hits: self.header_hits.load(Ordering::Relaxed),
+            header_misses: self.header_misses.load(Ordering::Relaxed),
+            blocks: self.blocks.lock().map_or(0, |blocks| blocks.entries.len()),
+            headers: self
+                .headers
+                .lock()
+                .map_or(0, |headers| headers.entries.len()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::BlockBuilder;
+
+    fn block(timestamp: u64) -> Arc<Block> {
+        Arc::new(BlockBuilder::genesis().timestamp(timestamp).build())
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let cache = BlockCache::new(2, 2);
+        let (a, b, c) = (Hash256([1; 32]), Hash256([2; 32]), Hash256([3; 32]));
+        cache.insert(a, block(1));
+        cache.insert(b, block(2));
+
+        // 访问 a 之后，b 成为最久未使用的条目
+        assert_eq!(cache.get_block(&a).unwrap().header.timestamp, 1);
+        cache.insert(c, block(3));
+        assert!(cache.get_block(&b).is_none());
+        assert!(cache.get_block(&a).is_some());
+        assert!(cache.get_header(&c).is_some());
+
+        let metrics = cache.metrics();
+        assert_eq!(metrics.block_hits, 2);
+        assert_eq!(metrics.block_misses, 1);
+        assert_eq!(metrics.header_hits, 1);
+        assert_eq!(metrics.blocks, 2);
+    }
+
+    #[test]
+    fn test_header_outlives_block() {
+        let cache = BlockCache::new(1, 4);
+        let (a, b) = (Hash256([1; 32]), Hash256([2; 32]));
+        cache.insert(a, block(1));
+        cache.insert(b, block(2));
+
+        assert!(cache.get_block(&a).is_none());
+        assert_eq!(cache.get_header(&a).unwrap().timestamp, 1);
+
+        cache.remove(&a);
+        assert!(cache.get_header(&a).is_none());
+        assert_eq!(cache.metrics().header_misses, 1);
+    }
+}
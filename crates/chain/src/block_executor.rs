@@ -4,32 +4,399 @@
 // •	如何计算：
 // •	tx_root
 // •	state_root
+//
+// 所有修改都发生在 WorldState 的检查点之后，执行成功且各个根都与区块头一致才提交，
+// 任何一步失败都回滚到检查点，不会留下部分执行的状态
+//
+// 校验区块时使用 [`simulate_block`]，在父状态之上的覆盖层中执行，父状态始终不被修改；
+// 校验通过后覆盖层中的修改可以直接应用到父状态上，区块只执行一次
 
+use crate::canonical::CanonicalEncode;
+use crate::error::ChainError;
+use crate::merkle;
+use latte_primitives::address::Address;
+use latte_primitives::bytes::Bytes;
+use latte_primitives::hash::Hash256;
+use latte_state::account_db::AccountDb;
 use latte_state::executor::Executor;
+use latte_state::overlay::{StateChanges, StateOverlay};
 use latte_state::state::WorldState;
 use latte_state::vm::VmEngine;
 use latte_types::block::Block;
+use latte_types::receipt::{Receipt, STATUS_SUCCESS};
 use latte_types::transaction::Transaction;
-use latte_vm::engine::ScriptVm;
-use crate::error::ChainError;
 
 pub struct BlockExecutor<'a, V: VmEngine> {
-    state: &'a WorldState,
-    executor:&'a Executor<'a, V>,
+    state: &'a mut WorldState,
+    executor: &'a Executor<'a, V>,
+    // 每个区块铸造给出块者的奖励
+    block_reward: u128,
+}
+
+/// 执行一个区块得到的结果
+#[derive(Clone, Debug)]
+pub struct BlockOutcome {
+    pub receipts: Vec<Receipt>,
+    pub state_root: Hash256,
+    pub receipt_root: Hash256,
+    pub gas_used: u64,
+    // 支付给出块者的手续费总和
+    pub fees: u128,
+}
+
+/// 模拟执行通过校验的区块：执行结果以及覆盖层中的状态修改
+#[derive(Debug)]
+pub struct SimulatedBlock {
+    pub outcome: BlockOutcome,
+    pub changes: StateChanges,
+}
+
+impl<'a, V: VmEngine> BlockExecutor<'a, V> {
+    pub fn new(state: &'a mut WorldState, executor: &'a Executor<'a, V>) -> Self {
+        Self {
+            state,
+            executor,
+            block_reward: 0,
+        }
+    }
+
+    pub fn with_block_reward(mut self, block_reward: u128) -> Self {
+        self.block_reward = block_reward;
+        self
+    }
+
+    /// 执行区块中的交易并支付奖励与手续费，不校验区块头中的根
+    ///
+    /// 任意一笔交易失败时整体回滚
+    pub fn execute(&mut self, block: &Block) -> Result<BlockOutcome, ChainError> {
+        self.execute_transactions(&block.transactions, &block.header.beneficiary)
+    }
+
+    /// 与 [`BlockExecutor::execute`] 相同，但交易列表和出块者单独给出，供出块前构造区块使用
+    pub fn execute_transactions(
+        &mut self,
+        transactions: &[Transaction],
+        beneficiary: &Address,
+    ) -> Result<BlockOutcome, ChainError> {
+        let checkpoint = self.state.checkpoint();
+        match self.run(transactions, beneficiary) {
+            Ok(outcome) => {
+                self.state.commit(checkpoint);
+                Ok(outcome)
+            }
+            Err(e) => {
+                self.state.revert_to(checkpoint);
+                Err(e)
+            }
+        }
+    }
+
+    /// 处理一个完整的区块，区块理论上是已经经过矿机挖矿后得到的
+    ///
+    /// 执行后依次校验 tx_root、gas_used、receipt_root、state_root，
+    /// 全部一致才提交状态，否则回滚到执行前
+    pub fn apply_block(&mut self, block: &Block) -> Result<BlockOutcome, ChainError> {
+        let header = &block.header;
+        let tx_root = merkle::tx_root_hash(&block.transactions);
+        if tx_root != header.tx_root {
+            return Err(ChainError::TxRootMismatch {
+                expected: header.tx_root,
+                actual: tx_root,
+            });
+        }
+
+        let checkpoint = self.state.checkpoint();
+        let result = self
+            .run(&block.transactions, &header.beneficiary)
+            .and_then(|outcome| check_outcome(&outcome, block).map(|_| outcome));
+
+        match result {
+            Ok(outcome) => {
+                self.state.commit(checkpoint);
+                Ok(outcome)
+            }
+            Err(e) => {
+                self.state.revert_to(checkpoint);
+                Err(e)
+            }
+        }
+    }
+
+    fn run(
+        &mut self,
+        transactions: &[Transaction],
+        beneficiary: &Address,
+    ) -> Result<BlockOutcome, ChainError> {
+        let (receipts, fees) = run_transactions(
+            self.state,
+            self.executor,
+            transactions,
+            beneficiary,
+            self.block_reward,
+        )?;
+        self.state.commit_storage_roots();
+        Ok(outcome(receipts, fees, self.state.state_root()))
+    }
+}
+
+/// 只读模拟：在 parent 之上的覆盖层中执行区块并校验区块头中的各个根
+///
+/// parent 必须是区块父区块执行后的状态，parent 不会被修改；
+/// 校验通过时返回覆盖层中的修改，调用方可以用 [`WorldState::apply_changes`] 把它应用到 parent 上
+pub fn simulate_block<V: VmEngine>(
+    parent: &WorldState,
+    executor: &Executor<'_, V>,
+    block: &Block,
+    block_reward: u128,
+) -> Result<SimulatedBlock, ChainError> {
+    let mut overlay = StateOverlay::new(parent);
+    let (receipts, fees) = run_transactions(
+        &mut overlay,
+        executor,
+        &block.transactions,
+        &block.header.beneficiary,
+        block_reward,
+    )?;
+    let outcome = outcome(receipts, fees, overlay.state_root());
+    check_outcome(&outcome, block)?;
+    Ok(SimulatedBlock {
+        outcome,
+        changes: overlay.into_changes(),
+    })
 }
 
-impl<'a> BlockExecutor<'a, ScriptVm> {
-    pub fn new(state: &'a mut WorldState, executor: &'a Executor<ScriptVm>) -> Self {
-        Self{state,executor}
+/// 在 parent 之上的覆盖层中逐笔执行区块，记录每笔交易的结果以及最终各个根与区块头的对比
+///
+/// 用于调试无效区块，遇到第一笔失败的交易时停止
+pub fn trace_block<V: VmEngine>(
+    parent: &WorldState,
+    executor: &Executor<'_, V>,
+    block: &Block,
+    block_reward: u128,
+) -> Vec<String> {
+    let header = &block.header;
+    let mut overlay = StateOverlay::new(parent);
+    let mut trace = Vec::with_capacity(block.transactions.len() + 3);
+    let mut receipts = Vec::with_capacity(block.transactions.len());
+    let mut fees: u128 = 0;
+    for (index, tx) in block.transactions.iter().enumerate() {
+        let hash = tx.canonical_hash();
+        if let Err(e) = executor.apply_tx(&mut overlay, tx) {
+            trace.push(format!("tx {index} {hash:?}: failed: {e}"));
+            return trace;
+        }
+        trace.push(format!("tx {index} {hash:?}: ok, gas {}", tx.gas_limit));
+        fees += tx.max_fee();
+        receipts.push(receipt_for(tx));
     }
 
-    pub fn execute(&self, block: &Block) -> Result<(), ()> {
-        unimplemented!()
+    pay_beneficiary(&mut overlay, &header.beneficiary, block_reward + fees);
+    let outcome = outcome(receipts, fees, overlay.state_root());
+    trace.push(format!(
+        "gas_used: header {}, computed {}",
+        header.gas_used, outcome.gas_used
+    ));
+    trace.push(format!(
+        "receipt_root: header {:?}, computed {:?}",
+        header.receipt_root, outcome.receipt_root
+    ));
+    trace.push(format!(
+        "state_root: header {:?}, computed {:?}",
+        header.state_root, outcome.state_root
+    ));
+    trace
+}
+
+/// 依次执行交易，并把奖励与手续费支付给出块者
+fn run_transactions<S: AccountDb, V: VmEngine>(
+    state: &mut S,
+    executor: &Executor<'_, V>,
+    transactions: &[Transaction],
+    beneficiary: &Address,
+    block_reward: u128,
+) -> Result<(Vec<Receipt>, u128), ChainError> {
+    let mut receipts = Vec::with_capacity(transactions.len());
+    let mut fees: u128 = 0;
+    for (index, tx) in transactions.iter().enumerate() {
+        executor
+            .apply_tx(state, tx)
+            .map_err(|source| ChainError::TxFailed { index, source })?;
+        fees += tx.max_fee();
+        receipts.push(receipt_for(tx));
     }
 
-    pub fn apply_block(&self, block: &Block) -> Result<(), ChainError> {
-        // 这里是处理区块，区块理论上是已经经过矿机挖矿后得到的
-        //
-        unimplemented!()
+    pay_beneficiary(state, beneficiary, block_reward + fees);
+    Ok((receipts, fees))
+}
+
+/// 奖励与手续费都支付给出块者
+pub(crate) fn pay_beneficiary<S: AccountDb>(state: &mut S, beneficiary: &Address, payout: u128) {
+    if payout > 0 {
+        state.get_or_create(beneficiary).balance += payout;
+    }
+}
+
+fn outcome(receipts: Vec<Receipt>, fees: u128, state_root: Hash256) -> BlockOutcome {
+    BlockOutcome {
+        receipt_root: merkle::receipt_root_hash(&receipts),
+        gas_used: receipts.iter().map(|r| r.gas_used).sum(),
+        state_root,
+        receipts,
+        fees,
+    }
+}
+
+/// 执行结果与区块头逐项比对
+fn check_outcome(outcome: &BlockOutcome, block: &Block) -> Result<(), ChainError> {
+    let header = &block.header;
+    if outcome.gas_used != header.gas_used {
+        return Err(ChainError::GasUsedMismatch {
+            expected: header.gas_used,
+            actual: outcome.gas_used,
+        });
+    }
+    if outcome.receipt_root != header.receipt_root {
+        return Err(ChainError::ReceiptRootMismatch {
+            expected: header.receipt_root,
+            actual: outcome.receipt_root,
+        });
+    }
+    if outcome.state_root != header.state_root {
+        return Err(ChainError::StateRootMismatch {
+            expected: header.state_root,
+            actual: outcome.state_root,
+        });
+    }
+    Ok(())
+}
+
+/// 成功执行的交易回执
+///
+/// 目前按 gas_limit 全额收取手续费，gas_used 与之保持一致
+pub(crate) fn receipt_for(tx: &Transaction) -> Receipt {
+    Receipt::new(
+        Bytes::new(tx.canonical_hash().0.to_vec()),
+        Bytes::new(vec![STATUS_SUCCESS]),
+        tx.gas_limit,
+        Vec::new(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::BlockBuilder;
+    use latte_primitives::crypto::Keypair;
+    use latte_types::builder::TransactionBuilder;
+    use latte_vm::engine::ScriptVm;
+
+    fn funded_state(keypair: &Keypair) -> WorldState {
+        let mut state = WorldState::default();
+        state
+            .get_or_create_account_mut(&Address::from_pubkey(keypair.verifying.as_bytes()))
+            .balance = 1_000;
+        state
+    }
+
+    fn transfer(keypair: &Keypair, nonce: u64) -> Transaction {
+        TransactionBuilder::new()
+            .to(Address([7; 20]))
+            .value(10)
+            .nonce(nonce)
+            .gas_limit(5)
+            .gas_price(2)
+            .sign(keypair)
+    }
+
+    #[test]
+    fn test_apply_block_commits_state() {
+        let keypair = Keypair::generate();
+        let vm = ScriptVm::new();
+        let executor = Executor::new(&vm);
+        let miner = Address([9; 20]);
+
+        let mut producer_state = funded_state(&keypair);
+        let block = BlockBuilder::genesis()
+            .beneficiary(miner)
+            .transactions([transfer(&keypair, 0), transfer(&keypair, 1)])
+            .execute(&mut BlockExecutor::new(&mut producer_state, &executor).with_block_reward(50))
+            .unwrap()
+            .build();
+
+        let mut state = funded_state(&keypair);
+        let outcome = BlockExecutor::new(&mut state, &executor)
+            .with_block_reward(50)
+            .apply_block(&block)
+            .unwrap();
+
+        assert_eq!(outcome.receipts.len(), 2);
+        assert_eq!(outcome.fees, 20);
+        assert_eq!(state.state_root(), block.header.state_root);
+        assert_eq!(state.get_account(&Address([7; 20])).unwrap().balance, 20);
+        assert_eq!(state.get_account(&miner).unwrap().balance, 70);
+    }
+
+    #[test]
+    fn test_apply_block_rolls_back_on_failure() {
+        let keypair = Keypair::generate();
+        let vm = ScriptVm::new();
+        let executor = Executor::new(&vm);
+
+        let mut state = funded_state(&keypair);
+        let root_before = state.state_root();
+
+        // 状态根不一致：所有交易都执行成功后仍需整体回滚
+        let mut block = BlockBuilder::genesis()
+            .transaction(transfer(&keypair, 0))
+            .execute(&mut BlockExecutor::new(
+                &mut funded_state(&keypair),
+                &executor,
+            ))
+            .unwrap()
+            .build();
+        block.header.state_root = Hash256([1; 32]);
+        let result = BlockExecutor::new(&mut state, &executor).apply_block(&block);
+        assert!(matches!(result, Err(ChainError::StateRootMismatch { .. })));
+        assert_eq!(state.state_root(), root_before);
+
+        // 第二笔交易 nonce 重复：第一笔交易的修改也要回滚
+        let block = BlockBuilder::genesis()
+            .transactions([transfer(&keypair, 0), transfer(&keypair, 0)])
+            .build();
+        let result = BlockExecutor::new(&mut state, &executor).apply_block(&block);
+        assert!(matches!(result, Err(ChainError::TxFailed { index: 1, .. })));
+        assert_eq!(state.state_root(), root_before);
+    }
+
+    #[test]
+    fn test_simulate_block_leaves_parent_untouched() {
+        let keypair = Keypair::generate();
+        let vm = ScriptVm::new();
+        let executor = Executor::new(&vm);
+
+        let mut block = BlockBuilder::genesis()
+            .transaction(transfer(&keypair, 0))
+            .execute(&mut BlockExecutor::new(&mut funded_state(&keypair), &executor))
+            .unwrap()
+            .build();
+
+        let mut parent = funded_state(&keypair);
+        let root_before = parent.state_root();
+        let simulated = simulate_block(&parent, &executor, &block, 0).unwrap();
+        assert_eq!(simulated.outcome.state_root, block.header.state_root);
+        assert_eq!(parent.state_root(), root_before);
+
+        // 应用模拟的修改与直接执行区块得到同样的状态
+        let mut executed = funded_state(&keypair);
+        BlockExecutor::new(&mut executed, &executor)
+            .apply_block(&block)
+            .unwrap();
+        parent.apply_changes(simulated.changes);
+        parent.commit_storage_roots();
+        assert_eq!(parent.state_root(), executed.state_root());
+
+        block.header.state_root = Hash256([1; 32]);
+        let result = simulate_block(&funded_state(&keypair), &executor, &block, 0);
+        assert!(matches!(result, Err(ChainError::StateRootMismatch { .. })));
     }
 }
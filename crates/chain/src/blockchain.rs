@@ -1,27 +1,399 @@
-use crate::storage::BlockStorage;
+use crate::archive::{ArchiveHeader, ArchiveReader, ArchiveWriter, ImportProgress};
+use crate::assembler::{AssembledBlock, BlockAssembler};
+use crate::bad_blocks::{BadBlock, BadBlockIndex};
+use crate::block_cache::{BlockCache, CacheMetrics};
+use crate::block_executor::{BlockExecutor, SimulatedBlock};
+use crate::canonical::CanonicalEncode;
+use crate::clock::{Clock, SystemClock};
+use crate::config::ChainConfig;
+use crate::storage::{BlockStorage, StorageBatch, TxLocation};
+use crate::storage_error::StorageError;
 use crate::error::ChainError;
+use crate::events::{ChainEvent, EventBus, Overflow, Subscription};
+use crate::fork_choice::{BlockTree, ForkChoice, HeaviestChain};
+use crate::difficulty::{self, DIFFICULTY_WINDOW};
+use crate::genesis::{ConsensusEngine, ConsensusParams, Genesis};
+use crate::mempool::{MAX_TX_SIZE, TxPool};
+use crate::merkle::{self, MerkleProof};
+use crate::orphan::OrphanPool;
+use crate::signature::{self, VerifiedTxCache};
+use crate::spec::ChainSpec;
 use latte_codec::codec::Codec;
 use latte_primitives::hash::Hash256;
+use latte_state::journal::{Checkpoint, StateUndo};
+use latte_state::state::WorldState;
 use latte_types::block::Block;
-use std::collections::HashMap;
+use latte_types::header::BlockHeader;
+use latte_types::receipt::Receipt;
+use latte_types::transaction::Transaction;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::ops::Range;
 use std::sync::Arc;
 use crate::validator::BlockValidator;
+use crate::verifier::{Divergence, DivergenceKind, StateSnapshot, VerificationReport};
 
-/// 说明：后续添加存储实现时，支持从缓存中如果取不到，从本地存储加载区块，比如rockDB，内存只缓存一部分区块
+/// 按 hash 查询到的主链交易
+#[derive(Debug)]
+pub struct TransactionLookup {
+    pub transaction: Transaction,
+    pub receipt: Receipt,
+    pub block_hash: Hash256,
+    pub block_number: u64,
+    pub index: usize,
+    /// 确认数：所在区块本身算 1 个确认，之后每个主链区块加 1
+    pub confirmations: u64,
+}
+
+/// 已经加入写入批次、写入成功后再应用到内存的最终确认
+struct Finality {
+    hash: Hash256,
+    number: u64,
+    // 与被确认区块冲突、要删除的侧链区块
+    pruned: Vec<Hash256>,
+}
+
+/// 区块体只在内存中缓存一部分，缓存未命中时从 [`BlockStorage`] 加载
 pub struct Blockchain<C: Codec> {
     /// 指向当前主链的最顶端
     head: Hash256,
     /// 当前主链的高度
     height: u64,
-    /// 内存中的热数据缓存 (后续替代为 LruCache)
-    blocks: HashMap<Hash256, Block>,
+    /// 最近使用的区块和区块头，容量有限，淘汰后从 storage 重新加载
+    cache: BlockCache,
+    /// 主链索引，下标为区块高度，随 head 一起更新并持久化
+    canonical: Vec<Hash256>,
+    /// 已执行区块的回执
+    receipts: HashMap<Hash256, Vec<Receipt>>,
+    /// 主链上的交易索引：交易 hash -> 所在区块和位置
+    tx_index: HashMap<Hash256, TxLocation>,
     /// 数据库句柄 (替代 HashMap)
     storage: Box<dyn BlockStorage>,
     /// 统一的编码器，用于计算区块哈希等
     codec: C,
+    /// head 对应的世界状态
+    state: WorldState,
+    /// 所有已知区块组成的区块树，包括侧链
+    tree: BlockTree,
+    /// 分叉选择规则，决定哪个分叉的末端成为 head
+    fork_choice: Box<dyn ForkChoice>,
+    /// 主链上每个区块对状态的修改，链重组时按相反顺序撤销
+    undo_logs: HashMap<Hash256, StateUndo>,
+    /// 撤销日志已经释放到的主链高度
+    undo_released: u64,
+    /// 交易池，链重组时被丢弃分叉上的交易会放回这里
+    mempool: TxPool,
+    /// 父区块尚未到达的区块，父区块接入后自动导入
+    orphans: OrphanPool,
+    /// 已登记的无效区块，记录本身在存储中，超过容量时淘汰最久没有命中的记录
+    bad_blocks: BadBlockIndex,
+    /// 已校验签名的交易，交易池与区块校验共用
+    verified_txs: Arc<VerifiedTxCache>,
+    /// 链事件总线，head 切换、重组、交易上链和最终确认时发布事件
+    events: Arc<EventBus>,
+    /// 创世配置中的共识参数
+    consensus: ConsensusParams,
+    /// 按高度激活的协议升级，校验和执行区块时使用区块高度对应的规则
+    spec: ChainSpec,
+    /// head 状态下的总供应量：创世分配加上主链上已发行的区块奖励
+    total_supply: u128,
+    /// 时间戳等本地校验规则
+    config: ChainConfig,
+    clock: Arc<dyn Clock>,
 }
 
 impl<C: Codec> Blockchain<C> {
+    /// 以创世区块和创世状态创建一条新链，默认使用最重链规则
+    pub fn new(
+        genesis: Genesis,
+        storage: Box<dyn BlockStorage>,
+        codec: C,
+    ) -> Result<Self, ChainError> {
+        let genesis_hash = genesis
+            .block
+            .hash_with(&codec)
+            .map_err(ChainError::InvalidBlock)?;
+        let block = &genesis.block;
+        let mut batch = StorageBatch::new();
+        batch.put_block(genesis_hash, block.clone());
+        batch.set_total_difficulty(genesis_hash, block.header.difficulty as u128);
+        batch.set_canonical(block.header.number, genesis_hash);
+        batch.put_receipts(genesis_hash, Vec::new());
+        batch.set_head(genesis_hash);
+        storage.write_batch(batch)?;
+
+        Ok(Self::from_genesis(genesis_hash, genesis, storage, codec))
+    }
+
+    /// 打开已有的存储并恢复到上次的 head，存储为空时等同于 [`Blockchain::new`]
+    ///
+    /// 世界状态不落盘，恢复时从创世状态开始重新执行主链上的区块：
+    /// 1. 校验存储中的创世区块与给定的创世配置一致
+    /// 2. 从持久化的 head 指针沿父区块回溯到创世区块，逐个校验区块 hash 和高度；
+    ///    没有 head 指针的旧存储使用高度索引中最高的区块
+    /// 3. 依次执行这些区块，重建状态、回执、区块树和内存中的索引
+    /// 4. 与存储中的高度索引、交易索引比对，不一致的部分在一批写入中修复
+    pub fn open(
+        genesis: Genesis,
+        storage: Box<dyn BlockStorage>,
+        codec: C,
+    ) -> Result<Self, ChainError> {
+        let genesis_hash = genesis
+            .block
+            .hash_with(&codec)
+            .map_err(ChainError::InvalidBlock)?;
+        match storage.get_canonical_hash(0)? {
+            None => return Self::new(genesis, storage, codec),
+            Some(stored) if stored != genesis_hash => {
+                return Err(ChainError::InvalidGenesis(format!(
+                    "storage was created with genesis {:?}",
+                    stored
+                )));
+            }
+            Some(_) => {}
+        }
+
+        let mut chain = Self::from_genesis(genesis_hash, genesis, storage, codec);
+        chain.restore()?;
+        Ok(chain)
+    }
+
+    fn from_genesis(
+        genesis_hash: Hash256,
+        genesis: Genesis,
+        storage: Box<dyn BlockStorage>,
+        codec: C,
+    ) -> Self {
+        let Genesis {
+            block,
+            state,
+            consensus,
+            spec,
+        } = genesis;
+        // 创世状态在 GenesisSpec::build_state 中已经检查过不会溢出
+        let total_supply = state.total_balance().unwrap_or(u128::MAX);
+        let cache = BlockCache::default();
+        let tree = BlockTree::new(genesis_hash, &block.header);
+        cache.insert(genesis_hash, Arc::new(block));
+
+        Self {
+            head: genesis_hash,
+            height: 0,
+            cache,
+            canonical: vec![genesis_hash],
+            receipts: HashMap::from([(genesis_hash, Vec::new())]),
+            tx_index: HashMap::new(),
+            storage,
+            codec,
+            state,
+            tree,
+            fork_choice: Box::new(HeaviestChain),
+            undo_logs: HashMap::new(),
+            undo_released: 0,
+            mempool: TxPool::new(),
+            orphans: OrphanPool::default(),
+            bad_blocks: BadBlockIndex::default(),
+            verified_txs: Arc::new(VerifiedTxCache::default()),
+            events: Arc::new(EventBus::new()),
+            consensus,
+            spec,
+            total_supply,
+            config: ChainConfig::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// 从存储中的主链恢复，见 [`Blockchain::open`]
+    fn restore(&mut self) -> Result<(), ChainError> {
+        let stored_head = self.storage.get_head()?;
+        let tip = match stored_head {
+            Some(head) => head,
+            None => self.highest_canonical()?,
+        };
+
+        // 从 tip 回溯到创世区块，得到按高度升序的主链
+        let genesis_hash = self.head;
+        let mut branch: Vec<(Hash256, Block)> = Vec::new();
+        let mut current = tip;
+        while current != genesis_hash {
+            let block = self
+                .storage
+                .get_block(current)?
+                .ok_or(ChainError::Storage(StorageError::BlockNotFound))?;
+            let hash = block.hash_with(&self.codec).map_err(ChainError::InvalidBlock)?;
+            if hash != current || block.header.number == 0 {
+                return Err(ChainError::Storage(StorageError::CorruptedData));
+            }
+            if let Some((_, child)) = branch.last()
+                && child.header.number != block.header.number + 1
+            {
+                return Err(ChainError::Storage(StorageError::CorruptedData));
+            }
+            current = block.header.parent_hash;
+            branch.push((hash, block));
+        }
+        branch.reverse();
+
+        let mut batch = StorageBatch::new();
+        for (hash, block) in branch {
+            self.execute_block(hash, &block)?;
+            let total_difficulty = self.tree.insert(hash, &block.header)?.total_difficulty;
+            if self.storage.get_total_difficulty(hash)? != Some(total_difficulty) {
+                batch.set_total_difficulty(hash, total_difficulty);
+            }
+            let number = block.header.number;
+            if self.storage.get_canonical_hash(number)? != Some(hash) {
+                batch.set_canonical(number, hash);
+            }
+            if self.storage.get_receipts(hash)?.is_none() {
+                let receipts = self.receipts.get(&hash).cloned().unwrap_or_default();
+                batch.put_receipts(hash, receipts);
+            }
+            for (index, tx) in block.transactions.iter().enumerate() {
+                let tx_hash = tx.canonical_hash();
+                let location = TxLocation {
+                    block_hash: hash,
+                    index,
+                };
+                if self.storage.get_tx_location(tx_hash)? != Some(location) {
+                    batch.set_tx_location(tx_hash, location);
+                }
+                self.tx_index.insert(tx_hash, location);
+            }
+            self.cache.insert(hash, Arc::new(block));
+            self.canonical.push(hash);
+        }
+
+        // tip 以上残留的高度索引，以及这些区块中不在主链上的交易的索引
+        let mut number = self.canonical.len() as u64;
+        while let Some(stale) = self.storage.get_canonical_hash(number)? {
+            if let Some(block) = self.storage.get_block(stale)? {
+                for (index, tx) in block.transactions.iter().enumerate() {
+                    let tx_hash = tx.canonical_hash();
+                    let location = TxLocation {
+                        block_hash: stale,
+                        index,
+                    };
+                    if !self.tx_index.contains_key(&tx_hash)
+                        && self.storage.get_tx_location(tx_hash)? == Some(location)
+                    {
+                        batch.remove_tx_location(tx_hash);
+                    }
+                }
+            }
+            batch.remove_canonical(number);
+            number += 1;
+        }
+        if stored_head != Some(tip) {
+            batch.set_head(tip);
+        }
+        if !batch.is_empty() {
+            self.storage.write_batch(batch)?;
+        }
+
+        self.head = tip;
+        self.height = self.canonical.len() as u64 - 1;
+
+        if let Some(finalized) = self.storage.get_finalized()?
+            && self.is_canonical(finalized)
+        {
+            let number = self.block_number(finalized).ok_or(ChainError::BlockNotFound)?;
+            self.tree.set_finalized(finalized)?;
+            self.release_undo_logs(number);
+        }
+        self.release_undo_logs(self.height.saturating_sub(self.config.max_reorg_depth));
+
+        // 按发现时间恢复无效区块记录的淘汰顺序，超出容量的部分在下次登记时淘汰
+        let mut bad_blocks = self.storage.get_bad_blocks()?;
+        bad_blocks.sort_by_key(|bad_block| bad_block.detected_at);
+        for bad_block in bad_blocks {
+            self.bad_blocks.insert(bad_block.hash, usize::MAX);
+        }
+        Ok(())
+    }
+
+    /// 高度索引中连续的最高主链区块
+    fn highest_canonical(&self) -> Result<Hash256, ChainError> {
+        let mut number = 0;
+        let mut highest = self.head;
+        while let Some(hash) = self.storage.get_canonical_hash(number + 1)? {
+            highest = hash;
+            number += 1;
+        }
+        Ok(highest)
+    }
+
+    pub fn with_fork_choice(mut self, fork_choice: Box<dyn ForkChoice>) -> Self {
+        self.fork_choice = fork_choice;
+        self
+    }
+
+    pub fn with_orphan_pool(mut self, orphans: OrphanPool) -> Self {
+        self.orphans = orphans;
+        self
+    }
+
+    /// 替换默认容量的区块缓存
+    pub fn with_block_cache(mut self, cache: BlockCache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    pub fn with_config(mut self, config: ChainConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn verified_txs(&self) -> &VerifiedTxCache {
+        &self.verified_txs
+    }
+
+    /// 共享的签名校验缓存，可以交给网络层等其他模块在收到交易时使用
+    pub fn verified_txs_handle(&self) -> Arc<VerifiedTxCache> {
+        self.verified_txs.clone()
+    }
+
+    /// 订阅链事件，队列满时丢弃新事件而不阻塞链的写入，落后的订阅者通过
+    /// [`Subscription::missed`] 发现丢失的事件后重新同步
+    pub fn subscribe(&self, capacity: usize) -> Subscription {
+        self.events.subscribe_with(capacity, Overflow::Drop)
+    }
+
+    /// 共享的事件总线，可以按需选择队列满时的处理策略（例如需要背压的 [`Overflow::Block`]）
+    pub fn events_handle(&self) -> Arc<EventBus> {
+        self.events.clone()
+    }
+
+    pub fn codec(&self) -> &C {
+        &self.codec
+    }
+
+    pub fn consensus(&self) -> &ConsensusParams {
+        &self.consensus
+    }
+
+    /// head 状态下的总供应量
+    pub fn total_supply(&self) -> u128 {
+        self.total_supply
+    }
+
+    pub fn spec(&self) -> &ChainSpec {
+        &self.spec
+    }
+
+    pub fn config(&self) -> &ChainConfig {
+        &self.config
+    }
+
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
     pub fn height(&self) -> u64 {
         self.height
     }
@@ -29,40 +401,1749 @@ impl<C: Codec> Blockchain<C> {
         self.head
     }
 
+    pub fn state(&self) -> &WorldState {
+        &self.state
+    }
+
+    pub fn tree(&self) -> &BlockTree {
+        &self.tree
+    }
+
+    pub fn mempool(&self) -> &TxPool {
+        &self.mempool
+    }
+
+    pub fn mempool_mut(&mut self) -> &mut TxPool {
+        &mut self.mempool
+    }
+
+    /// 校验签名后把交易加入交易池，校验结果记入缓存，打包进区块后不再重复校验
+    ///
+    /// 按 head 状态拒绝 nonce 已经用过、或余额付不起 value 和最大手续费的交易
+    pub fn submit_transaction(&mut self, tx: Transaction) -> Result<Hash256, ChainError> {
+        if tx.chain_id != self.spec.chain_id {
+            return Err(ChainError::InvalidTransaction(format!(
+                "chain id {} does not match {}",
+                tx.chain_id, self.spec.chain_id
+            )));
+        }
+        let size = tx.canonical_bytes().len();
+        if size > MAX_TX_SIZE {
+            return Err(ChainError::InvalidTransaction(format!(
+                "size {} exceeds {}",
+                size, MAX_TX_SIZE
+            )));
+        }
+        self.check_affordable(&tx)?;
+        signature::verify_transaction(&tx, &self.verified_txs)?;
+        self.mempool.insert(tx)
+    }
+
+    fn check_affordable(&self, tx: &Transaction) -> Result<(), ChainError> {
+        let account_nonce = self.state.get_account(&tx.from).map_or(0, |a| a.nonce);
+        if tx.nonce < account_nonce {
+            return Err(ChainError::InvalidTransaction(format!(
+                "nonce {} is below account nonce {}",
+                tx.nonce, account_nonce
+            )));
+        }
+        let balance = |address| self.state.get_account(address).map_or(0, |a| a.balance);
+        let (sender_cost, payer_cost) = if tx.is_sponsored() {
+            (tx.value as u128, tx.max_fee())
+        } else {
+            (tx.value as u128 + tx.max_fee(), 0)
+        };
+        if balance(&tx.from) < sender_cost || balance(&tx.payer()) < payer_cost {
+            return Err(ChainError::InvalidTransaction(
+                "insufficient balance for value and max fee".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn orphans(&self) -> &OrphanPool {
+        &self.orphans
+    }
+
+    /// 接入一个区块
+    ///
+    /// 父区块未知时区块进入孤块池，返回 [`ChainError::UnknownParent`]；
+    /// 区块接入成功后，等待它的孤块会被依次导入
     pub fn append_block(&mut self, block: Block) -> Result<Hash256, ChainError> {
-        // 1. 创建验证器实例
-        let validator = BlockValidator {};
-        
-        // 2. 获取父区块哈希
-        let parent_hash = block.header.parent_hash;
-        
-        // 3. 验证区块
-        validator.verify_block(&block, parent_hash, self)?;
-        
-        // 4. 计算区块哈希
+        // 1. 计算区块哈希，已知区块直接返回
         let block_hash = block
             .hash_with(&self.codec)
             .map_err(ChainError::InvalidBlock)?;
-        
-        // 5. 存储区块到内存缓存
-        self.blocks.insert(block_hash, block.clone());
-        
-        // 6. 存储区块到持久化存储
-        self.storage.put_block(&block)?;
-        
-        // 7. 更新区块链头部和高度
-        self.head = block_hash;
-        self.height = block.header.number;
-        
-        // 8. TODO: 广播新区块到P2P网络
-        // 这里需要调用P2P模块的广播功能，通知其他节点有新区块
-        
+        if self.tree.contains(&block_hash) {
+            return Ok(block_hash);
+        }
+        if self.bad_blocks.touch(&block_hash) {
+            return Err(ChainError::KnownBadBlock(block_hash));
+        }
+        // 不满足自身声明难度的区块不需要任何代价就能生成，无状态地拒绝：不进孤块池，也不登记
+        let difficulty = block.header.difficulty;
+        if self.consensus.engine == ConsensusEngine::ProofOfWork
+            && !difficulty::meets_target(&block_hash, difficulty)
+        {
+            return Err(ChainError::InvalidProofOfWork { difficulty });
+        }
+
+        // 2. 无效区块的后代同样无效，直接拒绝但不登记，避免伪造的后代挤掉记录；
+        // 父区块还没到达，先放进孤块池
+        let parent_hash = block.header.parent_hash;
+        if self.bad_blocks.touch(&parent_hash) {
+            return Err(ChainError::KnownBadBlock(parent_hash));
+        }
+        if !self.tree.contains(&parent_hash) {
+            self.orphans.insert(block_hash, block);
+            return Err(ChainError::UnknownParent(parent_hash));
+        }
+
+        self.connect_block(block_hash, block)?;
+
+        // 3. 导入等待该区块的孤块
+        self.connect_orphans(block_hash);
+
+        // 4. head 变化已经通过事件总线发布，P2P 模块订阅 NewHead 事件广播新区块
         Ok(block_hash)
     }
 
-    pub fn get_block(&self, hash256: Hash256) -> Option<&Block> {
-        self.blocks.get(&hash256)
+    /// 在 head 之上用交易池中的交易组装新区块
+    ///
+    /// 协议规则、区块奖励和难度取自链的配置，gas 上限不超过协议规定的上限；
+    /// 没有指定时间戳时取本地时钟，且晚于祖先时间戳的中位数。
+    /// 工作量证明链上返回的区块还需要用 [`difficulty::seal`] 找到满足难度的 nonce
+    pub fn assemble_block(&self, assembler: &BlockAssembler) -> Result<AssembledBlock, ChainError> {
+        let parent = self.get_header(self.head)?.ok_or(ChainError::BlockNotFound)?;
+        let number = parent.number + 1;
+        let rules = self.spec.rules_at(number);
+
+        let mut assembler = assembler
+            .clone()
+            .block_reward(self.consensus.issuance.reward_at(number));
+        if self.consensus.engine == ConsensusEngine::ProofOfWork {
+            assembler = assembler.difficulty(self.get_next_difficulty()?);
+        }
+        if let Some(max_block_gas) = rules.max_block_gas {
+            assembler.gas_limit = assembler.gas_limit.min(max_block_gas);
+        }
+        if assembler.timestamp.is_none() {
+            let median = self.median_time_past(self.head)?.unwrap_or(parent.timestamp);
+            assembler.timestamp = Some(self.clock.now().max(median + 1));
+        }
+
+        let vm = rules.vm();
+        let executor = rules.executor(&vm);
+        assembler.assemble(&parent, &self.state, &executor, &self.mempool, &self.codec)
+    }
+
+    /// 把主链上高度在 range 内的区块流式写入导出文件，返回导出的区块数
+    ///
+    /// 超出当前高度的部分被截掉
+    pub fn export_blocks<W: Write>(&self, range: Range<u64>, writer: W) -> Result<u64, ChainError> {
+        let end = range.end.min(self.height + 1);
+        let start = range.start.min(end);
+        let header = ArchiveHeader {
+            genesis_hash: self.canonical[0],
+            start,
+            count: end - start,
+        };
+        let mut archive = ArchiveWriter::new(writer, header)?;
+        for number in start..end {
+            let hash = self.canonical_hash(number).ok_or(ChainError::BlockNotFound)?;
+            let block = self.load_block(&hash)?;
+            archive.write_block(&block, &self.codec)?;
+        }
+        archive.finish()?;
+        Ok(header.count)
+    }
+
+    /// 导入导出文件中的区块，返回处理的区块数
+    ///
+    /// 每个区块都经过 [`Blockchain::append_block`] 完整地校验和执行，已在链上的区块直接跳过；
+    /// 每处理一个区块调用一次 progress。遇到无效区块时停止，之前导入的区块保留在链上
+    pub fn import_blocks<R: Read>(
+        &mut self,
+        reader: R,
+        mut progress: impl FnMut(&ImportProgress),
+    ) -> Result<u64, ChainError> {
+        let mut archive = ArchiveReader::new(reader)?;
+        let header = *archive.header();
+        if header.genesis_hash != self.canonical[0] {
+            return Err(ChainError::InvalidGenesis(format!(
+                "archive belongs to chain {:?}",
+                header.genesis_hash
+            )));
+        }
+
+        let mut imported = 0;
+        while let Some(block) = archive.read_block(&self.codec)? {
+            // 导出文件中的区块是连续的，父区块未知说明文件和本地链接不上，不放进孤块池
+            let parent_hash = block.header.parent_hash;
+            if block.header.number > 0 && !self.tree.contains(&parent_hash) {
+                return Err(ChainError::UnknownParent(parent_hash));
+            }
+            let number = block.header.number;
+            let hash = self.append_block(block)?;
+            imported += 1;
+            progress(&ImportProgress {
+                hash,
+                number,
+                imported,
+                total: header.count,
+            });
+        }
+        Ok(imported)
+    }
+
+    /// 从 start 开始重新执行主链上之后的每个区块，报告第一个与存储不一致的区块
+    ///
+    /// 区块直接从存储读取，不经过缓存；start 通常取创世状态，也可以是可信的状态快照。
+    /// 全部一致时，重放到 head 的状态还要与链当前的状态一致
+    pub fn verify_chain(&self, start: StateSnapshot) -> Result<VerificationReport, ChainError> {
+        let StateSnapshot { number, mut state } = start;
+        let mut report = VerificationReport {
+            start: number,
+            verified: 0,
+            state_root: state.state_root(),
+            divergence: None,
+        };
+        let Some(&start_hash) = self.canonical.get(number as usize) else {
+            return Err(ChainError::BlockNotFound);
+        };
+        let diverge = |number: u64, hash: Hash256, kind: DivergenceKind| {
+            Some(Divergence { number, hash, kind })
+        };
+
+        // 快照必须与对应区块头中的状态根一致
+        let start_header = self.load_block(&start_hash)?.header.clone();
+        if report.state_root != start_header.state_root {
+            report.divergence = diverge(
+                number,
+                start_hash,
+                DivergenceKind::Block(ChainError::StateRootMismatch {
+                    expected: start_header.state_root,
+                    actual: report.state_root,
+                }),
+            );
+            return Ok(report);
+        }
+
+        let mut parent_hash = start_hash;
+        for number in number + 1..=self.height {
+            let hash = self.canonical[number as usize];
+            let block = match self.load_stored_block(number, hash, parent_hash)? {
+                Ok(block) => block,
+                Err(kind) => {
+                    report.divergence = diverge(number, hash, kind);
+                    return Ok(report);
+                }
+            };
+
+            let rules = self.spec.rules_at(number);
+            let vm = rules.vm();
+            let executor = rules.executor(&vm);
+            let reward = self.consensus.issuance.reward_at(number);
+            let result = BlockExecutor::new(&mut state, &executor)
+                .with_block_reward(reward)
+                .apply_block(&block);
+            let outcome = match result {
+                Ok(outcome) => outcome,
+                Err(e) => {
+                    report.divergence = diverge(number, hash, DivergenceKind::Block(e));
+                    return Ok(report);
+                }
+            };
+            let stored_root = self
+                .storage
+                .get_receipts(hash)?
+                .map(|receipts| merkle::receipt_root_hash(&receipts));
+            if stored_root != Some(outcome.receipt_root) {
+                report.divergence = diverge(number, hash, DivergenceKind::Receipts);
+                return Ok(report);
+            }
+
+            report.verified += 1;
+            report.state_root = outcome.state_root;
+            parent_hash = hash;
+        }
+
+        let head_root = self.state.state_root();
+        if report.state_root != head_root {
+            report.divergence = diverge(
+                self.height,
+                self.head,
+                DivergenceKind::HeadState {
+                    expected: head_root,
+                    actual: report.state_root,
+                },
+            );
+        }
+        Ok(report)
+    }
+
+    /// 重放前从存储读出区块，并检查主链索引、区块 hash 和父 hash
+    fn load_stored_block(
+        &self,
+        number: u64,
+        hash: Hash256,
+        parent_hash: Hash256,
+    ) -> Result<Result<Block, DivergenceKind>, ChainError> {
+        let indexed = self.storage.get_canonical_hash(number)?;
+        if indexed != Some(hash) {
+            return Ok(Err(DivergenceKind::CanonicalIndex {
+                expected: hash,
+                actual: indexed,
+            }));
+        }
+        let block = match self.storage.get_block(hash) {
+            Ok(Some(block)) => block,
+            Ok(None) => {
+                return Ok(Err(DivergenceKind::Unreadable(
+                    "block not found".to_string(),
+                )));
+            }
+            Err(e) => return Ok(Err(DivergenceKind::Unreadable(e.to_string()))),
+        };
+        let actual = block
+            .hash_with(&self.codec)
+            .map_err(ChainError::InvalidBlock)?;
+        if actual != hash {
+            return Ok(Err(DivergenceKind::BlockHash {
+                expected: hash,
+                actual,
+            }));
+        }
+        if block.header.parent_hash != parent_hash {
+            return Ok(Err(DivergenceKind::ParentHash {
+                expected: parent_hash,
+                actual: block.header.parent_hash,
+            }));
+        }
+        Ok(Ok(block))
+    }
+
+    /// 区块树上的区块，缓存未命中时从存储加载
+    ///
+    /// 不在区块树上的区块返回 None；读取存储失败，或区块树上有而存储中没有时返回错误
+    pub fn get_block(&self, hash256: Hash256) -> Result<Option<Arc<Block>>, ChainError> {
+        if !self.tree.contains(&hash256) {
+            return Ok(None);
+        }
+        self.load_block(&hash256).map(Some)
+    }
+
+    pub fn get_header(&self, hash: Hash256) -> Result<Option<BlockHeader>, ChainError> {
+        if !self.tree.contains(&hash) {
+            return Ok(None);
+        }
+        match self.cache.get_header(&hash) {
+            Some(header) => Ok(Some(header)),
+            None => Ok(Some(self.load_block(&hash)?.header.clone())),
+        }
+    }
+
+    /// 区块缓存的命中情况
+    pub fn cache_metrics(&self) -> CacheMetrics {
+        self.cache.metrics()
+    }
+
+    /// 从 hash 开始（含）最近 `median_time_span` 个祖先时间戳的中位数
+    ///
+    /// 中位数用于校验 hash 的子区块，区块数量取子区块高度的协议规则 [`crate::spec::ProtocolRules::median_time_span`]。
+    /// 祖先不足时使用全部祖先，区块未知时返回 None
+    pub fn median_time_past(&self, hash: Hash256) -> Result<Option<u64>, ChainError> {
+        let Some(number) = self.block_number(hash) else {
+            return Ok(None);
+        };
+        let span = self.spec.rules_at(number + 1).median_time_span.max(1);
+        let Some(mut timestamps) = self.ancestor_timestamps(hash, span)? else {
+            return Ok(None);
+        };
+        timestamps.sort_unstable();
+        Ok(Some(timestamps[timestamps.len() / 2]))
+    }
+
+    /// head 的下一个区块应有的难度
+    pub fn get_next_difficulty(&self) -> Result<u64, ChainError> {
+        Ok(self
+            .difficulty_for_child(self.head)?
+            .unwrap_or(self.consensus.initial_difficulty))
+    }
+
+    /// 以 parent 为父区块的区块应有的难度，根据最近区块的出块间隔调整，父区块未知时返回 None
+    pub fn difficulty_for_child(&self, parent: Hash256) -> Result<Option<u64>, ChainError> {
+        let Some(header) = self.get_header(parent)? else {
+            return Ok(None);
+        };
+        let Some(timestamps) = self.ancestor_timestamps(parent, DIFFICULTY_WINDOW + 1)? else {
+            return Ok(None);
+        };
+        Ok(Some(difficulty::next_difficulty(
+            header.difficulty,
+            &timestamps,
+            self.consensus.block_time,
+        )))
+    }
+
+    /// 从创世区块到该区块（含）的累计难度
+    pub fn total_difficulty(&self, hash: Hash256) -> Option<u128> {
+        self.tree.get(&hash).map(|node| node.total_difficulty)
+    }
+
+    /// 从 hash 开始（含）向前最多 count 个祖先的时间戳，按高度升序
+    fn ancestor_timestamps(
+        &self,
+        hash: Hash256,
+        count: usize,
+    ) -> Result<Option<Vec<u64>>, ChainError> {
+        let mut timestamps = Vec::with_capacity(count);
+        let Some(mut current) = self.get_header(hash)? else {
+            return Ok(None);
+        };
+        loop {
+            timestamps.push(current.timestamp);
+            if timestamps.len() >= count || current.number == 0 {
+                break;
+            }
+            match self.get_header(current.parent_hash)? {
+                Some(parent) => current = parent,
+                None => break,
+            }
+        }
+        timestamps.reverse();
+        Ok(Some(timestamps))
+    }
+
+    /// 主链上指定高度的区块 hash
+    pub fn canonical_hash(&self, number: u64) -> Option<Hash256> {
+        self.canonical.get(usize::try_from(number).ok()?).copied()
+    }
+
+    /// 区块所在的高度，侧链区块同样可以查到
+    pub fn block_number(&self, hash: Hash256) -> Option<u64> {
+        self.tree.get(&hash).map(|node| node.number)
+    }
+
+    /// 区块是否在主链上
+    pub fn is_canonical(&self, hash: Hash256) -> bool {
+        self.block_number(hash)
+            .and_then(|number| self.canonical_hash(number))
+            .is_some_and(|canonical| canonical == hash)
+    }
+
+    /// 主链上指定高度的区块
+    pub fn get_block_by_number(&self, number: u64) -> Result<Option<Arc<Block>>, ChainError> {
+        match self.canonical_hash(number) {
+            Some(hash) => self.get_block(hash),
+            None => Ok(None),
+        }
+    }
+
+    /// 按高度升序遍历主链上 range 范围内的区块，超出 head 的部分被忽略，读取失败时产生错误
+    pub fn blocks_in_range(
+        &self,
+        range: Range<u64>,
+    ) -> impl Iterator<Item = Result<Arc<Block>, ChainError>> + '_ {
+        let end = range.end.min(self.canonical.len() as u64);
+        (range.start..end).filter_map(move |number| self.get_block_by_number(number).transpose())
+    }
+
+    /// 已执行区块的回执，侧链上还没有执行过的区块返回 None
+    pub fn get_receipts(&self, block_hash: Hash256) -> Option<&[Receipt]> {
+        self.receipts.get(&block_hash).map(Vec::as_slice)
+    }
+
+    /// 按交易 hash 查询主链上的交易、回执和确认数，不在主链上的交易返回 None
+    pub fn get_transaction(&self, tx_hash: Hash256) -> Result<Option<TransactionLookup>, ChainError> {
+        let Some(location) = self.tx_index.get(&tx_hash) else {
+            return Ok(None);
+        };
+        let block = self.load_block(&location.block_hash)?;
+        let lookup = self
+            .receipts
+            .get(&location.block_hash)
+            .and_then(|receipts| receipts.get(location.index))
+            .zip(block.transactions.get(location.index))
+            .map(|(receipt, transaction)| TransactionLookup {
+                transaction: transaction.clone(),
+                receipt: receipt.clone(),
+                block_hash: location.block_hash,
+                block_number: block.header.number,
+                index: location.index,
+                confirmations: self.height - block.header.number + 1,
+            });
+        Ok(lookup)
+    }
+
+    /// 主链交易的默克尔包含证明，轻节点只需要区块头即可校验
+    pub fn get_transaction_proof(&self, tx_hash: Hash256) -> Result<Option<MerkleProof>, ChainError> {
+        let Some(location) = self.tx_index.get(&tx_hash) else {
+            return Ok(None);
+        };
+        let block = self.load_block(&location.block_hash)?;
+        Ok(merkle::tx_proof(&block.transactions, location.index))
+    }
+
+    /// 校验并把父区块已知的区块挂到区块树上，必要时执行区块或切换分叉
+    fn connect_block(&mut self, block_hash: Hash256, block: Block) -> Result<(), ChainError> {
+        // 1. 验证区块
+        // 2. 延伸主链的区块在校验时已经模拟执行，直接应用模拟的结果；
+        // 侧链区块只挂到区块树上，被分叉选择选中时再执行
+        let parent_hash = block.header.parent_hash;
+        let extends_head = parent_hash == self.head;
+        let result = BlockValidator {}
+            .verify_block(&block, parent_hash, self)
+            .and_then(|simulated| match simulated {
+                Some(simulated) => self.apply_simulated(block_hash, &block, simulated),
+                None => Ok(()),
+            });
+        if let Err(e) = result {
+            // 依赖区块体的失败不登记，同一个区块头配上正确的区块体仍然可以接入；
+            // 区块头已经说明了失败原因，不再执行区块
+            if e.is_header_fault() && self.carries_work(&block.header) {
+                self.record_bad_block(block_hash, &block.header, &e)?;
+            }
+            return Err(e);
+        }
+
+        // 3. 存储区块到区块树、内存缓存和持久化存储；
+        // 延伸主链时区块与 head 指针在同一批写入中保存，写入失败时撤销区块对内存的修改；
+        // 侧链区块写入成功后才挂到区块树上，内存与存储不会不一致
+        let parent_difficulty = self
+            .tree
+            .get(&parent_hash)
+            .ok_or(ChainError::InvalidParent)?
+            .total_difficulty;
+        let mut batch = StorageBatch::new();
+        batch.put_block(block_hash, block.clone());
+        batch.set_total_difficulty(block_hash, parent_difficulty + block.header.difficulty as u128);
+
+        // 4. 分叉选择，更新区块链头部和高度
+        if extends_head {
+            self.tree.insert(block_hash, &block.header)?;
+            self.cache.insert(block_hash, Arc::new(block));
+            if let Err(e) = self.set_head(block_hash, batch) {
+                if self.head != block_hash {
+                    self.disconnect_block(&block_hash)?;
+                }
+                return Err(e);
+            }
+            self.remove_included_txs(&[block_hash]);
+        } else {
+            self.storage.write_batch(batch)?;
+            self.tree.insert(block_hash, &block.header)?;
+            self.cache.insert(block_hash, Arc::new(block));
+            let candidate = self.tree.get(&block_hash).ok_or(ChainError::BlockNotFound)?;
+            let current = self.tree.get(&self.head).ok_or(ChainError::BlockNotFound)?;
+            if self.fork_choice.is_better(&self.tree, candidate, current) {
+                self.reorg(block_hash)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 依次导入以 parent 为祖先的孤块，无效孤块连同其后代一起丢弃
+    fn connect_orphans(&mut self, parent: Hash256) {
+        let mut pending = vec![parent];
+        while let Some(parent) = pending.pop() {
+            for (hash, block) in self.orphans.take_children(&parent) {
+                match self.connect_block(hash, block) {
+                    Ok(()) => pending.push(hash),
+                    Err(_) => {
+                        self.orphans.remove_descendants(&hash);
+                    }
+                }
+            }
+        }
+    }
+
+    /// 切换到另一个分叉
+    ///
+    /// 1. 找到当前 head 与 new_head 的共同祖先
+    /// 2. 按相反顺序撤销当前分叉上的区块，把状态回退到共同祖先
+    /// 3. 依次执行新分叉上的区块
+    /// 4. 被丢弃分叉上、没有被新分叉包含的交易放回交易池
+    ///
+    /// 新分叉上的区块执行失败时，恢复原来的主链，并把失败的区块及其后代从区块树中删除
+    fn reorg(&mut self, new_head: Hash256) -> Result<(), ChainError> {
+        let ancestor = self.tree.common_ancestor(&self.head, &new_head)?;
+        let old_branch = self.tree.path(&ancestor, &self.head)?;
+        let new_branch = self.tree.path(&ancestor, &new_head)?;
+        // 更深的主链区块已经没有撤销日志
+        let depth = old_branch.len() as u64;
+        if depth > self.config.max_reorg_depth {
+            return Err(ChainError::ReorgTooDeep {
+                depth,
+                max: self.config.max_reorg_depth,
+            });
+        }
+
+        for hash in old_branch.iter().rev() {
+            self.undo_block(hash)?;
+        }
+
+        for (index, hash) in new_branch.iter().enumerate() {
+            let block = self.load_block(hash)?;
+            if let Err(e) = self.execute_block(*hash, &block) {
+                // 执行失败依赖区块体，不登记为无效区块，只把它和后代从区块树中删除
+                self.restore_branch(&new_branch[..index], &old_branch)?;
+                for invalid in self.tree.remove_subtree(hash) {
+                    self.cache.remove(&invalid);
+                    self.receipts.remove(&invalid);
+                }
+                return Err(e);
+            }
+        }
+
+        // 写入失败时 head 没有切换，状态也回到原来的分叉
+        if let Err(e) = self.set_head(new_head, StorageBatch::new()) {
+            if self.head != new_head {
+                self.restore_branch(&new_branch, &old_branch)?;
+            }
+            return Err(e);
+        }
+        self.return_orphaned_txs(&old_branch, &new_branch);
+        Ok(())
     }
 
-    pub fn get_next_difficulty(&self) {}
+    /// 按相反顺序撤销新分叉上已执行的区块，再依次重新执行原来的分叉
+    fn restore_branch(&mut self, applied: &[Hash256], old_branch: &[Hash256]) -> Result<(), ChainError> {
+        for hash in applied.iter().rev() {
+            self.undo_block(hash)?;
+        }
+        for hash in old_branch {
+            let block = self.load_block(hash)?;
+            self.execute_block(*hash, &block)?;
+        }
+        Ok(())
+    }
+
+    /// 撤销已应用到 head 状态、但没有写入存储的区块，并把它从区块树和缓存中删除
+    fn disconnect_block(&mut self, hash: &Hash256) -> Result<(), ChainError> {
+        self.undo_block(hash)?;
+        self.tree.remove_subtree(hash);
+        self.cache.remove(hash);
+        self.receipts.remove(hash);
+        Ok(())
+    }
+
+    /// 工作量证明链上，只有按父区块要求的难度完成了工作量的区块才值得登记；
+    /// 自身 hash 满足声明难度已经在 [`Blockchain::append_block`] 中检查过
+    fn carries_work(&self, header: &BlockHeader) -> bool {
+        if self.consensus.engine != ConsensusEngine::ProofOfWork {
+            return true;
+        }
+        matches!(
+            self.difficulty_for_child(header.parent_hash),
+            Ok(Some(expected)) if expected == header.difficulty
+        )
+    }
+
+    /// 持久化无效区块并发布事件，之后再收到该区块或它的后代时直接拒绝；
+    /// 超过 [`ChainConfig::max_bad_blocks`] 时在同一批写入中删除最久没有命中的记录
+    fn record_bad_block(
+        &mut self,
+        hash: Hash256,
+        header: &BlockHeader,
+        reason: &ChainError,
+    ) -> Result<(), ChainError> {
+        let capacity = self.config.max_bad_blocks.max(1);
+        let bad_block = BadBlock::new(hash, header, reason, self.clock.now());
+        let mut batch = StorageBatch::new();
+        batch.put_bad_block(bad_block);
+        for evicted in self.bad_blocks.excess(capacity) {
+            batch.delete_bad_block(evicted);
+        }
+        self.storage.write_batch(batch)?;
+        self.bad_blocks.insert(hash, capacity);
+        self.events.publish(ChainEvent::BadBlock {
+            hash,
+            reason: reason.to_string(),
+        });
+        Ok(())
+    }
+
+    /// 校验失败的区块记录，包含区块头和失败原因
+    pub fn bad_block(&self, hash: Hash256) -> Result<Option<BadBlock>, ChainError> {
+        Ok(self.storage.get_bad_block(hash)?)
+    }
+
+    /// 在 head 状态上执行区块，记录撤销日志和回执
+    ///
+    /// 执行使用区块高度对应的协议规则。区块在接入时已经通过 [`BlockValidator`] 的签名校验，执行时不再重复校验。
+    /// 执行后检查总供应量不变式：所有余额之和必须正好增加本区块的奖励，手续费只是转移
+    fn execute_block(&mut self, hash: Hash256, block: &Block) -> Result<(), ChainError> {
+        let reward = self.consensus.issuance.reward_at(block.header.number);
+        let rules = self.spec.rules_at(block.header.number);
+        let vm = rules.vm();
+        let executor = rules.executor(&vm).without_signature_check();
+        let checkpoint = self.state.checkpoint();
+        let result = BlockExecutor::new(&mut self.state, &executor)
+            .with_block_reward(reward)
+            .apply_block(block);
+        match result {
+            Ok(outcome) => self.commit_block(hash, checkpoint, reward, outcome.receipts),
+            Err(e) => {
+                self.state.revert_to(checkpoint);
+                Err(e)
+            }
+        }
+    }
+
+    /// 把校验时模拟执行得到的修改应用到 head 状态上，效果与 [`Blockchain::execute_block`] 相同
+    fn apply_simulated(
+        &mut self,
+        hash: Hash256,
+        block: &Block,
+        simulated: SimulatedBlock,
+    ) -> Result<(), ChainError> {
+        let reward = self.consensus.issuance.reward_at(block.header.number);
+        let checkpoint = self.state.checkpoint();
+        self.state.apply_changes(simulated.changes);
+        self.state.commit_storage_roots();
+        self.commit_block(hash, checkpoint, reward, simulated.outcome.receipts)
+    }
+
+    /// 提交区块对状态的修改并保留撤销日志，总供应量不变式不成立时撤销修改
+    fn commit_block(
+        &mut self,
+        hash: Hash256,
+        checkpoint: Checkpoint,
+        reward: u128,
+        receipts: Vec<Receipt>,
+    ) -> Result<(), ChainError> {
+        let undo = self.state.commit_with_undo(checkpoint);
+        // 只看本区块修改过的账户：余额净增加必须恰好等于奖励
+        let change = self.state.balance_change(&undo);
+        let minted = change.and_then(|(increase, decrease)| increase.checked_sub(decrease));
+        let total_supply = match (minted, self.total_supply.checked_add(reward)) {
+            (Some(minted), Some(total_supply)) if minted == reward => total_supply,
+            _ => {
+                self.state.apply_undo(undo);
+                let (increase, decrease) = change.unwrap_or((u128::MAX, 0));
+                return Err(ChainError::SupplyMismatch {
+                    expected: self.total_supply.saturating_add(reward),
+                    actual: self
+                        .total_supply
+                        .saturating_add(increase)
+                        .saturating_sub(decrease),
+                });
+            }
+        };
+        self.total_supply = total_supply;
+        self.undo_logs.insert(hash, undo);
+        self.receipts.insert(hash, receipts);
+        Ok(())
+    }
+
+    /// 撤销主链末端区块对状态的修改，总供应量减去该区块的奖励
+    fn undo_block(&mut self, hash: &Hash256) -> Result<(), ChainError> {
+        let undo = self.undo_logs.remove(hash).ok_or(ChainError::BlockNotFound)?;
+        let number = self.tree.get(hash).ok_or(ChainError::BlockNotFound)?.number;
+        self.state.apply_undo(undo);
+        self.total_supply -= self.consensus.issuance.reward_at(number);
+        Ok(())
+    }
+
+    /// 切换 head，并在同一批写入中更新持久化的 head 指针、主链索引和交易索引
+    ///
+    /// batch 中已有的操作（例如保存新区块）与 head 的修改一起原子写入。
+    /// 从原 head 与新 head 的共同祖先开始改写高度索引，新 head 以上的旧索引被删除；
+    /// 离开主链的区块中的交易先从交易索引中删除，再写入新分叉上的交易。
+    /// 写入成功后依次发布 Reorg（如果有区块离开主链）、NewHead 和 TransactionIncluded 事件
+    fn set_head(&mut self, hash: Hash256, mut batch: StorageBatch) -> Result<(), ChainError> {
+        let ancestor = self.tree.common_ancestor(&self.head, &hash)?;
+        let branch = self.tree.path(&ancestor, &hash)?;
+        let ancestor_number = self.tree.get(&ancestor).ok_or(ChainError::BlockNotFound)?.number;
+        let height = ancestor_number + branch.len() as u64;
+
+        let mut removed_txs = Vec::new();
+        for old in &self.canonical[ancestor_number as usize + 1..] {
+            let block = self.load_block(old)?;
+            for tx in &block.transactions {
+                let tx_hash = tx.canonical_hash();
+                batch.remove_tx_location(tx_hash);
+                removed_txs.push(tx_hash);
+            }
+        }
+        let mut added_txs = Vec::new();
+        for (offset, block_hash) in branch.iter().enumerate() {
+            batch.set_canonical(ancestor_number + 1 + offset as u64, *block_hash);
+            if let Some(receipts) = self.receipts.get(block_hash) {
+                batch.put_receipts(*block_hash, receipts.clone());
+            }
+            let block = self.load_block(block_hash)?;
+            for (index, tx) in block.transactions.iter().enumerate() {
+                let location = TxLocation {
+                    block_hash: *block_hash,
+                    index,
+                };
+                let tx_hash = tx.canonical_hash();
+                batch.set_tx_location(tx_hash, location);
+                added_txs.push((tx_hash, location));
+            }
+        }
+        for number in height + 1..self.canonical.len() as u64 {
+            batch.remove_canonical(number);
+        }
+        batch.set_head(hash);
+        // 按 finality_depth 推进的最终确认与 head 在同一批写入中保存
+        let finality = match self.finality_target(ancestor_number, &branch, height) {
+            Some(target) => self.prepare_finality(target, &mut batch)?,
+            None => None,
+        };
+        self.storage.write_batch(batch)?;
+
+        let removed = self.canonical.split_off(ancestor_number as usize + 1);
+        let mut events = Vec::new();
+        if !removed.is_empty() {
+            events.push(ChainEvent::Reorg {
+                common_ancestor: ancestor,
+                removed,
+                added: branch.clone(),
+            });
+        }
+        events.push(ChainEvent::NewHead {
+            hash,
+            number: height,
+        });
+        events.extend(added_txs.iter().map(|(tx_hash, location)| {
+            ChainEvent::TransactionIncluded {
+                tx_hash: *tx_hash,
+                block_hash: location.block_hash,
+                index: location.index,
+            }
+        }));
+
+        for tx_hash in removed_txs {
+            self.tx_index.remove(&tx_hash);
+        }
+        self.tx_index.extend(added_txs);
+        self.canonical.extend(branch);
+        self.head = hash;
+        self.height = height;
+        self.release_undo_logs(height.saturating_sub(self.config.max_reorg_depth));
+        if let Some(finality) = finality {
+            events.push(self.apply_finality(finality)?);
+        }
+        self.events.publish_all(events);
+        Ok(())
+    }
+
+    /// 把主链上的区块标记为最终确认，由共识的最终确认机制或 [`ChainConfig::finality_depth`] 触发
+    ///
+    /// 最终确认只能沿主链向前推进。确认之后：
+    /// - 不再接受不包含它的分叉上的区块，链重组不会越过它
+    /// - 与它冲突的侧链从区块树、缓存和存储中删除
+    /// - 它及其祖先不会再被撤销，对应的撤销日志被释放
+    pub fn finalize(&mut self, hash: Hash256) -> Result<(), ChainError> {
+        if !self.is_canonical(hash) {
+            return Err(ChainError::BlockNotFound);
+        }
+        let mut batch = StorageBatch::new();
+        let Some(finality) = self.prepare_finality(hash, &mut batch)? else {
+            return Ok(());
+        };
+        self.storage.write_batch(batch)?;
+        let event = self.apply_finality(finality)?;
+        self.events.publish(event);
+        Ok(())
+    }
+
+    /// 把最终确认 hash 需要的写入加入 batch，不修改内存；hash 已经被确认时返回 None
+    fn prepare_finality(
+        &self,
+        hash: Hash256,
+        batch: &mut StorageBatch,
+    ) -> Result<Option<Finality>, ChainError> {
+        if let Some(current) = self.tree.finalized() {
+            if current == hash {
+                return Ok(None);
+            }
+            if !self.tree.is_ancestor(&current, &hash) {
+                return Err(ChainError::ConflictsWithFinalized(current));
+            }
+        }
+        let number = self.tree.get(&hash).ok_or(ChainError::BlockNotFound)?.number;
+        let pruned = self.tree.conflicting(&hash);
+        batch.set_finalized(hash);
+        for block_hash in &pruned {
+            batch.delete_block(*block_hash);
+        }
+        Ok(Some(Finality {
+            hash,
+            number,
+            pruned,
+        }))
+    }
+
+    /// 写入成功后在内存中完成最终确认，返回要发布的事件
+    fn apply_finality(&mut self, finality: Finality) -> Result<ChainEvent, ChainError> {
+        let Finality {
+            hash,
+            number,
+            pruned,
+        } = finality;
+        for block_hash in &pruned {
+            self.tree.remove_subtree(block_hash);
+            self.cache.remove(block_hash);
+            self.receipts.remove(block_hash);
+            self.orphans.remove_descendants(block_hash);
+        }
+        self.tree.set_finalized(hash)?;
+        self.release_undo_logs(number);
+        Ok(ChainEvent::BlockFinalized { hash, number })
+    }
+
+    /// 最终确认的区块，还没有区块被确认时返回 None
+    pub fn finalized(&self) -> Option<Hash256> {
+        self.tree.finalized()
+    }
+
+    /// 最终确认区块的高度，创世区块总是被视为已确认
+    pub fn finalized_number(&self) -> u64 {
+        self.finalized()
+            .and_then(|hash| self.block_number(hash))
+            .unwrap_or(0)
+    }
+
+    /// head 切换到 branch 末端（高度 height）后按 [`ChainConfig::finality_depth`] 应当确认的区块
+    ///
+    /// 此时主链索引还没有更新，高于 ancestor_number 的区块从 branch 中取
+    fn finality_target(&self, ancestor_number: u64, branch: &[Hash256], height: u64) -> Option<Hash256> {
+        let number = height.checked_sub(self.config.finality_depth?)?;
+        if number <= self.finalized_number() {
+            return None;
+        }
+        if number <= ancestor_number {
+            self.canonical_hash(number)
+        } else {
+            branch.get((number - ancestor_number - 1) as usize).copied()
+        }
+    }
+
+    /// 释放高度不超过 number 的主链区块的撤销日志
+    ///
+    /// 已释放部分的主链不会再被重组，只需要从上次释放到的高度继续
+    fn release_undo_logs(&mut self, number: u64) {
+        let end = (number as usize + 1).min(self.canonical.len());
+        let start = (self.undo_released as usize + 1).min(end);
+        for hash in &self.canonical[start..end] {
+            self.undo_logs.remove(hash);
+        }
+        self.undo_released = self.undo_released.max(end as u64 - 1);
+    }
+
+    /// 从缓存或存储加载区块树上的区块，存储中读到的区块回填缓存
+    fn load_block(&self, hash: &Hash256) -> Result<Arc<Block>, ChainError> {
+        if !self.tree.contains(hash) {
+            return Err(ChainError::BlockNotFound);
+        }
+        if let Some(block) = self.cache.get_block(hash) {
+            return Ok(block);
+        }
+        let block = Arc::new(
+            self.storage
+                .get_block(*hash)?
+                .ok_or(ChainError::BlockNotFound)?,
+        );
+        self.cache.insert(*hash, block.clone());
+        Ok(block)
+    }
+
+    /// 已上链的交易从交易池中移除
+    ///
+    /// 交易池的维护只是尽力而为，读不到的区块跳过，不影响已经写入的 head
+    fn remove_included_txs(&mut self, block_hashes: &[Hash256]) {
+        for hash in block_hashes {
+            if let Ok(block) = self.load_block(hash) {
+                for tx in &block.transactions {
+                    self.mempool.remove(&tx.canonical_hash());
+                }
+            }
+        }
+    }
+
+    /// 被丢弃分叉上的交易放回交易池，新分叉已包含的交易从交易池移除
+    fn return_orphaned_txs(&mut self, removed: &[Hash256], added: &[Hash256]) {
+        let mut included = HashSet::new();
+        for hash in added {
+            if let Ok(block) = self.load_block(hash) {
+                included.extend(block.transactions.iter().map(|tx| tx.canonical_hash()));
+            }
+        }
+
+        for hash in removed {
+            if let Ok(block) = self.load_block(hash) {
+                for tx in &block.transactions {
+                    if !included.contains(&tx.canonical_hash()) {
+                        // 交易池满时放不回的交易直接丢弃
+                        let _ = self.mempool.insert(tx.clone());
+                    }
+                }
+            }
+        }
+        self.remove_included_txs(added);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{CHAIN_ID, Fixture, INITIAL_BALANCE, SharedStorage, TestCodec};
+    use latte_primitives::address::Address;
+    use latte_types::builder::TransactionBuilder;
+
+    #[test]
+    fn test_reorg_to_heavier_branch() {
+        let fixture = Fixture::new();
+        let mut chain = fixture.chain();
+        let a1 = fixture.child(&[], vec![fixture.transfer(0, 1)], 1, 0);
+        let a2 = fixture.child(&[&a1], vec![fixture.transfer(1, 1)], 1, 0);
+        chain.append_block(a1.clone()).unwrap();
+        let a2_hash = chain.append_block(a2.clone()).unwrap();
+        assert_eq!((chain.head(), chain.height()), (a2_hash, 2));
+        assert_eq!(chain.state().get_account(&Address([1; 20])).unwrap().balance, 10);
+
+        // 侧链追上之前不切换
+        let b1 = fixture.child(&[], vec![fixture.transfer(0, 2)], 1, 5);
+        let b2 = fixture.child(&[&b1], vec![], 1, 0);
+        let b3 = fixture.child(&[&b1, &b2], vec![fixture.transfer(1, 2)], 1, 0);
+        chain.append_block(b1).unwrap();
+        chain.append_block(b2).unwrap();
+        assert_eq!(chain.head(), a2_hash);
+        let b3_hash = chain.append_block(b3.clone()).unwrap();
+        assert_eq!((chain.head(), chain.height()), (b3_hash, 3));
+        assert_eq!(chain.state().state_root(), b3.header.state_root);
+        assert!(chain.state().get_account(&Address([1; 20])).is_none());
+        // 原主链上的两笔交易回到交易池
+        assert_eq!(chain.mempool().len(), 2);
+
+        // 再切回原来的分叉
+        let a3 = fixture.child(&[&a1, &a2], vec![], 5, 0);
+        let a3_hash = chain.append_block(a3.clone()).unwrap();
+        assert_eq!(chain.head(), a3_hash);
+        assert_eq!(chain.state().state_root(), a3.header.state_root);
+        assert_eq!(chain.mempool().len(), 2);
+    }
+
+    #[test]
+    fn test_failed_reorg_restores_old_branch() {
+        let fixture = Fixture::new();
+        let mut chain = fixture.chain();
+        let a1 = fixture.child(&[], vec![fixture.transfer(0, 1)], 1, 0);
+        let a2 = fixture.child(&[&a1], vec![], 1, 0);
+        chain.append_block(a1).unwrap();
+        let a2_hash = chain.append_block(a2.clone()).unwrap();
+
+        // b2 的状态根是错的，侧链区块接入时不执行，切换分叉时才发现
+        let b1 = fixture.child(&[], vec![fixture.transfer(0, 2)], 1, 5);
+        let b2_valid = fixture.child(&[&b1], vec![], 1, 0);
+        let mut b2 = b2_valid.clone();
+        b2.header.state_root = Hash256([7; 32]);
+        let mut b3 = fixture.child(&[&b1, &b2_valid], vec![], 5, 0);
+        b3.header.parent_hash = b2.hash_with(&chain.codec).unwrap();
+        let b1_hash = chain.append_block(b1).unwrap();
+        let b2_hash = chain.append_block(b2).unwrap();
+        assert_eq!(chain.head(), a2_hash);
+
+        let b3_hash = b3.hash_with(&chain.codec).unwrap();
+        assert!(matches!(
+            chain.append_block(b3),
+            Err(ChainError::StateRootMismatch { .. })
+        ));
+        assert_eq!((chain.head(), chain.height()), (a2_hash, 2));
+        assert_eq!(chain.state().state_root(), a2.header.state_root);
+        assert_eq!(chain.canonical_hash(2), Some(a2_hash));
+        // 失败的区块及其后代从区块树中删除，之前的侧链区块保留
+        assert!(chain.tree().contains(&b1_hash));
+        assert!(!chain.tree().contains(&b2_hash));
+        assert!(!chain.tree().contains(&b3_hash));
+        assert!(chain.mempool().is_empty());
+    }
+
+    #[test]
+    fn test_bogus_state_transition_rejected_before_execution() {
+        let fixture = Fixture::new();
+        let mut chain = fixture.chain();
+        let mut a1 = fixture.child(&[], vec![fixture.transfer(0, 1)], 1, 0);
+        a1.header.state_root = Hash256([3; 32]);
+        let root = chain.state().state_root();
+        assert!(matches!(
+            chain.append_block(a1),
+            Err(ChainError::StateRootMismatch { .. })
+        ));
+        assert_eq!(chain.state().state_root(), root);
+        assert_eq!((chain.height(), chain.tree().len()), (0, 1));
+        assert!(chain.undo_logs.is_empty());
+
+        // 通过校验的区块直接应用模拟结果，与重新执行得到的状态一致
+        let a1 = fixture.child(&[], vec![fixture.transfer(0, 1)], 1, 0);
+        chain.append_block(a1.clone()).unwrap();
+        assert_eq!(chain.state().state_root(), a1.header.state_root);
+        assert_eq!(chain.state().state_root(), fixture.state_after(&[&a1]).state_root());
+        assert_eq!(chain.get_receipts(a1.hash_with(&chain.codec).unwrap()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_canonical_index_follows_reorg() {
+        let fixture = Fixture::new();
+        let mut chain = fixture.chain();
+        let a1 = fixture.child(&[], vec![], 1, 0);
+        let a2 = fixture.child(&[&a1], vec![], 1, 0);
+        let a3 = fixture.child(&[&a1, &a2], vec![], 1, 0);
+        let a1_hash = chain.append_block(a1.clone()).unwrap();
+        let a2_hash = chain.append_block(a2.clone()).unwrap();
+        let a3_hash = chain.append_block(a3).unwrap();
+        assert_eq!(
+            chain.get_block_by_number(2).unwrap().unwrap().header.timestamp,
+            a2.header.timestamp
+        );
+        assert_eq!(chain.blocks_in_range(1..10).count(), 3);
+
+        // 更重但更短的分叉成为主链，原来高度 3 的索引被删除
+        let b2 = fixture.child(&[&a1], vec![], 10, 7);
+        let b2_hash = chain.append_block(b2.clone()).unwrap();
+        assert_eq!(chain.head(), b2_hash);
+        assert_eq!(chain.canonical_hash(1), Some(a1_hash));
+        assert_eq!(chain.canonical_hash(2), Some(b2_hash));
+        assert_eq!(chain.canonical_hash(3), None);
+        assert!(!chain.is_canonical(a2_hash));
+        assert!(!chain.is_canonical(a3_hash));
+        assert_eq!(chain.block_number(a3_hash), Some(3));
+        assert_eq!(
+            chain.get_block_by_number(2).unwrap().unwrap().header.timestamp,
+            b2.header.timestamp
+        );
+        assert!(chain.get_block_by_number(3).unwrap().is_none());
+        let numbers: Vec<u64> = chain
+            .blocks_in_range(0..10)
+            .map(|block| block.unwrap().header.number)
+            .collect();
+        assert_eq!(numbers, vec![0, 1, 2]);
+
+        // 持久化的高度索引与内存中一致
+        assert_eq!(chain.storage.get_canonical_hash(2).unwrap(), Some(b2_hash));
+        assert_eq!(chain.storage.get_canonical_hash(3).unwrap(), None);
+    }
+
+    #[test]
+    fn test_tx_index_follows_reorg() {
+        let fixture = Fixture::new();
+        let mut chain = fixture.chain();
+        let t0 = fixture.transfer(0, 1);
+        let t1 = fixture.transfer(1, 1);
+        let (t0_hash, t1_hash) = (t0.canonical_hash(), t1.canonical_hash());
+        let a1 = fixture.child(&[], vec![t0], 1, 0);
+        let a2 = fixture.child(&[&a1], vec![t1.clone()], 1, 0);
+        let a1_hash = chain.append_block(a1.clone()).unwrap();
+        let a2_hash = chain.append_block(a2).unwrap();
+
+        let lookup = chain.get_transaction(t0_hash).unwrap().unwrap();
+        assert_eq!((lookup.block_hash, lookup.block_number), (a1_hash, 1));
+        assert_eq!(lookup.confirmations, 2);
+        assert!(lookup.receipt.is_success());
+        assert_eq!(chain.get_transaction(t1_hash).unwrap().unwrap().confirmations, 1);
+
+        // 不包含 t1 的更重分叉成为主链：t1 离开交易索引并回到交易池，t0 的确认数不变
+        let b2 = fixture.child(&[&a1], vec![], 10, 7);
+        chain.append_block(b2.clone()).unwrap();
+        assert!(chain.get_transaction(t1_hash).unwrap().is_none());
+        assert_eq!(chain.storage.get_tx_location(t1_hash).unwrap(), None);
+        assert_eq!(chain.get_transaction(t0_hash).unwrap().unwrap().confirmations, 2);
+        assert!(chain.mempool().contains(&t1_hash));
+
+        // t1 在新分叉上重新打包，索引指向新的区块
+        let b3 = fixture.child(&[&a1, &b2], vec![t1], 1, 0);
+        let b3_hash = chain.append_block(b3).unwrap();
+        let location = TxLocation {
+            block_hash: b3_hash,
+            index: 0,
+        };
+        assert_eq!(chain.storage.get_tx_location(t1_hash).unwrap(), Some(location));
+        let lookup = chain.get_transaction(t1_hash).unwrap().unwrap();
+        assert_ne!(lookup.block_hash, a2_hash);
+        assert_eq!((lookup.block_number, lookup.confirmations), (3, 1));
+        assert!(!chain.mempool().contains(&t1_hash));
+    }
+
+    #[test]
+    fn test_undo_logs_bounded_by_reorg_depth() {
+        let fixture = Fixture::new();
+        let config = ChainConfig {
+            max_reorg_depth: 1,
+            ..ChainConfig::default()
+        };
+        let mut chain = fixture.chain().with_config(config);
+        let a1 = fixture.child(&[], vec![], 1, 0);
+        let a2 = fixture.child(&[&a1], vec![], 1, 0);
+        let a3 = fixture.child(&[&a1, &a2], vec![], 1, 0);
+        chain.append_block(a1.clone()).unwrap();
+        chain.append_block(a2.clone()).unwrap();
+        let a3_hash = chain.append_block(a3).unwrap();
+        assert_eq!(chain.undo_logs.len(), 1);
+
+        // 深度为 1 的重组仍然可以进行
+        let b3 = fixture.child(&[&a1, &a2], vec![], 5, 3);
+        let b3_hash = chain.append_block(b3).unwrap();
+        assert_eq!(chain.head(), b3_hash);
+
+        // 更深的重组被拒绝，head 不变
+        let c2 = fixture.child(&[&a1], vec![], 20, 3);
+        assert!(matches!(
+            chain.append_block(c2),
+            Err(ChainError::ReorgTooDeep { depth: 2, max: 1 })
+        ));
+        assert_eq!(chain.head(), b3_hash);
+        assert_ne!(chain.head(), a3_hash);
+        assert_eq!(chain.undo_logs.len(), 1);
+    }
+
+    #[test]
+    fn test_events_follow_head_and_reorg() {
+        let fixture = Fixture::new();
+        let mut chain = fixture.chain();
+        let subscription = chain.subscribe(64);
+
+        let tx = fixture.transfer(0, 1);
+        let a1 = fixture.child(&[], vec![tx.clone()], 1, 0);
+        let a1_hash = chain.append_block(a1).unwrap();
+        assert_eq!(
+            subscription.drain(),
+            vec![
+                ChainEvent::NewHead {
+                    hash: a1_hash,
+                    number: 1
+                },
+                ChainEvent::TransactionIncluded {
+                    tx_hash: tx.canonical_hash(),
+                    block_hash: a1_hash,
+                    index: 0
+                },
+            ]
+        );
+
+        // 侧链区块不改变 head，没有事件
+        let b1 = fixture.child(&[], vec![], 1, 5);
+        let b1_hash = chain.append_block(b1.clone()).unwrap();
+        assert!(subscription.drain().is_empty());
+
+        let b2 = fixture.child(&[&b1], vec![], 1, 0);
+        let b2_hash = chain.append_block(b2).unwrap();
+        assert_eq!(
+            subscription.drain(),
+            vec![
+                ChainEvent::Reorg {
+                    common_ancestor: fixture.genesis.hash_with(chain.codec()).unwrap(),
+                    removed: vec![a1_hash],
+                    added: vec![b1_hash, b2_hash],
+                },
+                ChainEvent::NewHead {
+                    hash: b2_hash,
+                    number: 2
+                },
+            ]
+        );
+        assert_eq!(subscription.missed(), 0);
+    }
+
+    #[test]
+    fn test_evicted_blocks_reload_from_storage() {
+        let fixture = Fixture::new();
+        let mut chain = fixture.chain().with_block_cache(BlockCache::new(1, 1));
+        let a1 = fixture.child(&[], vec![fixture.transfer(0, 1)], 1, 0);
+        let a2 = fixture.child(&[&a1], vec![fixture.transfer(1, 1)], 1, 0);
+        let a1_hash = chain.append_block(a1.clone()).unwrap();
+        chain.append_block(a2).unwrap();
+
+        // 缓存只能容纳一个区块，a1 已被淘汰，需要从存储重新加载
+        let before = chain.cache_metrics();
+        let block = chain.get_block(a1_hash).unwrap().unwrap();
+        assert_eq!(block.header.state_root, a1.header.state_root);
+        assert_eq!(chain.cache_metrics().block_misses, before.block_misses + 1);
+
+        let numbers: Vec<u64> = chain
+            .blocks_in_range(0..10)
+            .map(|block| block.unwrap().header.number)
+            .collect();
+        assert_eq!(numbers, vec![0, 1, 2]);
+        let metrics = chain.cache_metrics();
+        assert!(metrics.blocks <= 1);
+        assert!(metrics.block_misses > before.block_misses + 1);
+        assert!(chain.get_block(Hash256([7; 32])).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_open_restores_head_and_state() {
+        let fixture = Fixture::new();
+        let storage = SharedStorage::default();
+        let mut chain = fixture.chain_with(Box::new(storage.clone()));
+        let t1 = fixture.transfer(1, 2);
+        let a1 = fixture.child(&[], vec![fixture.transfer(0, 1)], 1, 0);
+        let a2 = fixture.child(&[&a1], vec![t1.clone()], 1, 0);
+        chain.append_block(a1).unwrap();
+        chain.append_block(a2).unwrap();
+
+        let reopened = fixture.open(Box::new(storage)).unwrap();
+        assert_eq!(
+            (reopened.head(), reopened.height()),
+            (chain.head(), chain.height())
+        );
+        assert_eq!(reopened.state().state_root(), chain.state().state_root());
+        let lookup = reopened.get_transaction(t1.canonical_hash()).unwrap().unwrap();
+        assert_eq!(lookup.block_hash, chain.head());
+    }
+
+    #[test]
+    fn test_open_without_head_pointer() {
+        let fixture = Fixture::new();
+        let a1 = fixture.child(&[], vec![fixture.transfer(0, 1)], 1, 0);
+        let a2 = fixture.child(&[&a1], vec![], 1, 0);
+        let a2_hash = a2.hash_with(&TestCodec).unwrap();
+
+        // 只有区块和高度索引、没有 head 指针的旧存储
+        let storage = SharedStorage::default();
+        let mut batch = StorageBatch::new();
+        for (number, block) in [&fixture.genesis, &a1, &a2].into_iter().enumerate() {
+            let hash = block.hash_with(&TestCodec).unwrap();
+            batch.put_block(hash, block.clone());
+            batch.set_canonical(number as u64, hash);
+        }
+        storage.write_batch(batch).unwrap();
+
+        let chain = fixture.open(Box::new(storage.clone())).unwrap();
+        assert_eq!((chain.head(), chain.height()), (a2_hash, 2));
+        assert_eq!(chain.state().state_root(), a2.header.state_root);
+        assert_eq!(storage.get_head().unwrap(), Some(a2_hash));
+        assert!(storage.get_receipts(a2_hash).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_open_removes_stale_indices_above_head() {
+        let fixture = Fixture::new();
+        let tx = fixture.transfer(0, 1);
+        let a1 = fixture.child(&[], vec![], 1, 0);
+        let a2 = fixture.child(&[&a1], vec![tx.clone()], 1, 0);
+        let a1_hash = a1.hash_with(&TestCodec).unwrap();
+        let a2_hash = a2.hash_with(&TestCodec).unwrap();
+
+        // head 指针停在 a1，但 a2 的高度索引和交易索引还在
+        let storage = SharedStorage::default();
+        let mut batch = StorageBatch::new();
+        for (number, block) in [&fixture.genesis, &a1, &a2].into_iter().enumerate() {
+            let hash = block.hash_with(&TestCodec).unwrap();
+            batch.put_block(hash, block.clone());
+            batch.set_canonical(number as u64, hash);
+        }
+        batch.set_tx_location(
+            tx.canonical_hash(),
+            TxLocation {
+                block_hash: a2_hash,
+                index: 0,
+            },
+        );
+        batch.set_head(a1_hash);
+        storage.write_batch(batch).unwrap();
+
+        let chain = fixture.open(Box::new(storage.clone())).unwrap();
+        assert_eq!((chain.head(), chain.height()), (a1_hash, 1));
+        assert_eq!(storage.get_canonical_hash(2).unwrap(), None);
+        assert_eq!(storage.get_tx_location(tx.canonical_hash()).unwrap(), None);
+        assert!(chain.get_transaction(tx.canonical_hash()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_open_rejects_other_genesis() {
+        let storage = SharedStorage::default();
+        Fixture::new().chain_with(Box::new(storage.clone()));
+        assert!(matches!(
+            Fixture::new().open(Box::new(storage)),
+            Err(ChainError::InvalidGenesis(_))
+        ));
+    }
+
+    #[test]
+    fn test_failed_write_leaves_chain_unchanged() {
+        let fixture = Fixture::new();
+        let storage = SharedStorage::default();
+        let mut chain = fixture.chain_with(Box::new(storage.clone()));
+        let genesis = (chain.head(), chain.state().state_root());
+        let a1 = fixture.child(&[], vec![fixture.transfer(0, 1)], 1, 0);
+        let a1_hash = a1.hash_with(&TestCodec).unwrap();
+
+        storage.fail_writes(true);
+        assert!(matches!(
+            chain.append_block(a1.clone()),
+            Err(ChainError::Storage(_))
+        ));
+        assert_eq!((chain.head(), chain.state().state_root()), genesis);
+        assert!(chain.get_block(a1_hash).unwrap().is_none());
+        assert!(chain.undo_logs.is_empty());
+
+        // 存储恢复后同一个区块可以正常接入
+        storage.fail_writes(false);
+        assert_eq!(chain.append_block(a1.clone()).unwrap(), a1_hash);
+        assert_eq!(chain.state().state_root(), a1.header.state_root);
+    }
+
+    #[test]
+    fn test_finalize_prunes_conflicting_fork() {
+        let fixture = Fixture::new();
+        let storage = SharedStorage::default();
+        let mut chain = fixture.chain_with(Box::new(storage.clone()));
+        let subscription = chain.subscribe(16);
+        let a1 = fixture.child(&[], vec![], 1, 0);
+        let a2 = fixture.child(&[&a1], vec![], 1, 0);
+        let b2 = fixture.child(&[&a1], vec![], 1, 5);
+        let a1_hash = chain.append_block(a1.clone()).unwrap();
+        let a2_hash = chain.append_block(a2).unwrap();
+        let b2_hash = chain.append_block(b2).unwrap();
+        subscription.drain();
+
+        chain.finalize(a2_hash).unwrap();
+        assert_eq!((chain.finalized(), chain.finalized_number()), (Some(a2_hash), 2));
+        assert_eq!(storage.get_finalized().unwrap(), Some(a2_hash));
+        assert!(chain.get_block(b2_hash).unwrap().is_none());
+        assert!(storage.get_block(b2_hash).unwrap().is_none());
+        assert_eq!(
+            subscription.drain(),
+            vec![ChainEvent::BlockFinalized {
+                hash: a2_hash,
+                number: 2
+            }]
+        );
+
+        // 确认不能后退，也不再接受从已确认区块之前分出的分叉
+        assert!(matches!(
+            chain.finalize(a1_hash),
+            Err(ChainError::ConflictsWithFinalized(hash)) if hash == a2_hash
+        ));
+        let c2 = fixture.child(&[&a1], vec![], 5, 9);
+        assert!(matches!(
+            chain.append_block(c2),
+            Err(ChainError::ConflictsWithFinalized(hash)) if hash == a2_hash
+        ));
+    }
+
+    #[test]
+    fn test_finality_depth_survives_reopen() {
+        let fixture = Fixture::new();
+        let storage = SharedStorage::default();
+        let config = ChainConfig {
+            finality_depth: Some(2),
+            ..ChainConfig::default()
+        };
+        let mut chain = fixture
+            .chain_with(Box::new(storage.clone()))
+            .with_config(config);
+        let a1 = fixture.child(&[], vec![], 1, 0);
+        let a2 = fixture.child(&[&a1], vec![], 1, 0);
+        let a1_hash = chain.append_block(a1.clone()).unwrap();
+        chain.append_block(a2.clone()).unwrap();
+        assert_eq!(chain.finalized(), None);
+
+        // a1 得到两个后续确认，与 head 一起写入
+        let a3 = fixture.child(&[&a1, &a2], vec![], 1, 0);
+        chain.append_block(a3).unwrap();
+        assert_eq!(chain.finalized(), Some(a1_hash));
+        assert_eq!(storage.get_finalized().unwrap(), Some(a1_hash));
+        assert!(!chain.undo_logs.contains_key(&a1_hash));
+
+        let reopened = fixture.open(Box::new(storage)).unwrap();
+        assert_eq!(reopened.finalized(), Some(a1_hash));
+        assert_eq!(reopened.head(), chain.head());
+    }
+
+    #[test]
+    fn test_export_then_import_into_fresh_chain() {
+        let fixture = Fixture::new();
+        let mut source = fixture.chain();
+        let a1 = fixture.child(&[], vec![fixture.transfer(0, 1)], 1, 0);
+        let a2 = fixture.child(&[&a1], vec![fixture.transfer(1, 2)], 1, 0);
+        source.append_block(a1).unwrap();
+        source.append_block(a2).unwrap();
+        let mut archive = Vec::new();
+        assert_eq!(source.export_blocks(0..10, &mut archive).unwrap(), 3);
+
+        let mut target = fixture.chain();
+        let mut reports = Vec::new();
+        let imported = target
+            .import_blocks(archive.as_slice(), |progress| reports.push(*progress))
+            .unwrap();
+        assert_eq!(imported, 3);
+        assert_eq!((target.head(), target.height()), (source.head(), 2));
+        assert_eq!(target.state().state_root(), source.state().state_root());
+        // 创世区块已在链上，同样计入进度
+        let counts: Vec<(u64, u64, u64)> = reports
+            .iter()
+            .map(|progress| (progress.number, progress.imported, progress.total))
+            .collect();
+        assert_eq!(counts, vec![(0, 1, 3), (1, 2, 3), (2, 3, 3)]);
+        assert_eq!(reports[2].hash, source.head());
+    }
+
+    #[test]
+    fn test_import_rejects_bad_archives() {
+        let fixture = Fixture::new();
+        let mut source = fixture.chain();
+        let a1 = fixture.child(&[], vec![], 1, 0);
+        let a2 = fixture.child(&[&a1], vec![], 1, 0);
+        let a1_hash = source.append_block(a1).unwrap();
+        source.append_block(a2).unwrap();
+        let mut archive = Vec::new();
+        source.export_blocks(0..10, &mut archive).unwrap();
+
+        // 最后一个区块被截断：之前的区块保留在链上
+        let mut target = fixture.chain();
+        assert!(matches!(
+            target.import_blocks(&archive[..archive.len() - 3], |_| {}),
+            Err(ChainError::Io(_))
+        ));
+        assert_eq!(target.head(), a1_hash);
+
+        // 其他链的导出文件
+        let mut other = Fixture::new().chain();
+        assert!(matches!(
+            other.import_blocks(archive.as_slice(), |_| {}),
+            Err(ChainError::InvalidGenesis(_))
+        ));
+        assert_eq!(other.height(), 0);
+    }
+
+    #[test]
+    fn test_verify_chain_reports_first_divergence() {
+        let fixture = Fixture::new();
+        let storage = SharedStorage::default();
+        let mut chain = fixture.chain_with(Box::new(storage.clone()));
+        let a1 = fixture.child(&[], vec![fixture.transfer(0, 1)], 1, 0);
+        let a2 = fixture.child(&[&a1], vec![fixture.transfer(1, 2)], 1, 0);
+        let a3 = fixture.child(&[&a1, &a2], vec![], 1, 0);
+        let a1_hash = chain.append_block(a1).unwrap();
+        let a2_hash = chain.append_block(a2).unwrap();
+        chain.append_block(a3).unwrap();
+        let genesis = || StateSnapshot::from(fixture.spec.build().unwrap());
+
+        let report = chain.verify_chain(genesis()).unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.verified, 3);
+        assert_eq!(report.state_root, chain.state().state_root());
+
+        // 快照与区块头的状态根不一致
+        let report = chain
+            .verify_chain(StateSnapshot::new(1, fixture.state_after(&[])))
+            .unwrap();
+        let divergence = report.divergence.unwrap();
+        assert_eq!((divergence.number, divergence.hash), (1, a1_hash));
+        assert!(matches!(
+            divergence.kind,
+            DivergenceKind::Block(ChainError::StateRootMismatch { .. })
+        ));
+
+        // 存储中 a2 的回执被篡改
+        let mut batch = StorageBatch::new();
+        batch.put_receipts(a2_hash, Vec::new());
+        storage.write_batch(batch).unwrap();
+        let report = chain.verify_chain(genesis()).unwrap();
+        assert_eq!(report.verified, 1);
+        let divergence = report.divergence.unwrap();
+        assert_eq!((divergence.number, divergence.hash), (2, a2_hash));
+        assert!(matches!(divergence.kind, DivergenceKind::Receipts));
+
+        // 更早的高度索引指向了别的区块
+        let mut batch = StorageBatch::new();
+        batch.set_canonical(1, a2_hash);
+        storage.write_batch(batch).unwrap();
+        let report = chain.verify_chain(genesis()).unwrap();
+        assert_eq!(report.verified, 0);
+        let divergence = report.divergence.unwrap();
+        assert_eq!((divergence.number, divergence.hash), (1, a1_hash));
+        assert!(matches!(
+            divergence.kind,
+            DivergenceKind::CanonicalIndex { expected, actual: Some(actual) }
+                if expected == a1_hash && actual == a2_hash
+        ));
+    }
+
+    #[test]
+    fn test_known_bad_block_rejects_descendants() {
+        let fixture = Fixture::new();
+        let storage = SharedStorage::default();
+        let mut chain = fixture.chain_with(Box::new(storage.clone()));
+        let a1 = fixture.child(&[], vec![], 1, 0);
+        chain.append_block(a1.clone()).unwrap();
+
+        // 时间戳不晚于祖先中位数，由区块头决定
+        let mut a2 = fixture.child(&[&a1], vec![], 1, 0);
+        a2.header.timestamp = a1.header.timestamp;
+        let a2_hash = a2.hash_with(&TestCodec).unwrap();
+        assert!(matches!(
+            chain.append_block(a2.clone()),
+            Err(ChainError::TimestampTooOld { .. })
+        ));
+        assert!(matches!(
+            chain.append_block(a2.clone()),
+            Err(ChainError::KnownBadBlock(hash)) if hash == a2_hash
+        ));
+        let bad = chain.bad_block(a2_hash).unwrap().unwrap();
+        assert_eq!(bad.header.number, 2);
+
+        let a3 = fixture.child(&[&a1, &a2], vec![], 1, 0);
+        let a3_hash = a3.hash_with(&TestCodec).unwrap();
+        assert!(matches!(
+            chain.append_block(a3),
+            Err(ChainError::KnownBadBlock(hash)) if hash == a2_hash
+        ));
+        assert!(chain.bad_block(a3_hash).unwrap().is_none());
+        assert_eq!(chain.height(), 1);
+
+        // 重新打开后记录仍然有效
+        drop(chain);
+        let mut chain = fixture.open(Box::new(storage)).unwrap();
+        assert!(matches!(
+            chain.append_block(a2),
+            Err(ChainError::KnownBadBlock(hash)) if hash == a2_hash
+        ));
+    }
+
+    #[test]
+    fn test_body_faults_are_not_registered() {
+        let fixture = Fixture::new();
+        let mut chain = fixture.chain();
+        let a1 = fixture.child(&[], vec![fixture.transfer(0, 1)], 1, 0);
+        let a1_hash = a1.hash_with(&TestCodec).unwrap();
+
+        // 区块头不变、区块体被替换，hash 与正确的区块相同
+        let mut tampered = a1.clone();
+        tampered.transactions = vec![fixture.transfer(0, 2)];
+        assert_eq!(tampered.hash_with(&TestCodec).unwrap(), a1_hash);
+        assert!(matches!(
+            chain.append_block(tampered),
+            Err(ChainError::TxRootMismatch { .. })
+        ));
+        assert!(chain.bad_block(a1_hash).unwrap().is_none());
+
+        assert_eq!(chain.append_block(a1).unwrap(), a1_hash);
+        assert_eq!(chain.head(), a1_hash);
+    }
+
+    #[test]
+    fn test_bad_block_registry_is_capped() {
+        let fixture = Fixture::new();
+        let config = ChainConfig {
+            max_bad_blocks: 2,
+            ..ChainConfig::default()
+        };
+        let mut chain = fixture.chain().with_config(config);
+        let bad: Vec<Block> = (0..3)
+            .map(|offset| {
+                let mut block = fixture.child(&[], vec![], 1, offset);
+                block.header.number = 5;
+                block
+            })
+            .collect();
+        let hashes: Vec<Hash256> = bad
+            .iter()
+            .map(|b| b.hash_with(&TestCodec).unwrap())
+            .collect();
+        chain.append_block(bad[0].clone()).unwrap_err();
+        chain.append_block(bad[1].clone()).unwrap_err();
+        // 再次命中的记录移到队尾，登记第三个时淘汰的是 bad[1]
+        assert!(matches!(
+            chain.append_block(bad[0].clone()),
+            Err(ChainError::KnownBadBlock(_))
+        ));
+        chain.append_block(bad[2].clone()).unwrap_err();
+
+        assert!(chain.bad_block(hashes[0]).unwrap().is_some());
+        assert!(chain.bad_block(hashes[1]).unwrap().is_none());
+        assert!(chain.bad_block(hashes[2]).unwrap().is_some());
+        assert!(matches!(
+            chain.append_block(bad[1].clone()),
+            Err(ChainError::InvalidHeight {
+                expected: 1,
+                actual: 5
+            })
+        ));
+    }
+
+    #[test]
+    fn test_submit_rejects_stale_unaffordable_and_oversized() {
+        let fixture = Fixture::new();
+        let mut chain = fixture.chain();
+        let a1 = fixture.child(&[], vec![fixture.transfer(0, 1)], 1, 0);
+        chain.append_block(a1).unwrap();
+
+        let reject = |chain: &mut Blockchain<TestCodec>, tx| {
+            assert!(matches!(
+                chain.submit_transaction(tx),
+                Err(ChainError::InvalidTransaction(_))
+            ));
+        };
+        reject(&mut chain, fixture.transfer(0, 2));
+        let builder = || {
+            TransactionBuilder::new()
+                .chain_id(CHAIN_ID)
+                .to(Address([2; 20]))
+                .nonce(1)
+                .gas_limit(1)
+                .gas_price(1)
+        };
+        let unaffordable = builder().value(INITIAL_BALANCE as u64);
+        reject(&mut chain, unaffordable.sign(&fixture.keypair));
+        let oversized = builder().data(vec![0; MAX_TX_SIZE]);
+        reject(&mut chain, oversized.sign(&fixture.keypair));
+        assert!(chain.mempool().is_empty());
+
+        chain.submit_transaction(fixture.transfer(1, 2)).unwrap();
+        assert_eq!(chain.mempool().len(), 1);
+    }
+
+    #[test]
+    fn test_unauthenticated_pow_failures_are_not_registered() {
+        let fixture = Fixture::with_spec(|spec| {
+            spec.consensus.engine = ConsensusEngine::ProofOfWork;
+            spec.consensus.initial_difficulty = 100;
+        });
+        let mut chain = fixture.chain();
+        let sealed = |difficulty, stale: bool| {
+            let mut block = fixture.child(&[], vec![], difficulty, 0);
+            if stale {
+                block.header.timestamp -= 1;
+            }
+            difficulty::seal(&mut block.header, &TestCodec).unwrap();
+            block
+        };
+
+        // hash 不满足声明的难度
+        let mut no_work = sealed(100, false);
+        while difficulty::meets_target(&no_work.hash_with(&TestCodec).unwrap(), 100) {
+            no_work.header.nonce += 1;
+        }
+        // 声明的难度低于父区块要求，工作量几乎为零
+        let cheap = sealed(1, false);
+        for block in [no_work, cheap] {
+            let hash = block.hash_with(&TestCodec).unwrap();
+            for _ in 0..2 {
+                let error = chain.append_block(block.clone()).unwrap_err();
+                assert!(matches!(
+                    error,
+                    ChainError::InvalidProofOfWork { .. } | ChainError::InvalidDifficulty { .. }
+                ));
+            }
+            assert!(chain.bad_block(hash).unwrap().is_none());
+        }
+
+        // 完成了要求的工作量、但时间戳无效的区块会登记
+        let stale = sealed(100, true);
+        let stale_hash = stale.hash_with(&TestCodec).unwrap();
+        assert!(matches!(
+            chain.append_block(stale.clone()),
+            Err(ChainError::TimestampTooOld { .. })
+        ));
+        assert!(matches!(
+            chain.append_block(stale),
+            Err(ChainError::KnownBadBlock(hash)) if hash == stale_hash
+        ));
+    }
 }
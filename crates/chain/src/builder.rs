@@ -0,0 +1,185 @@
+use crate::block_executor::BlockExecutor;
+use crate::clock::{Clock, SystemClock};
+use crate::error::ChainError;
+use crate::merkle;
+use latte_codec::codec::Codec;
+use latte_primitives::address::Address;
+use latte_primitives::hash::Hash256;
+use latte_state::vm::VmEngine;
+use latte_types::block::Block;
+use latte_types::header::BlockHeader;
+use latte_types::receipt::Receipt;
+use latte_types::transaction::Transaction;
+use std::sync::Arc;
+
+/// 区块构造器
+///
+/// 负责填充区块头中可以推导出来的字段，避免手动计算出错：
+/// - parent_hash / number：由父区块推导
+/// - tx_root：由交易列表计算
+/// - receipt_root / gas_used：由回执计算
+/// - state_root：由执行后的状态计算
+/// - timestamp：未指定时取时钟的当前时间，且晚于父区块
+pub struct BlockBuilder {
+    parent_hash: Hash256,
+    number: u64,
+    parent_timestamp: u64,
+    timestamp: Option<u64>,
+    state_root: Hash256,
+    beneficiary: Address,
+    difficulty: u64,
+    nonce: u64,
+    extra_data: Vec<u8>,
+    transactions: Vec<Transaction>,
+    receipts: Vec<Receipt>,
+    clock: Arc<dyn Clock>,
+}
+
+impl BlockBuilder {
+    /// 创世区块：没有父区块，高度为 0
+    pub fn genesis() -> Self {
+        Self {
+            parent_hash: Hash256([0u8; 32]),
+            number: 0,
+            parent_timestamp: 0,
+            timestamp: None,
+            state_root: Hash256([0u8; 32]),
+            beneficiary: Address([0u8; 20]),
+            difficulty: 1,
+            nonce: 0,
+            extra_data: Vec::new(),
+            transactions: Vec::new(),
+            receipts: Vec::new(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// 以 parent 为父区块构造子区块
+    pub fn child_of<C: Codec>(parent: &BlockHeader, codec: &C) -> Result<Self, ChainError> {
+        let parent_hash = parent.hash_with(codec).map_err(ChainError::InvalidBlock)?;
+        Ok(Self {
+            parent_hash,
+            number: parent.number + 1,
+            parent_timestamp: parent.timestamp,
+            difficulty: parent.difficulty,
+            ..Self::genesis()
+        })
+    }
+
+    pub fn timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// 没有指定时间戳时使用的时钟，默认为系统时钟
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// 出块者，接收区块奖励和手续费
+    pub fn beneficiary(mut self, beneficiary: Address) -> Self {
+        self.beneficiary = beneficiary;
+        self
+    }
+
+    pub fn difficulty(mut self, difficulty: u64) -> Self {
+        self.difficulty = difficulty;
+        self
+    }
+
+    pub fn nonce(mut self, nonce: u64) -> Self {
+        self.nonce = nonce;
+        self
+    }
+
+    pub fn extra_data(mut self, extra_data: Vec<u8>) -> Self {
+        self.extra_data = extra_data;
+        self
+    }
+
+    pub fn transaction(mut self, tx: Transaction) -> Self {
+        self.transactions.push(tx);
+        self
+    }
+
+    pub fn transactions(mut self, txs: impl IntoIterator<Item = Transaction>) -> Self {
+        self.transactions.extend(txs);
+        self
+    }
+
+    /// 直接指定回执，适用于交易已经在别处执行过的情况
+    pub fn receipts(mut self, receipts: Vec<Receipt>) -> Self {
+        self.receipts = receipts;
+        self
+    }
+
+    pub fn state_root(mut self, state_root: Hash256) -> Self {
+        self.state_root = state_root;
+        self
+    }
+
+    /// 执行所有交易，使用执行得到的回执和状态根
+    ///
+    /// 任意一笔交易失败即返回错误，此时状态已由 block_executor 回滚
+    pub fn execute<V: VmEngine>(
+        mut self,
+        block_executor: &mut BlockExecutor<V>,
+    ) -> Result<Self, ChainError> {
+        let outcome = block_executor.execute_transactions(&self.transactions, &self.beneficiary)?;
+        self.receipts = outcome.receipts;
+        self.state_root = outcome.state_root;
+        Ok(self)
+    }
+
+    pub fn build(self) -> Block {
+        let timestamp = self
+            .timestamp
+            .unwrap_or_else(|| self.clock.now().max(self.parent_timestamp + 1));
+
+        let header = BlockHeader {
+            parent_hash: self.parent_hash,
+            state_root: self.state_root,
+            tx_root: merkle::tx_root_hash(&self.transactions),
+            receipt_root: merkle::receipt_root_hash(&self.receipts),
+            number: self.number,
+            timestamp,
+            gas_used: self.receipts.iter().map(|r| r.gas_used).sum(),
+            beneficiary: self.beneficiary,
+            difficulty: self.difficulty,
+            nonce: self.nonce,
+            extra_data: self.extra_data,
+        };
+
+        Block {
+            header,
+            transactions: self.transactions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::test_utils::TestCodec;
+
+    #[test]
+    fn test_default_timestamp_from_clock() {
+        let clock = Arc::new(MockClock::new(100));
+        let parent = BlockBuilder::genesis().timestamp(50).build();
+        let child = BlockBuilder::child_of(&parent.header, &TestCodec)
+            .unwrap()
+            .clock(clock.clone())
+            .build();
+        assert_eq!(child.header.timestamp, 100);
+
+        // 时钟落后于父区块时取父区块时间戳加 1
+        clock.set(10);
+        let child = BlockBuilder::child_of(&parent.header, &TestCodec)
+            .unwrap()
+            .clock(clock)
+            .build();
+        assert_eq!(child.header.timestamp, 51);
+    }
+}
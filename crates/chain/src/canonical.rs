@@ -1,26 +1,69 @@
+use latte_primitives::hash::{Hash256, sha256};
+use latte_types::receipt::Receipt;
 use latte_types::transaction::Transaction;
 
 pub trait CanonicalEncode {
     fn canonical_bytes(&self) -> Vec<u8>;
+
+    /// 规范编码的 sha256，交易 hash、回执 hash 与默克尔树叶子都以此为准
+    fn canonical_hash(&self) -> Hash256 {
+        sha256(&self.canonical_bytes())
+    }
 }
 
 impl CanonicalEncode for Transaction {
     fn canonical_bytes(&self) -> Vec<u8> {
-        let mut out = Vec::new();
-        out.extend(self.from.0);
+        // 签名消息 + 各方的公钥和签名，变长字段加 u32 长度前缀，字节不能在相邻字段间挪动
+        let mut out = self.signing_bytes();
+        extend_prefixed(&mut out, &self.public_key);
+        extend_prefixed(&mut out, &self.signature);
 
-        match &self.to {
-            Some(addr) => out.extend(addr.0),
-            None => out.extend([0u8; 20]), // 约定 None = 20 个 0
+        if let Some(payer) = &self.fee_payer {
+            extend_prefixed(&mut out, &payer.public_key);
+            extend_prefixed(&mut out, &payer.signature);
         }
 
-        out.extend(self.value.to_be_bytes());
-        out.extend(self.nonce.to_be_bytes());
-        out.extend(self.gas_limit.to_be_bytes());
-        out.extend(self.gas_price.to_be_bytes());
-        out.extend(&self.data);
-        out.extend(&self.signature);
+        out
+    }
+}
+
+fn extend_prefixed(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend((bytes.len() as u32).to_be_bytes());
+    out.extend(bytes);
+}
+
+impl CanonicalEncode for Receipt {
+    fn canonical_bytes(&self) -> Vec<u8> {
+        // 变长字段统一加 u32 长度前缀
+        let mut out = Vec::new();
+        out.extend((self.transaction_hash.length() as u32).to_be_bytes());
+        out.extend(self.transaction_hash.as_slice());
+        out.extend((self.status.length() as u32).to_be_bytes());
+        out.extend(self.status.as_slice());
+        out.extend(self.gas_used.to_be_bytes());
+
+        out.extend((self.logs.len() as u32).to_be_bytes());
+        for log in &self.logs {
+            out.extend((log.length() as u32).to_be_bytes());
+            out.extend(log.as_slice());
+        }
 
         out
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::Fixture;
+
+    #[test]
+    fn test_moved_signature_bytes_change_hash() {
+        let tx = Fixture::new().transfer(0, 1);
+        let mut moved = tx.clone();
+        let byte = moved.public_key.pop().unwrap();
+        moved.signature.insert(0, byte);
+
+        assert_ne!(tx.canonical_hash(), moved.canonical_hash());
+    }
+}
@@ -0,0 +1,48 @@
+//! 时钟
+// 校验区块时间戳时需要当前时间，通过 Clock 注入，测试中使用 MockClock 得到确定的结果
+
+use chrono::Utc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// 当前时间，unix 时间戳（秒）
+pub trait Clock: Send + Sync {
+    fn now(&self) -> u64;
+}
+
+/// 系统时钟
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        Utc::now().timestamp() as u64
+    }
+}
+
+/// 手动控制的时钟，用于测试
+#[derive(Debug, Default)]
+pub struct MockClock {
+    now: AtomicU64,
+}
+
+impl MockClock {
+    pub fn new(now: u64) -> Self {
+        Self {
+            now: AtomicU64::new(now),
+        }
+    }
+
+    pub fn set(&self, now: u64) {
+        self.now.store(now, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, seconds: u64) {
+        self.now.fetch_add(seconds, Ordering::SeqCst);
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,33 @@
+//! 链配置
+// 与共识相关、但不属于创世区块的本地规则
+
+use crate::bad_blocks::DEFAULT_MAX_BAD_BLOCKS;
+
+/// 区块时间戳最多可以超前本地时钟的秒数
+pub const DEFAULT_MAX_FUTURE_DRIFT: u64 = 15;
+/// 保留撤销日志的主链区块数量，即最深可以重组的区块数
+pub const DEFAULT_MAX_REORG_DEPTH: u64 = 1024;
+
+#[derive(Clone, Debug)]
+pub struct ChainConfig {
+    /// 区块时间戳不能超过 本地时间 + max_future_drift
+    pub max_future_drift: u64,
+    /// 主链区块得到这么多个后续确认后自动最终确认，None 表示只由外部的最终确认机制决定
+    pub finality_depth: Option<u64>,
+    /// 只保留最近这么多个主链区块的撤销日志，更深的链重组被拒绝；
+    /// 没有最终确认时用它限制撤销日志占用的内存
+    pub max_reorg_depth: u64,
+    /// 最多保留的无效区块记录数，超出时淘汰最久没有命中的记录
+    pub max_bad_blocks: usize,
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        Self {
+            max_future_drift: DEFAULT_MAX_FUTURE_DRIFT,
+            finality_depth: None,
+            max_reorg_depth: DEFAULT_MAX_REORG_DEPTH,
+            max_bad_blocks: DEFAULT_MAX_BAD_BLOCKS,
+        }
+    }
+}
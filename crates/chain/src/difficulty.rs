@@ -0,0 +1,127 @@
+//! 难度调整
+//!
+//! 工作量证明链根据最近若干区块的实际出块时间调整难度，使平均出块间隔接近目标值：
+//!
+//! ```text
+//! next = parent_difficulty * (target_block_time * intervals) / actual_span
+//! ```
+//!
+//! - 最近 [`DIFFICULTY_WINDOW`] 个区块间隔参与计算，祖先不足时使用全部祖先
+//! - 单次调整幅度限制在 [`MAX_ADJUSTMENT_FACTOR`] 倍以内，避免时间戳异常导致难度剧烈波动
+//! - 难度不低于 [`MIN_DIFFICULTY`]
+//!
+//! 工作量证明：区块 hash 按大端序解释为 256 位整数，不能超过 `(2^256 - 1) / difficulty`，
+//! 出块者通过调整区块头中的 nonce 寻找满足条件的 hash，见 [`seal`]
+
+use crate::error::ChainError;
+use latte_codec::codec::Codec;
+use latte_primitives::hash::Hash256;
+use latte_types::header::BlockHeader;
+
+/// 参与计算的区块间隔数量
+pub const DIFFICULTY_WINDOW: usize = 10;
+/// 单次调整的最大倍数
+pub const MAX_ADJUSTMENT_FACTOR: u64 = 4;
+pub const MIN_DIFFICULTY: u64 = 1;
+
+/// 由父区块难度和最近区块的时间戳（按高度升序，最后一个为父区块）计算下一个区块的难度
+pub fn next_difficulty(parent_difficulty: u64, timestamps: &[u64], target_block_time: u64) -> u64 {
+    let (Some(first), Some(last)) = (timestamps.first(), timestamps.last()) else {
+        return parent_difficulty.max(MIN_DIFFICULTY);
+    };
+    let intervals = (timestamps.len() - 1) as u128;
+    if intervals == 0 || target_block_time == 0 {
+        return parent_difficulty.max(MIN_DIFFICULTY);
+    }
+
+    let expected = target_block_time as u128 * intervals;
+    // 时间戳严格递增，实际间隔至少为 1 秒
+    let actual = last.saturating_sub(*first).max(1) as u128;
+    let adjusted = parent_difficulty as u128 * expected / actual;
+
+    let lower = (parent_difficulty / MAX_ADJUSTMENT_FACTOR) as u128;
+    let upper = parent_difficulty as u128 * MAX_ADJUSTMENT_FACTOR as u128;
+    let next = adjusted.clamp(lower, upper).min(u64::MAX as u128) as u64;
+    next.max(MIN_DIFFICULTY)
+}
+
+/// 难度对应的目标值（大端序），难度越高目标值越小
+pub fn target(difficulty: u64) -> [u8; 32] {
+    // (2^256 - 1) / difficulty 按字节做长除法
+    let divisor = difficulty.max(MIN_DIFFICULTY) as u128;
+    let mut target = [0u8; 32];
+    let mut remainder = 0u128;
+    for byte in target.iter_mut() {
+        let current = (remainder << 8) | 0xff;
+        *byte = (current / divisor) as u8;
+        remainder = current % divisor;
+    }
+    target
+}
+
+/// 区块 hash 是否满足难度要求
+pub fn meets_target(hash: &Hash256, difficulty: u64) -> bool {
+    hash.0 <= target(difficulty)
+}
+
+/// 从 nonce 0 开始搜索，直到区块头的 hash 满足难度要求
+pub fn seal<C: Codec>(header: &mut BlockHeader, codec: &C) -> Result<Hash256, ChainError> {
+    let target = target(header.difficulty);
+    header.nonce = 0;
+    loop {
+        let hash = header.hash_with(codec).map_err(ChainError::InvalidBlock)?;
+        if hash.0 <= target {
+            return Ok(hash);
+        }
+        header.nonce = header
+            .nonce
+            .checked_add(1)
+            .ok_or_else(|| ChainError::InvalidBlock("nonce space exhausted".to_string()))?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::BlockBuilder;
+    use crate::test_utils::TestCodec;
+
+    #[test]
+    fn test_target() {
+        assert_eq!(target(1), [0xff; 32]);
+        let half = target(2);
+        assert_eq!(half[0], 0x7f);
+        assert!(half[1..].iter().all(|byte| *byte == 0xff));
+        assert_eq!(target(256)[0], 0);
+        assert_eq!(target(256)[1], 0xff);
+
+        let mut hash = Hash256(target(256));
+        assert!(meets_target(&hash, 256));
+        hash.0[1] = 0;
+        hash.0[0] = 1;
+        assert!(!meets_target(&hash, 256));
+    }
+
+    #[test]
+    fn test_seal_finds_valid_nonce() {
+        let mut header = BlockBuilder::genesis().difficulty(64).timestamp(1).build().header;
+        let hash = seal(&mut header, &TestCodec).unwrap();
+        assert_eq!(header.hash_with(&TestCodec).unwrap(), hash);
+        assert!(meets_target(&hash, 64));
+    }
+
+    #[test]
+    fn test_next_difficulty() {
+        // 出块间隔正好等于目标值，难度不变
+        assert_eq!(next_difficulty(1000, &[0, 10, 20, 30], 10), 1000);
+        // 出块太快，难度上升；太慢，难度下降
+        assert_eq!(next_difficulty(1000, &[0, 5, 10, 15], 10), 2000);
+        assert_eq!(next_difficulty(1000, &[0, 20, 40, 60], 10), 500);
+        // 调整幅度受限
+        assert_eq!(next_difficulty(1000, &[0, 1], 100), 4000);
+        assert_eq!(next_difficulty(1000, &[0, 10_000], 10), 250);
+        // 只有父区块时无法计算间隔，沿用父区块难度
+        assert_eq!(next_difficulty(1000, &[5], 10), 1000);
+        assert_eq!(next_difficulty(1, &[0, 1000], 10), MIN_DIFFICULTY);
+    }
+}
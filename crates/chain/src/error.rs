@@ -1,26 +1,70 @@
 use thiserror::Error;
 use crate::storage_error::StorageError;
+use latte_primitives::hash::Hash256;
+use latte_state::error::StateError;
 
 #[derive(Debug, Error)]
 pub enum ChainError {
     #[error("invalid parent hash")]
     InvalidParent,
 
-    #[error("invalid block height")]
-    InvalidHeight,
+    #[error("invalid block height: expected {expected}, got {actual}")]
+    InvalidHeight { expected: u64, actual: u64 },
+
+    #[error("unknown parent block {0:?}")]
+    UnknownParent(Hash256),
 
     #[error("block execution failed")]
     ExecutionFailed,
 
-    #[error("state root mismatch")]
-    StateRootMismatch,
+    #[error("state root mismatch: expected {expected:?}, got {actual:?}")]
+    StateRootMismatch { expected: Hash256, actual: Hash256 },
+
+    #[error("transaction root mismatch: expected {expected:?}, got {actual:?}")]
+    TxRootMismatch { expected: Hash256, actual: Hash256 },
+
+    #[error("receipt root mismatch: expected {expected:?}, got {actual:?}")]
+    ReceiptRootMismatch { expected: Hash256, actual: Hash256 },
+
+    #[error("gas used mismatch: expected {expected}, got {actual}")]
+    GasUsedMismatch { expected: u64, actual: u64 },
+
+    #[error("block gas used {used} exceeds limit {limit}")]
+    BlockGasExceeded { limit: u64, used: u64 },
+
+    #[error("total supply mismatch: expected {expected}, got {actual}")]
+    SupplyMismatch { expected: u128, actual: u128 },
+
+    #[error("transaction {index} failed: {source}")]
+    TxFailed { index: usize, source: StateError },
+
+    #[error("invalid difficulty: expected {expected}, got {actual}")]
+    InvalidDifficulty { expected: u64, actual: u64 },
+
+    #[error("block hash does not meet difficulty {difficulty}")]
+    InvalidProofOfWork { difficulty: u64 },
+
+    #[error("invalid signature in transaction {index}")]
+    InvalidSignature { index: usize },
+
+    #[error("invalid transaction: {0}")]
+    InvalidTransaction(String),
 
-    #[error("transaction root mismatch")]
-    TxRootMismatch,
+    #[error("block {0:?} is known to be invalid")]
+    KnownBadBlock(Hash256),
+
+    #[error("reorg depth {depth} exceeds limit {max}")]
+    ReorgTooDeep { depth: u64, max: u64 },
+
+    #[error("block conflicts with finalized block {0:?}")]
+    ConflictsWithFinalized(Hash256),
 
     #[error("block not found")]
     BlockNotFound,
 
+    #[error("transaction pool is full, gas price must exceed {min_gas_price}")]
+    PoolFull { min_gas_price: u64 },
+
     #[error("invalid block: {0}")]
     InvalidBlock(String),
 
@@ -30,7 +74,41 @@ pub enum ChainError {
     #[error("block timeout error")]
     TimeoutError,
 
+    #[error("timestamp {timestamp} is not after median time past {median}")]
+    TimestampTooOld { timestamp: u64, median: u64 },
+
+    #[error("timestamp {timestamp} is too far in the future (max {max})")]
+    TimestampTooFarInFuture { timestamp: u64, max: u64 },
+
+    #[error("invalid genesis: {0}")]
+    InvalidGenesis(String),
+
     #[error("storage error: {0}")]
     Storage(#[from] StorageError),
 
+    #[error("invalid archive: {0}")]
+    InvalidArchive(String),
+
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+}
+
+impl ChainError {
+    /// 只由区块头和父区块决定的失败，可以按区块 hash 登记为无效区块
+    ///
+    /// 区块 hash 只覆盖区块头，交易根、签名、执行结果这类依赖区块体的失败可能来自被篡改的区块体，
+    /// 同一个区块头配上正确的区块体仍然有效，不能登记；
+    /// 难度和工作量证明错误不需要代价就能伪造，同样不登记，每次无状态地拒绝
+    ///
+    /// 父区块未知、时间戳超前本地时钟、与最终确认冲突以及存储错误等情况下，区块之后仍可能被接受
+    pub fn is_header_fault(&self) -> bool {
+        matches!(
+            self,
+            ChainError::InvalidParent
+                | ChainError::InvalidHeight { .. }
+                | ChainError::BlockGasExceeded { .. }
+                | ChainError::TimestampTooOld { .. }
+        )
+    }
 }
@@ -0,0 +1,234 @@
+//! 链事件订阅
+//!
+//! 链状态变化后向所有订阅者广播 [`ChainEvent`]，P2P、交易池、RPC 订阅和索引器据此响应，无需轮询。
+//!
+//! 每个订阅者有一个有界队列，队列满时按订阅时选择的策略处理：
+//! - [`Overflow::Block`]：发布方阻塞等待订阅者消费，形成背压，不丢事件
+//! - [`Overflow::Drop`]：丢弃新事件并计数，订阅者可通过 [`Subscription::missed`] 发现自己落后了
+//!
+//! 接收端被丢弃的订阅者在下一次发布时自动移除。
+//! 发送在订阅者列表的锁之外进行，阻塞的订阅者不会妨碍其他线程订阅
+
+use latte_primitives::hash::Hash256;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TryRecvError, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub const DEFAULT_EVENT_CAPACITY: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChainEvent {
+    /// head 切换到新区块
+    NewHead { hash: Hash256, number: u64 },
+    /// 链重组：removed 为离开主链的区块，added 为进入主链的区块，都按高度升序
+    Reorg {
+        common_ancestor: Hash256,
+        removed: Vec<Hash256>,
+        added: Vec<Hash256>,
+    },
+    /// 交易被主链上的区块包含
+    TransactionIncluded {
+        tx_hash: Hash256,
+        block_hash: Hash256,
+        index: usize,
+    },
+    /// 区块被最终确认，不会再被重组
+    BlockFinalized { hash: Hash256, number: u64 },
+    /// 区块校验失败，已记入无效区块记录
+    BadBlock { hash: Hash256, reason: String },
+}
+
+/// 订阅者队列满时的处理策略
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overflow {
+    Block,
+    Drop,
+}
+
+#[derive(Clone)]
+struct Subscriber {
+    id: u64,
+    sender: SyncSender<ChainEvent>,
+    overflow: Overflow,
+    missed: Arc<AtomicU64>,
+}
+
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Mutex<Vec<Subscriber>>,
+    next_id: AtomicU64,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 订阅事件，队列满时阻塞发布方
+    pub fn subscribe(&self, capacity: usize) -> Subscription {
+        self.subscribe_with(capacity, Overflow::Block)
+    }
+
+    pub fn subscribe_with(&self, capacity: usize, overflow: Overflow) -> Subscription {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let missed = Arc::new(AtomicU64::new(0));
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.push(Subscriber {
+                id: self.next_id.fetch_add(1, Ordering::Relaxed),
+                sender,
+                overflow,
+                missed: missed.clone(),
+            });
+        }
+        Subscription { receiver, missed }
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers
+            .lock()
+            .map_or(0, |subscribers| subscribers.len())
+    }
+
+    /// 按顺序向所有订阅者发布一批事件
+    ///
+    /// 先复制订阅者列表再发送，阻塞的发送不持有锁；发送时发现已断开的订阅者在发送完后移除
+    pub fn publish_all(&self, events: Vec<ChainEvent>) {
+        if events.is_empty() {
+            return;
+        }
+        let Ok(subscribers) = self.subscribers.lock().map(|subscribers| subscribers.clone()) else {
+            return;
+        };
+        let closed: Vec<u64> = subscribers
+            .iter()
+            .filter(|subscriber| !events.iter().all(|event| subscriber.send(event.clone())))
+            .map(|subscriber| subscriber.id)
+            .collect();
+        if closed.is_empty() {
+            return;
+        }
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.retain(|subscriber| !closed.contains(&subscriber.id));
+        }
+    }
+
+    pub fn publish(&self, event: ChainEvent) {
+        self.publish_all(vec![event]);
+    }
+}
+
+impl Subscriber {
+    /// 发送一个事件，接收端已断开时返回 false
+    fn send(&self, event: ChainEvent) -> bool {
+        match self.overflow {
+            Overflow::Block => self.sender.send(event).is_ok(),
+            Overflow::Drop => match self.sender.try_send(event) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    self.missed.fetch_add(1, Ordering::Relaxed);
+                    true
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            },
+        }
+    }
+}
+
+/// 事件的接收端，丢弃即取消订阅
+pub struct Subscription {
+    receiver: Receiver<ChainEvent>,
+    missed: Arc<AtomicU64>,
+}
+
+impl Subscription {
+    /// 阻塞等待下一个事件，事件总线被销毁后返回 None
+    pub fn recv(&self) -> Option<ChainEvent> {
+        self.receiver.recv().ok()
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<ChainEvent, RecvTimeoutError> {
+        self.receiver.recv_timeout(timeout)
+    }
+
+    pub fn try_recv(&self) -> Result<ChainEvent, TryRecvError> {
+        self.receiver.try_recv()
+    }
+
+    /// 取出当前已到达的所有事件
+    pub fn drain(&self) -> Vec<ChainEvent> {
+        self.receiver.try_iter().collect()
+    }
+
+    /// 因队列已满被丢弃的事件数，只有 [`Overflow::Drop`] 的订阅会丢事件
+    pub fn missed(&self) -> u64 {
+        self.missed.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn head(n: u8) -> ChainEvent {
+        ChainEvent::NewHead {
+            hash: Hash256([n; 32]),
+            number: n as u64,
+        }
+    }
+
+    #[test]
+    fn test_multiple_subscribers() {
+        let bus = EventBus::new();
+        let a = bus.subscribe(4);
+        let b = bus.subscribe(4);
+        bus.publish_all(vec![head(1), head(2)]);
+        assert_eq!(a.drain(), vec![head(1), head(2)]);
+        assert_eq!(b.drain(), vec![head(1), head(2)]);
+
+        // 取消订阅后被移除
+        drop(b);
+        bus.publish(head(3));
+        assert_eq!(bus.subscriber_count(), 1);
+    }
+
+    #[test]
+    fn test_overflow() {
+        let bus = Arc::new(EventBus::new());
+        let lossy = bus.subscribe_with(1, Overflow::Drop);
+        let blocking = bus.subscribe(1);
+
+        // 阻塞订阅者的队列满时发布方等待消费
+        let publisher = {
+            let bus = bus.clone();
+            thread::spawn(move || bus.publish_all(vec![head(1), head(2), head(3)]))
+        };
+        let received: Vec<ChainEvent> = (0..3).filter_map(|_| blocking.recv()).collect();
+        publisher.join().unwrap();
+        assert_eq!(received, vec![head(1), head(2), head(3)]);
+
+        assert_eq!(lossy.drain(), vec![head(1)]);
+        assert_eq!(lossy.missed(), 2);
+    }
+
+    #[test]
+    fn test_blocked_publisher_does_not_hold_lock() {
+        let bus = Arc::new(EventBus::new());
+        let blocking = bus.subscribe(1);
+        bus.publish(head(1));
+
+        // 发布方阻塞在已满的队列上时，其他线程仍然可以订阅
+        let publisher = {
+            let bus = bus.clone();
+            thread::spawn(move || bus.publish(head(2)))
+        };
+        let late = bus.subscribe_with(1, Overflow::Drop);
+        assert_eq!(bus.subscriber_count(), 2);
+
+        assert_eq!(blocking.recv(), Some(head(1)));
+        assert_eq!(blocking.recv(), Some(head(2)));
+        publisher.join().unwrap();
+        assert!(late.drain().len() <= 1);
+    }
+}
@@ -0,0 +1,366 @@
+//! 区块树与分叉选择
+//!
+//! 所有已知且通过校验的区块都挂在区块树上，同一高度可以有多个区块（分叉）。
+//! 主链的 head 由可插拔的分叉选择规则决定：
+//! - [`LongestChain`]：高度最高的链
+//! - [`HeaviestChain`]：累计难度最大的链
+//! - [`FinalityAware`]：不允许离开已最终确认的区块，再交给内层规则比较
+
+use crate::error::ChainError;
+use latte_primitives::hash::Hash256;
+use latte_types::header::BlockHeader;
+use std::collections::HashMap;
+
+/// 区块树上的一个节点，只保存分叉选择需要的信息，区块本身另行存放
+#[derive(Clone, Debug)]
+pub struct TreeNode {
+    pub hash: Hash256,
+    pub parent: Hash256,
+    pub number: u64,
+    // 从创世区块到本区块（含）的累计难度
+    pub total_difficulty: u128,
+    pub children: Vec<Hash256>,
+}
+
+pub struct BlockTree {
+    nodes: HashMap<Hash256, TreeNode>,
+    root: Hash256,
+    // 已最终确认的区块，分叉选择不能越过它
+    finalized: Option<Hash256>,
+}
+
+impl BlockTree {
+    /// 以创世区块为根创建区块树
+    pub fn new(root_hash: Hash256, root: &BlockHeader) -> Self {
+        let node = TreeNode {
+            hash: root_hash,
+            parent: root.parent_hash,
+            number: root.number,
+            total_difficulty: root.difficulty as u128,
+            children: Vec::new(),
+        };
+        let mut nodes = HashMap::new();
+        nodes.insert(root_hash, node);
+        Self {
+            nodes,
+            root: root_hash,
+            finalized: None,
+        }
+    }
+
+    pub fn root(&self) -> Hash256 {
+        self.root
+    }
+
+    pub fn get(&self, hash: &Hash256) -> Option<&TreeNode> {
+        self.nodes.get(hash)
+    }
+
+    pub fn contains(&self, hash: &Hash256) -> bool {
+        self.nodes.contains_key(hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn finalized(&self) -> Option<Hash256> {
+        self.finalized
+    }
+
+    pub fn set_finalized(&mut self, hash: Hash256) -> Result<(), ChainError> {
+        if !self.contains(&hash) {
+            return Err(ChainError::BlockNotFound);
+        }
+        self.finalized = Some(hash);
+        Ok(())
+    }
+
+    /// 把区块挂到父区块下面，返回新节点
+    pub fn insert(&mut self, hash: Hash256, header: &BlockHeader) -> Result<&TreeNode, ChainError> {
+        let parent = self
+            .nodes
+            .get_mut(&header.parent_hash)
+            .ok_or(ChainError::InvalidParent)?;
+        let total_difficulty = parent.total_difficulty + header.difficulty as u128;
+        if !parent.children.contains(&hash) {
+            parent.children.push(hash);
+        }
+
+        let node = self.nodes.entry(hash).or_insert(TreeNode {
+            hash,
+            parent: header.parent_hash,
+            number: header.number,
+            total_difficulty,
+            children: Vec::new(),
+        });
+        Ok(node)
+    }
+
+    /// 删除一个区块及其所有后代，返回被删除的区块 hash
+    pub fn remove_subtree(&mut self, hash: &Hash256) -> Vec<Hash256> {
+        let Some(node) = self.nodes.get(hash) else {
+            return Vec::new();
+        };
+        let parent = node.parent;
+        if let Some(parent) = self.nodes.get_mut(&parent) {
+            parent.children.retain(|child| child != hash);
+        }
+
+        let mut removed = Vec::new();
+        let mut pending = vec![*hash];
+        while let Some(current) = pending.pop() {
+            if let Some(node) = self.nodes.remove(&current) {
+                pending.extend(node.children);
+                removed.push(current);
+            }
+        }
+        removed
+    }
+
+    /// 与 finalized 冲突的分叉中的所有区块，即从它的严格祖先分出、不包含它的子树
+    ///
+    /// 只向上检查到当前已确认的区块为止：更早的冲突分叉在确认该区块时已经删除，
+    /// 因此每次确认只需要遍历两次确认之间的区块
+    pub fn conflicting(&self, finalized: &Hash256) -> Vec<Hash256> {
+        let mut conflicting = Vec::new();
+        let mut keep = *finalized;
+        while let Some(node) = self.nodes.get(&keep) {
+            if keep == self.root {
+                break;
+            }
+            if let Some(parent) = self.nodes.get(&node.parent) {
+                for child in parent.children.iter().filter(|child| **child != keep) {
+                    self.collect_subtree(child, &mut conflicting);
+                }
+            }
+            keep = node.parent;
+            if self.finalized == Some(keep) {
+                break;
+            }
+        }
+        conflicting
+    }
+
+    /// 删除与 finalized 冲突的分叉，返回被删除的区块 hash，范围见 [`BlockTree::conflicting`]
+    ///
+    /// finalized 的祖先和后代都保留
+    pub fn prune_conflicting(&mut self, finalized: &Hash256) -> Vec<Hash256> {
+        let pruned = self.conflicting(finalized);
+        for hash in &pruned {
+            self.remove_subtree(hash);
+        }
+        pruned
+    }
+
+    fn collect_subtree(&self, hash: &Hash256, out: &mut Vec<Hash256>) {
+        let mut pending = vec![*hash];
+        while let Some(current) = pending.pop() {
+            if let Some(node) = self.nodes.get(&current) {
+                pending.extend(node.children.iter().copied());
+                out.push(current);
+            }
+        }
+    }
+
+    /// 没有子区块的节点，即每个分叉的末端
+    pub fn tips(&self) -> Vec<&TreeNode> {
+        self.nodes
+            .values()
+            .filter(|node| node.children.is_empty())
+            .collect()
+    }
+
+    /// ancestor 是否是 descendant 的祖先（包括自身）
+    pub fn is_ancestor(&self, ancestor: &Hash256, descendant: &Hash256) -> bool {
+        let Some(target) = self.nodes.get(ancestor) else {
+            return false;
+        };
+        let mut current = self.nodes.get(descendant);
+        while let Some(node) = current {
+            if node.number < target.number {
+                return false;
+            }
+            if node.hash == *ancestor {
+                return true;
+            }
+            current = self.nodes.get(&node.parent);
+        }
+        false
+    }
+
+    /// 两个区块的最近公共祖先
+    pub fn common_ancestor(&self, a: &Hash256, b: &Hash256) -> Result<Hash256, ChainError> {
+        let mut a = self.nodes.get(a).ok_or(ChainError::BlockNotFound)?;
+        let mut b = self.nodes.get(b).ok_or(ChainError::BlockNotFound)?;
+        while a.hash != b.hash {
+            // 总是让更高的一方后退，高度相同时同时后退
+            let (step_a, step_b) = (a.number >= b.number, b.number >= a.number);
+            if step_a {
+                a = self.nodes.get(&a.parent).ok_or(ChainError::BlockNotFound)?;
+            }
+            if step_b {
+                b = self.nodes.get(&b.parent).ok_or(ChainError::BlockNotFound)?;
+            }
+        }
+        Ok(a.hash)
+    }
+
+    /// 从 ancestor（不含）到 descendant（含）的路径，按高度升序
+    pub fn path(&self, ancestor: &Hash256, descendant: &Hash256) -> Result<Vec<Hash256>, ChainError> {
+        let mut path = Vec::new();
+        let mut current = *descendant;
+        while current != *ancestor {
+            let node = self.nodes.get(&current).ok_or(ChainError::BlockNotFound)?;
+            path.push(current);
+            current = node.parent;
+        }
+        path.reverse();
+        Ok(path)
+    }
+}
+
+/// 分叉选择规则
+pub trait ForkChoice: Send + Sync {
+    /// candidate 是否应该取代 current 成为新的 head
+    ///
+    /// 条件相同时应返回 false，保留先收到的区块，避免 head 来回切换
+    fn is_better(&self, tree: &BlockTree, candidate: &TreeNode, current: &TreeNode) -> bool;
+}
+
+/// 最长链规则
+pub struct LongestChain;
+
+impl ForkChoice for LongestChain {
+    fn is_better(&self, _tree: &BlockTree, candidate: &TreeNode, current: &TreeNode) -> bool {
+        candidate.number > current.number
+    }
+}
+
+/// 最重链规则：累计难度最大
+pub struct HeaviestChain;
+
+impl ForkChoice for HeaviestChain {
+    fn is_better(&self, _tree: &BlockTree, candidate: &TreeNode, current: &TreeNode) -> bool {
+        candidate.total_difficulty > current.total_difficulty
+    }
+}
+
+/// 感知最终确认的规则：不包含最终确认区块的分叉一律不选，其余交给内层规则
+pub struct FinalityAware<F: ForkChoice> {
+    inner: F,
+}
+
+impl<F: ForkChoice> FinalityAware<F> {
+    pub fn new(inner: F) -> Self {
+        Self { inner }
+    }
+}
+
+impl<F: ForkChoice> ForkChoice for FinalityAware<F> {
+    fn is_better(&self, tree: &BlockTree, candidate: &TreeNode, current: &TreeNode) -> bool {
+        if let Some(finalized) = tree.finalized()
+            && !tree.is_ancestor(&finalized, &candidate.hash)
+        {
+            return false;
+        }
+        self.inner.is_better(tree, candidate, current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use latte_primitives::address::Address;
+
+    fn header(parent: Hash256, number: u64, difficulty: u64) -> BlockHeader {
+        BlockHeader {
+            parent_hash: parent,
+            state_root: Hash256([0; 32]),
+            tx_root: Hash256([0; 32]),
+            receipt_root: Hash256([0; 32]),
+            number,
+            timestamp: 0,
+            gas_used: 0,
+            beneficiary: Address([0; 20]),
+            difficulty,
+            nonce: 0,
+            extra_data: Vec::new(),
+        }
+    }
+
+    fn h(n: u8) -> Hash256 {
+        Hash256([n; 32])
+    }
+
+    /// 0 - 1 - 2 - 3
+    ///      \
+    ///       4 - 5   （难度更高）
+    fn sample_tree() -> BlockTree {
+        let mut tree = BlockTree::new(h(0), &header(Hash256([0xff; 32]), 0, 1));
+        tree.insert(h(1), &header(h(0), 1, 1)).unwrap();
+        tree.insert(h(2), &header(h(1), 2, 1)).unwrap();
+        tree.insert(h(3), &header(h(2), 3, 1)).unwrap();
+        tree.insert(h(4), &header(h(1), 2, 5)).unwrap();
+        tree.insert(h(5), &header(h(4), 3, 5)).unwrap();
+        tree
+    }
+
+    #[test]
+    fn test_ancestor_and_path() {
+        let tree = sample_tree();
+        assert_eq!(tree.common_ancestor(&h(3), &h(5)).unwrap(), h(1));
+        assert_eq!(tree.common_ancestor(&h(2), &h(3)).unwrap(), h(2));
+        assert_eq!(tree.path(&h(1), &h(5)).unwrap(), vec![h(4), h(5)]);
+        assert!(tree.is_ancestor(&h(1), &h(5)));
+        assert!(!tree.is_ancestor(&h(2), &h(5)));
+        assert_eq!(tree.tips().len(), 2);
+    }
+
+    #[test]
+    fn test_fork_choice_rules() {
+        let mut tree = sample_tree();
+        let (a, b) = (tree.get(&h(3)).unwrap().clone(), tree.get(&h(5)).unwrap().clone());
+
+        // 同高度时最长链保留当前 head，最重链选择累计难度更高的分叉
+        assert!(!LongestChain.is_better(&tree, &b, &a));
+        assert!(HeaviestChain.is_better(&tree, &b, &a));
+
+        tree.set_finalized(h(2)).unwrap();
+        assert!(!FinalityAware::new(HeaviestChain).is_better(&tree, &b, &a));
+    }
+
+    #[test]
+    fn test_prune_conflicting() {
+        let mut tree = sample_tree();
+        tree.set_finalized(h(2)).unwrap();
+        let mut pruned = tree.prune_conflicting(&h(2));
+        pruned.sort_by_key(|hash| hash.0);
+        assert_eq!(pruned, vec![h(4), h(5)]);
+        assert!(tree.contains(&h(3)));
+        assert_eq!(tree.get(&h(1)).unwrap().children, vec![h(2)]);
+    }
+
+    #[test]
+    fn test_conflicting_stops_at_finalized() {
+        let mut tree = sample_tree();
+        assert_eq!(tree.conflicting(&h(3)).len(), 2);
+
+        // 从已确认的 2 向后推进时不再回溯到 2 之前
+        tree.set_finalized(h(2)).unwrap();
+        assert!(tree.conflicting(&h(3)).is_empty());
+    }
+
+    #[test]
+    fn test_remove_subtree() {
+        let mut tree = sample_tree();
+        let mut removed = tree.remove_subtree(&h(4));
+        removed.sort_by_key(|hash| hash.0);
+        assert_eq!(removed, vec![h(4), h(5)]);
+        assert_eq!(tree.get(&h(1)).unwrap().children, vec![h(2)]);
+    }
+}
@@ -1,3 +1,434 @@
-// •	初始账户是谁？
-// •	初始余额是多少？
-// •	genesis block 长什么样？
+//! 创世配置
+//!
+//! 回答三个问题：
+//! - 初始账户是谁？初始余额是多少？—— `alloc`
+//! - 谁来出块？—— `validators` 与 `consensus`
+//! - genesis block 长什么样？—— [`GenesisSpec::build`]
+//! - 共识规则何时升级？—— `spec`，见 [`ChainSpec`]
+//!
+//! 配置文件支持 JSON 和 TOML，地址和字节数据使用 hex 字符串（可带 `0x` 前缀），
+//! 余额既可以写成整数，也可以写成十进制字符串（TOML 的整数只有 64 位）：
+//!
+//! ```json
+//! {
+//!   "chain_id": 1,
+//!   "timestamp": 1700000000,
+//!   "alloc": [{ "address": "0x0101...01", "balance": "1000000" }],
+//!   "validators": ["0x0202...02"],
+//!   "consensus": {
+//!     "engine": "proof_of_work", "block_time": 10, "initial_difficulty": 1,
+//!     "issuance": { "type": "fixed", "reward": "50" }
+//!   },
+//!   "spec": { "upgrades": [{ "name": "mod-opcode", "height": 1000, "instruction_set": "v2" }] }
+//! }
+//! ```
+
+use crate::builder::BlockBuilder;
+use crate::error::ChainError;
+use crate::issuance::IssuanceSchedule;
+use crate::spec::ChainSpec;
+use latte_codec::codec::Codec;
+use latte_primitives::address::Address;
+use latte_primitives::hash::{Hash256, sha256};
+use latte_state::state::WorldState;
+use latte_types::block::Block;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GenesisSpec {
+    pub chain_id: u64,
+    pub timestamp: u64,
+    #[serde(default)]
+    pub alloc: Vec<GenesisAccount>,
+    #[serde(default, with = "serde_hex::addresses")]
+    pub validators: Vec<Address>,
+    pub consensus: ConsensusParams,
+    // 按高度激活的协议升级
+    #[serde(default)]
+    pub spec: ChainSpec,
+}
+
+/// 创世时的初始账户
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GenesisAccount {
+    #[serde(with = "serde_hex::address")]
+    pub address: Address,
+    #[serde(with = "serde_hex::balance")]
+    pub balance: u128,
+    #[serde(default)]
+    pub nonce: u64,
+    // 预置的合约代码
+    #[serde(default, with = "serde_hex::bytes")]
+    pub code: Vec<u8>,
+    // 预置的合约存储
+    #[serde(default, with = "serde_hex::storage")]
+    pub storage: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConsensusEngine {
+    ProofOfWork,
+    ProofOfAuthority,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConsensusParams {
+    pub engine: ConsensusEngine,
+    // 目标出块间隔，单位秒
+    pub block_time: u64,
+    #[serde(default = "default_difficulty")]
+    pub initial_difficulty: u64,
+    // 区块奖励的发行计划，默认不发行
+    #[serde(default)]
+    pub issuance: IssuanceSchedule,
+}
+
+fn default_difficulty() -> u64 {
+    1
+}
+
+/// 由创世配置生成的创世区块及其对应的状态
+pub struct Genesis {
+    pub block: Block,
+    pub state: WorldState,
+    // 出块和校验区块时使用的共识参数
+    pub consensus: ConsensusParams,
+    pub spec: ChainSpec,
+}
+
+impl GenesisSpec {
+    pub fn from_json(s: &str) -> Result<Self, ChainError> {
+        serde_json::from_str(s).map_err(|e| ChainError::InvalidGenesis(e.to_string()))
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self, ChainError> {
+        toml::from_str(s).map_err(|e| ChainError::InvalidGenesis(e.to_string()))
+    }
+
+    /// 从文件加载，按扩展名区分格式：`.toml` 为 TOML，其余按 JSON 解析
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ChainError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| ChainError::InvalidGenesis(format!("{}: {}", path.display(), e)))?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::from_toml(&content),
+            _ => Self::from_json(&content),
+        }
+    }
+
+    /// 创世状态：按 alloc 写入余额、nonce、代码和存储
+    pub fn build_state(&self) -> Result<WorldState, ChainError> {
+        let mut seen = HashSet::new();
+        let mut state = WorldState::default();
+        for entry in &self.alloc {
+            if !seen.insert(entry.address) {
+                return Err(ChainError::InvalidGenesis(format!(
+                    "duplicate alloc address 0x{}",
+                    hex::encode(entry.address.0)
+                )));
+            }
+
+            let account = state.get_or_create_account_mut(&entry.address);
+            account.balance = entry.balance;
+            account.nonce = entry.nonce;
+
+            if !entry.code.is_empty() {
+                state.set_code(&entry.address, entry.code.clone());
+            }
+            for (key, value) in &entry.storage {
+                state.set_storage(&entry.address, key.clone(), value.clone());
+            }
+        }
+        if state.total_balance().is_none() {
+            return Err(ChainError::InvalidGenesis(
+                "total alloc balance overflows u128".into(),
+            ));
+        }
+        state.commit_storage_roots();
+        Ok(state)
+    }
+
+    /// 构造创世区块与创世状态
+    ///
+    /// 创世区块高度为 0，没有交易，state_root 为创世状态的根，
+    /// extra_data 承诺了不在状态中的参数（链 id、验证者、共识参数），
+    /// 因此任何一项配置不同都会得到不同的创世 hash。
+    /// 协议升级计划不参与：上线后追加的升级不改变链的身份
+    pub fn build(&self) -> Result<Genesis, ChainError> {
+        self.spec.validate()?;
+        let state = self.build_state()?;
+        let block = BlockBuilder::genesis()
+            .timestamp(self.timestamp)
+            .state_root(state.state_root())
+            .difficulty(self.consensus.initial_difficulty)
+            .extra_data(self.params_hash().0.to_vec())
+            .build();
+        Ok(Genesis {
+            block,
+            state,
+            consensus: self.consensus.clone(),
+            spec: ChainSpec {
+                chain_id: self.chain_id,
+                ..self.spec.clone()
+            },
+        })
+    }
+
+    /// 创世区块 hash，作为网络身份：只有创世 hash 相同的节点才属于同一条链
+    pub fn genesis_hash<C: Codec>(&self, codec: &C) -> Result<Hash256, ChainError> {
+        self.build()?
+            .block
+            .hash_with(codec)
+            .map_err(ChainError::InvalidBlock)
+    }
+
+    /// 链 id、验证者与共识参数的摘要
+    fn params_hash(&self) -> Hash256 {
+        let mut out = Vec::new();
+        out.extend(self.chain_id.to_be_bytes());
+
+        out.extend((self.validators.len() as u32).to_be_bytes());
+        for validator in &self.validators {
+            out.extend(validator.0);
+        }
+
+        let engine: u8 = match self.consensus.engine {
+            ConsensusEngine::ProofOfWork => 0,
+            ConsensusEngine::ProofOfAuthority => 1,
+        };
+        out.push(engine);
+        out.extend(self.consensus.block_time.to_be_bytes());
+        out.extend(self.consensus.initial_difficulty.to_be_bytes());
+        out.extend(self.consensus.issuance.canonical_bytes());
+        sha256(&out)
+    }
+}
+
+/// 配置文件中的 hex 编码
+pub(crate) mod serde_hex {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    fn decode(s: &str) -> Result<Vec<u8>, String> {
+        hex::decode(s.strip_prefix("0x").unwrap_or(s)).map_err(|e| e.to_string())
+    }
+
+    fn encode(bytes: &[u8]) -> String {
+        format!("0x{}", hex::encode(bytes))
+    }
+
+    pub mod bytes {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(v: &[u8], s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_str(&encode(v))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+            decode(&String::deserialize(d)?).map_err(D::Error::custom)
+        }
+    }
+
+    pub mod address {
+        use super::*;
+        use latte_primitives::address::Address;
+
+        pub fn serialize<S: Serializer>(v: &Address, s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_str(&encode(&v.0))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Address, D::Error> {
+            parse(&String::deserialize(d)?).map_err(D::Error::custom)
+        }
+
+        pub(super) fn parse(s: &str) -> Result<Address, String> {
+            let bytes = decode(s)?;
+            let raw: [u8; 20] = bytes
+                .try_into()
+                .map_err(|_| format!("address must be 20 bytes: {}", s))?;
+            Ok(Address(raw))
+        }
+    }
+
+    pub mod addresses {
+        use super::*;
+        use latte_primitives::address::Address;
+        use serde::ser::SerializeSeq;
+
+        pub fn serialize<S: Serializer>(v: &[Address], s: S) -> Result<S::Ok, S::Error> {
+            let mut seq = s.serialize_seq(Some(v.len()))?;
+            for addr in v {
+                seq.serialize_element(&encode(&addr.0))?;
+            }
+            seq.end()
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<Address>, D::Error> {
+            Vec::<String>::deserialize(d)?
+                .iter()
+                .map(|s| super::address::parse(s).map_err(D::Error::custom))
+                .collect()
+        }
+    }
+
+    pub mod storage {
+        use super::*;
+        use std::collections::BTreeMap;
+
+        pub fn serialize<S: Serializer>(
+            v: &BTreeMap<Vec<u8>, Vec<u8>>,
+            s: S,
+        ) -> Result<S::Ok, S::Error> {
+            s.collect_map(v.iter().map(|(k, v)| (encode(k), encode(v))))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            d: D,
+        ) -> Result<BTreeMap<Vec<u8>, Vec<u8>>, D::Error> {
+            BTreeMap::<String, String>::deserialize(d)?
+                .iter()
+                .map(|(k, v)| Ok((decode(k)?, decode(v)?)))
+                .collect::<Result<_, String>>()
+                .map_err(D::Error::custom)
+        }
+    }
+
+    /// 余额可以是整数或十进制字符串
+    pub mod balance {
+        use super::*;
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Balance {
+            Number(u64),
+            Text(String),
+        }
+
+        pub fn serialize<S: Serializer>(v: &u128, s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_str(&v.to_string())
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<u128, D::Error> {
+            match Balance::deserialize(d)? {
+                Balance::Number(n) => Ok(n as u128),
+                Balance::Text(s) => s.parse().map_err(D::Error::custom),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestCodec;
+
+    const SPEC_JSON: &str = r#"{
+        "chain_id": 7,
+        "timestamp": 1700000000,
+        "alloc": [
+            { "address": "0x0101010101010101010101010101010101010101", "balance": "340282366920938463463374607431768210955" },
+            { "address": "0202020202020202020202020202020202020202", "balance": 500, "code": "0x0c0e", "storage": { "0x01": "0x02" } }
+        ],
+        "validators": ["0x0303030303030303030303030303030303030303"],
+        "consensus": { "engine": "proof_of_work", "block_time": 10 }
+    }"#;
+
+    const SPEC_TOML: &str = r#"
+        chain_id = 7
+        timestamp = 1700000000
+        validators = ["0x0303030303030303030303030303030303030303"]
+
+        [[alloc]]
+        address = "0x0101010101010101010101010101010101010101"
+        balance = "340282366920938463463374607431768210955"
+
+        [[alloc]]
+        address = "0202020202020202020202020202020202020202"
+        balance = 500
+        code = "0x0c0e"
+        storage = { "0x01" = "0x02" }
+
+        [consensus]
+        engine = "proof_of_work"
+        block_time = 10
+    "#;
+
+    #[test]
+    fn test_genesis_block_commits_to_state() {
+        let spec = GenesisSpec::from_json(SPEC_JSON).unwrap();
+        let genesis = spec.build().unwrap();
+
+        assert_eq!(genesis.block.header.number, 0);
+        assert_eq!(genesis.block.header.timestamp, 1700000000);
+        assert_eq!(genesis.block.header.state_root, genesis.state.state_root());
+
+        let rich = genesis.state.get_account(&Address([1; 20])).unwrap();
+        assert_eq!(rich.balance, u128::MAX - 500);
+        let contract = genesis.state.get_account(&Address([2; 20])).unwrap();
+        assert!(contract.is_contract());
+        assert_eq!(
+            genesis.state.get_storage(&Address([2; 20]), &[1]),
+            Some(&[2u8][..])
+        );
+    }
+
+    #[test]
+    fn test_json_and_toml_produce_same_genesis() {
+        let from_json = GenesisSpec::from_json(SPEC_JSON).unwrap().build().unwrap();
+        let from_toml = GenesisSpec::from_toml(SPEC_TOML).unwrap().build().unwrap();
+
+        assert_eq!(
+            from_json.block.header.state_root,
+            from_toml.block.header.state_root
+        );
+        assert_eq!(
+            from_json.block.header.extra_data,
+            from_toml.block.header.extra_data
+        );
+    }
+
+    #[test]
+    fn test_chain_id_changes_genesis() {
+        let spec = GenesisSpec::from_json(SPEC_JSON).unwrap();
+        let mut other = spec.clone();
+        other.chain_id += 1;
+
+        assert_ne!(
+            spec.build().unwrap().block.header.extra_data,
+            other.build().unwrap().block.header.extra_data
+        );
+        // 交易执行规则要求签名中的链 id 与创世配置一致
+        assert_eq!(other.build().unwrap().spec.rules_at(0).chain_id, 8);
+    }
+
+    #[test]
+    fn test_duplicate_alloc_rejected() {
+        let mut spec = GenesisSpec::from_json(SPEC_JSON).unwrap();
+        spec.alloc.push(spec.alloc[0].clone());
+        assert!(matches!(spec.build(), Err(ChainError::InvalidGenesis(_))));
+    }
+
+    #[test]
+    fn test_alloc_overflowing_supply_rejected() {
+        let mut spec = GenesisSpec::from_json(SPEC_JSON).unwrap();
+        spec.alloc[1].balance = 501;
+        assert!(matches!(spec.build(), Err(ChainError::InvalidGenesis(_))));
+    }
+
+    #[test]
+    fn test_upgrades_do_not_change_genesis() {
+        let spec = GenesisSpec::from_json(SPEC_JSON).unwrap();
+        let mut upgraded = spec.clone();
+        upgraded.spec = serde_json::from_str(
+            r#"{ "upgrades": [{ "name": "mod-opcode", "height": 1000, "instruction_set": "v2" }] }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            spec.genesis_hash(&TestCodec).unwrap(),
+            upgraded.genesis_hash(&TestCodec).unwrap()
+        );
+    }
+}
@@ -0,0 +1,240 @@
+//! 发行计划
+//!
+//! 每个区块给出块者铸造的奖励由高度决定，写在创世配置的共识参数中：
+//!
+//! - `fixed`：每个区块奖励固定
+//! - `halving`：每 `interval` 个区块奖励减半
+//! - `decaying`：每 `interval` 个区块奖励乘以 `numerator / denominator`，比例不小于 1 时奖励保持不变；
+//!   第 k 个周期的奖励为 `initial * (numerator / denominator)^k`，比例的 k 次方用 64 位定点数快速幂计算，
+//!   每一步向上取整，结果向下取整，所有节点得到相同的值
+//!
+//! ```json
+//! { "type": "halving", "initial": "5000000000", "interval": 210000 }
+//! ```
+//!
+//! 创世区块（高度 0）没有奖励。手续费不属于发行，由付费方转给出块者，不改变总供应量
+
+use crate::genesis::serde_hex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum IssuanceSchedule {
+    Fixed {
+        #[serde(with = "serde_hex::balance")]
+        reward: u128,
+    },
+    Halving {
+        #[serde(with = "serde_hex::balance")]
+        initial: u128,
+        interval: u64,
+    },
+    Decaying {
+        #[serde(with = "serde_hex::balance")]
+        initial: u128,
+        interval: u64,
+        numerator: u64,
+        denominator: u64,
+    },
+}
+
+/// 默认不发行
+impl Default for IssuanceSchedule {
+    fn default() -> Self {
+        IssuanceSchedule::Fixed { reward: 0 }
+    }
+}
+
+impl IssuanceSchedule {
+    /// 高度为 number 的区块的奖励
+    pub fn reward_at(&self, number: u64) -> u128 {
+        if number == 0 {
+            return 0;
+        }
+        match *self {
+            IssuanceSchedule::Fixed { reward } => reward,
+            IssuanceSchedule::Halving { initial, interval } => {
+                let halvings = periods(number, interval);
+                if halvings >= 128 {
+                    0
+                } else {
+                    initial >> halvings
+                }
+            }
+            IssuanceSchedule::Decaying {
+                initial,
+                interval,
+                numerator,
+                denominator,
+            } => {
+                if denominator == 0 {
+                    return 0;
+                }
+                // 比例不小于 1 时奖励不衰减
+                if numerator >= denominator {
+                    return initial;
+                }
+                let ratio = ((numerator as u128) << FRACTION_BITS).div_ceil(denominator as u128);
+                mul_fraction(initial, pow_fraction(ratio, periods(number, interval)))
+            }
+        }
+    }
+
+    /// 参数的规范编码，参与创世参数摘要
+    pub(crate) fn canonical_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        match *self {
+            IssuanceSchedule::Fixed { reward } => {
+                out.push(0);
+                out.extend(reward.to_be_bytes());
+            }
+            IssuanceSchedule::Halving { initial, interval } => {
+                out.push(1);
+                out.extend(initial.to_be_bytes());
+                out.extend(interval.to_be_bytes());
+            }
+            IssuanceSchedule::Decaying {
+                initial,
+                interval,
+                numerator,
+                denominator,
+            } => {
+                out.push(2);
+                out.extend(initial.to_be_bytes());
+                out.extend(interval.to_be_bytes());
+                out.extend(numerator.to_be_bytes());
+                out.extend(denominator.to_be_bytes());
+            }
+        }
+        out
+    }
+}
+
+/// 定点数的小数位数，比例小于 1 时定点表示小于 2^64
+const FRACTION_BITS: u32 = 64;
+
+/// 两个小于 1 的定点数相乘，向上取整
+fn mul_ceil(a: u128, b: u128) -> u128 {
+    (a * b).div_ceil(1 << FRACTION_BITS)
+}
+
+/// 小于 1 的定点数的 exponent 次方，快速幂，O(log exponent)
+fn pow_fraction(mut base: u128, mut exponent: u64) -> u128 {
+    let mut result = 1 << FRACTION_BITS;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul_ceil(result, base);
+        }
+        base = mul_ceil(base, base);
+        exponent >>= 1;
+    }
+    result
+}
+
+/// value * fraction 向下取整；value 拆成高低 64 位分别相乘，不会溢出
+fn mul_fraction(value: u128, fraction: u128) -> u128 {
+    if fraction == 1 << FRACTION_BITS {
+        return value;
+    }
+    let (high, low) = (value >> FRACTION_BITS, value & u64::MAX as u128);
+    high * fraction + ((low * fraction) >> FRACTION_BITS)
+}
+
+/// 高度 number 所在的周期，周期从高度 1 开始计算
+fn periods(number: u64, interval: u64) -> u64 {
+    if interval == 0 {
+        return 0;
+    }
+    (number - 1) / interval
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reward_schedules() {
+        let fixed = IssuanceSchedule::Fixed { reward: 50 };
+        assert_eq!(fixed.reward_at(0), 0);
+        assert_eq!(fixed.reward_at(1_000_000), 50);
+
+        let halving = IssuanceSchedule::Halving {
+            initial: 100,
+            interval: 10,
+        };
+        assert_eq!(halving.reward_at(10), 100);
+        assert_eq!(halving.reward_at(11), 50);
+        assert_eq!(halving.reward_at(21), 25);
+        assert_eq!(halving.reward_at(10 * 200), 0);
+
+        let decaying = IssuanceSchedule::Decaying {
+            initial: 1000,
+            interval: 5,
+            numerator: 9,
+            denominator: 10,
+        };
+        assert_eq!(decaying.reward_at(5), 1000);
+        assert_eq!(decaying.reward_at(6), 900);
+        assert_eq!(decaying.reward_at(11), 810);
+    }
+
+    #[test]
+    fn test_decaying_reward_does_not_overflow() {
+        let decaying = IssuanceSchedule::Decaying {
+            initial: u128::MAX,
+            interval: 1,
+            numerator: u64::MAX - 1,
+            denominator: u64::MAX,
+        };
+        let reward = decaying.reward_at(2);
+        assert!(reward < u128::MAX);
+        assert!(reward > u128::MAX / 2);
+
+        // 比例为 1 时直接返回初始奖励，不按周期数循环
+        let flat = IssuanceSchedule::Decaying {
+            initial: 1000,
+            interval: 1,
+            numerator: 7,
+            denominator: 7,
+        };
+        assert_eq!(flat.reward_at(u64::MAX), 1000);
+    }
+
+    #[test]
+    fn test_decaying_reward_is_logarithmic_in_height() {
+        // 比例接近 1 时奖励要经过极多个周期才降到 0，不能按周期逐个计算
+        let slow = IssuanceSchedule::Decaying {
+            initial: 1 << 100,
+            interval: 1,
+            numerator: u64::MAX - 1,
+            denominator: u64::MAX,
+        };
+        let reward = slow.reward_at(u64::MAX);
+        assert!(reward > 0 && reward < 1 << 100);
+        assert!(slow.reward_at(1 << 40) >= reward);
+
+        let decaying = IssuanceSchedule::Decaying {
+            initial: 1 << 20,
+            interval: 1,
+            numerator: 1,
+            denominator: 2,
+        };
+        assert_eq!(decaying.reward_at(11), 1 << 10);
+        assert_eq!(decaying.reward_at(200), 0);
+    }
+
+    #[test]
+    fn test_parse_schedule() {
+        let schedule: IssuanceSchedule = serde_json::from_str(
+            r#"{ "type": "halving", "initial": "5000000000", "interval": 210000 }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            schedule,
+            IssuanceSchedule::Halving {
+                initial: 5_000_000_000,
+                interval: 210_000
+            }
+        );
+    }
+}
@@ -1,13 +1,31 @@
 use crate::error::ChainError;
 
+pub mod archive;
+pub mod assembler;
+pub mod bad_blocks;
+pub mod block_cache;
 pub mod block_executor;
+pub mod builder;
 pub mod blockchain;
+pub mod clock;
+pub mod config;
+pub mod difficulty;
 pub mod error;
+pub mod events;
+pub mod fork_choice;
 pub mod genesis;
+pub mod issuance;
+pub mod mempool;
 pub mod merkle;
+pub mod orphan;
+pub mod signature;
+pub mod spec;
 pub mod storage;
 pub mod validator;
+pub mod verifier;
 pub mod canonical;
 pub mod storage_error;
+#[cfg(test)]
+mod test_utils;
 
 pub type Result<T> = std::result::Result<T, ChainError>;
@@ -0,0 +1,116 @@
+//! 交易池
+// 保存尚未打包的交易，区块上链后移除其中的交易，链重组时被丢弃分叉上的交易重新放回
+// 交易数量有上限，满了以后新交易的 gas_price 必须高于池中最低的，并挤掉最低的那笔
+
+use crate::canonical::CanonicalEncode;
+use crate::error::ChainError;
+use latte_primitives::hash::Hash256;
+use latte_types::transaction::Transaction;
+use std::collections::HashMap;
+
+pub const DEFAULT_MAX_POOL_TXS: usize = 4096;
+/// 交易规范编码的最大字节数，超过的交易不进入交易池
+pub const MAX_TX_SIZE: usize = 128 * 1024;
+
+pub struct TxPool {
+    txs: HashMap<Hash256, Transaction>,
+    max_txs: usize,
+}
+
+impl Default for TxPool {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_MAX_POOL_TXS)
+    }
+}
+
+impl TxPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(max_txs: usize) -> Self {
+        Self {
+            txs: HashMap::new(),
+            max_txs,
+        }
+    }
+
+    /// 加入交易，返回交易 hash；已存在时不重复加入
+    ///
+    /// 交易池已满时淘汰 gas_price 最低的交易，新交易的 gas_price 不高于它时返回 [`ChainError::PoolFull`]
+    pub fn insert(&mut self, tx: Transaction) -> Result<Hash256, ChainError> {
+        let hash = tx.canonical_hash();
+        if self.txs.contains_key(&hash) {
+            return Ok(hash);
+        }
+        if self.txs.len() >= self.max_txs {
+            let lowest = self
+                .txs
+                .iter()
+                .min_by_key(|(_, pooled)| pooled.gas_price)
+                .map(|(hash, pooled)| (*hash, pooled.gas_price));
+            match lowest {
+                Some((lowest, min_gas_price)) if tx.gas_price > min_gas_price => {
+                    self.txs.remove(&lowest);
+                }
+                Some((_, min_gas_price)) => return Err(ChainError::PoolFull { min_gas_price }),
+                None => return Err(ChainError::PoolFull { min_gas_price: 0 }),
+            }
+        }
+        self.txs.insert(hash, tx);
+        Ok(hash)
+    }
+
+    pub fn remove(&mut self, hash: &Hash256) -> Option<Transaction> {
+        self.txs.remove(hash)
+    }
+
+    pub fn get(&self, hash: &Hash256) -> Option<&Transaction> {
+        self.txs.get(hash)
+    }
+
+    pub fn contains(&self, hash: &Hash256) -> bool {
+        self.txs.contains_key(hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.txs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.txs.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Hash256, &Transaction)> {
+        self.txs.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::Fixture;
+
+    fn priced(fixture: &Fixture, nonce: u64, gas_price: u64) -> Transaction {
+        let mut tx = fixture.transfer(nonce, 1);
+        tx.gas_price = gas_price;
+        tx
+    }
+
+    #[test]
+    fn test_full_pool_evicts_lowest_fee() {
+        let fixture = Fixture::new();
+        let mut pool = TxPool::with_capacity(2);
+        let cheap = pool.insert(priced(&fixture, 0, 1)).unwrap();
+        let mid = pool.insert(priced(&fixture, 1, 2)).unwrap();
+
+        assert!(matches!(
+            pool.insert(priced(&fixture, 2, 1)),
+            Err(ChainError::PoolFull { min_gas_price: 1 })
+        ));
+        let expensive = pool.insert(priced(&fixture, 2, 3)).unwrap();
+        assert_eq!(pool.len(), 2);
+        assert!(!pool.contains(&cheap));
+        assert!(pool.contains(&mid) && pool.contains(&expensive));
+    }
+}
@@ -1,43 +1,168 @@
 use crate::canonical::CanonicalEncode;
 use latte_primitives::hash;
-use latte_primitives::hash::{Hash256, sha256};
+use latte_primitives::hash::Hash256;
+use latte_types::header::BlockHeader;
+use latte_types::receipt::Receipt;
 use latte_types::transaction::Transaction;
 
 ///
 /// 求一系列交易的默克尔树hash值
-/// 未处理交易数为0的情况
 ///
 pub fn tx_root_hash(transactions: &Vec<Transaction>) -> Hash256 {
     let hash_vec: Vec<Hash256> = transactions
         .iter()
-        .map(|transaction| {
-            return sha256(&transaction.canonical_bytes());
-        })
+        .map(|transaction| transaction.canonical_hash())
         .collect();
     root_hash(hash_vec)
 }
 
+/// 求一系列交易回执的默克尔树hash值
+pub fn receipt_root_hash(receipts: &[Receipt]) -> Hash256 {
+    let hash_vec: Vec<Hash256> = receipts
+        .iter()
+        .map(|receipt| receipt.canonical_hash())
+        .collect();
+    root_hash(hash_vec)
+}
+
+// 默克尔树构造规则
+//
+// - 空列表的根为 sha256("")，与任何叶子、内部节点都不会相同
+// - 叶子与内部节点使用不同的前缀（域分离），内部节点无法被伪装成叶子，反之亦然：
+//     leaf = sha256(0x00 || item)
+//     node = sha256(0x01 || left || right)
+// - 某一层节点数为奇数时，最后一个节点原样提升到上一层，不复制，
+//   因此 [a, b, c] 与 [a, b, c, c] 得到不同的根（CVE-2012-2459）
+// - 根提交叶子总数，树的形状随之固定，包含证明中的下标和叶子总数都无法篡改：
+//     root = sha256(0x02 || leaf_count（u64 大端）|| 树顶节点)
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+const COUNT_PREFIX: u8 = 0x02;
+
+/// 空列表（没有交易的区块）的根
+pub fn empty_root() -> Hash256 {
+    hash::sha256(&[])
+}
+
+fn leaf_hash(item: &Hash256) -> Hash256 {
+    hash::sha256(&[&[LEAF_PREFIX][..], &item.0[..]].concat())
+}
+
+fn node_hash(left: &Hash256, right: &Hash256) -> Hash256 {
+    hash::sha256(&[&[NODE_PREFIX][..], &left.0[..], &right.0[..]].concat())
+}
+
+fn count_hash(leaf_count: usize, top: &Hash256) -> Hash256 {
+    let count = (leaf_count as u64).to_be_bytes();
+    hash::sha256(&[&[COUNT_PREFIX][..], &count[..], &top.0[..]].concat())
+}
+
+/// 由下一层节点得到上一层节点，奇数个时最后一个节点原样提升
+fn next_level(level: &[Hash256]) -> Vec<Hash256> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [single] => *single,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
 /// 求默克尔树hash值
-pub fn root_hash(mut hashes: Vec<Hash256>) -> Hash256 {
-    while hashes.len() > 1 {
-        if hashes.len() % 2 != 0 {
-            // 解引用后，如果hash256实现了copy，可以在当前栈得到一份克隆的数据
-            let last = *hashes.last().unwrap();
-            hashes.push(last);
-        }
+pub fn root_hash(hashes: Vec<Hash256>) -> Hash256 {
+    if hashes.is_empty() {
+        return empty_root();
+    }
+
+    let mut level: Vec<Hash256> = hashes.iter().map(leaf_hash).collect();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    count_hash(hashes.len(), &level[0])
+}
+
+/// 默克尔包含证明：从叶子到根路径上每一层的兄弟节点
+///
+/// 被原样提升的层没有兄弟节点，校验时根据叶子总数跳过这些层；
+/// 叶子总数提交在根中，叶子总数确定后下标也只有一种取值能通过校验
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleProof {
+    // 叶子在列表中的下标
+    pub index: usize,
+    // 叶子总数，用于确定每一层的宽度
+    pub leaf_count: usize,
+    // 自底向上的兄弟节点
+    pub siblings: Vec<Hash256>,
+}
 
-        let mut next_level = Vec::new();
+/// 为下标为 index 的叶子生成包含证明，下标越界时返回 None
+pub fn proof(hashes: &[Hash256], index: usize) -> Option<MerkleProof> {
+    if index >= hashes.len() {
+        return None;
+    }
 
-        for index in (0..hashes.len()).step_by(2) {
-            let first = hashes.get(index).unwrap();
-            let second = hashes.get(index + 1).unwrap();
-            let merge_hash = hash::sha256(&[&first.0[..], &second.0[..]].concat());
-            next_level.push(merge_hash);
+    let mut level: Vec<Hash256> = hashes.iter().map(leaf_hash).collect();
+    let mut position = index;
+    let mut siblings = Vec::new();
+    while level.len() > 1 {
+        if let Some(sibling) = level.get(position ^ 1) {
+            siblings.push(*sibling);
         }
+        level = next_level(&level);
+        position /= 2;
+    }
 
-        hashes = next_level;
+    Some(MerkleProof {
+        index,
+        leaf_count: hashes.len(),
+        siblings,
+    })
+}
+
+/// 为区块中第 index 笔交易生成包含证明
+pub fn tx_proof(transactions: &[Transaction], index: usize) -> Option<MerkleProof> {
+    let hash_vec: Vec<Hash256> = transactions
+        .iter()
+        .map(|transaction| transaction.canonical_hash())
+        .collect();
+    proof(&hash_vec, index)
+}
+
+/// 校验 leaf 是否以 proof 给出的位置包含在 root 对应的默克尔树中
+///
+/// 兄弟节点必须恰好用完，多余或缺少都视为无效
+pub fn verify_proof(leaf: Hash256, proof: &MerkleProof, root: Hash256) -> bool {
+    if proof.index >= proof.leaf_count {
+        return false;
     }
-    hashes.pop().unwrap()
+
+    let mut current = leaf_hash(&leaf);
+    let mut position = proof.index;
+    let mut width = proof.leaf_count;
+    let mut siblings = proof.siblings.iter();
+    while width > 1 {
+        // 奇数层的最后一个节点没有兄弟，直接提升
+        if position ^ 1 < width {
+            let Some(sibling) = siblings.next() else {
+                return false;
+            };
+            current = if position.is_multiple_of(2) {
+                node_hash(&current, sibling)
+            } else {
+                node_hash(sibling, &current)
+            };
+        }
+        position /= 2;
+        width = width.div_ceil(2);
+    }
+    siblings.next().is_none() && count_hash(proof.leaf_count, &current) == root
+}
+
+/// 校验交易是否包含在区块头对应的区块中，不需要下载整个区块
+pub fn verify_tx_inclusion(tx: &Transaction, proof: &MerkleProof, header: &BlockHeader) -> bool {
+    verify_proof(tx.canonical_hash(), proof, header.tx_root)
 }
 
 #[cfg(test)]
@@ -51,4 +176,95 @@ mod tests {
         let hash256 = root_hash(hashes);
         println!("{:?}", hash256);
     }
+
+    #[test]
+    fn test_merkle_proof() {
+        for count in 1..=7u8 {
+            let hashes = leaves(count);
+            let root = root_hash(hashes.clone());
+            for (index, leaf) in hashes.iter().enumerate() {
+                let proof = proof(&hashes, index).unwrap();
+                assert!(verify_proof(*leaf, &proof, root));
+
+                // 换一个叶子或者篡改兄弟节点都无法通过校验
+                assert!(!verify_proof(hash::sha256(&[0xff]), &proof, root));
+                if let Some(first) = proof.siblings.first() {
+                    let mut tampered = proof.clone();
+                    tampered.siblings[0] = hash::sha256(&first.0);
+                    assert!(!verify_proof(*leaf, &tampered, root));
+                }
+            }
+            assert!(proof(&hashes, hashes.len()).is_none());
+        }
+    }
+
+    fn leaves(count: u8) -> Vec<Hash256> {
+        (0..count).map(|n| hash::sha256(&[n])).collect()
+    }
+
+    #[test]
+    fn test_proof_position_is_committed() {
+        let hashes = leaves(3);
+        let root = root_hash(hashes.clone());
+        let proof = proof(&hashes, 2).unwrap();
+        assert!(verify_proof(hashes[2], &proof, root));
+
+        // 第 3 个叶子的证明不能当作 2 叶子树中第 2 个叶子的证明
+        let relabelled = MerkleProof {
+            index: 1,
+            leaf_count: 2,
+            ..proof.clone()
+        };
+        assert!(!verify_proof(hashes[2], &relabelled, root));
+        for (index, leaf_count) in [(1, 3), (0, 3), (2, 4), (3, 4)] {
+            let tampered = MerkleProof {
+                index,
+                leaf_count,
+                ..proof.clone()
+            };
+            assert!(!verify_proof(hashes[2], &tampered, root));
+        }
+    }
+
+    #[test]
+    fn test_empty_root() {
+        assert_eq!(root_hash(Vec::new()), empty_root());
+        assert_ne!(root_hash(Vec::new()), Hash256([0; 32]));
+        assert!(proof(&[], 0).is_none());
+    }
+
+    #[test]
+    fn test_duplicate_leaf_mutation() {
+        // [a, b, c] 与 [a, b, c, c] 的根不同
+        let abc = leaves(3);
+        let mut abcc = abc.clone();
+        abcc.push(abc[2]);
+        assert_ne!(root_hash(abc.clone()), root_hash(abcc));
+
+        // 同理 [a, b, c, d, e] 与 [a, b, c, d, e, e]
+        let five = leaves(5);
+        let mut six = five.clone();
+        six.push(five[4]);
+        assert_ne!(root_hash(five), root_hash(six));
+    }
+
+    #[test]
+    fn test_leaf_node_domain_separation() {
+        // 内部节点不能当作叶子：单叶子树的根不等于其内容
+        let ab = leaves(2);
+        let root = root_hash(ab.clone());
+        assert_ne!(root_hash(vec![root]), root);
+
+        // 用 [a, b] 的内部节点伪造 4 叶子树中的一个“叶子”
+        let abcd = leaves(4);
+        let root = root_hash(abcd.clone());
+        let left = node_hash(&leaf_hash(&abcd[0]), &leaf_hash(&abcd[1]));
+        let right = node_hash(&leaf_hash(&abcd[2]), &leaf_hash(&abcd[3]));
+        let forged = MerkleProof {
+            index: 0,
+            leaf_count: 2,
+            siblings: vec![right],
+        };
+        assert!(!verify_proof(left, &forged, root));
+    }
 }
@@ -0,0 +1,215 @@
+//! 孤块池
+//!
+//! 网络上子区块可能先于父区块到达，此时父区块还不在区块树上，无法校验和执行。
+//! 这类区块按缺失的父区块 hash 暂存在孤块池中，父区块接入后再自动导入。
+//!
+//! 孤块池有数量和存活时间上限，避免被伪造的、永远等不到父区块的区块占满内存：
+//! - 超过存活时间的孤块在下一次插入时被清理
+//! - 数量达到上限时淘汰最早收到的孤块
+
+use latte_primitives::hash::Hash256;
+use latte_types::block::Block;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_MAX_ORPHANS: usize = 256;
+pub const DEFAULT_MAX_ORPHAN_AGE: Duration = Duration::from_secs(10 * 60);
+
+struct OrphanBlock {
+    block: Block,
+    received: Instant,
+    // 插入顺序，收到时间相同时也能区分先后
+    sequence: u64,
+}
+
+pub struct OrphanPool {
+    orphans: HashMap<Hash256, OrphanBlock>,
+    // 缺失的父区块 hash -> 等待它的孤块 hash
+    by_parent: HashMap<Hash256, Vec<Hash256>>,
+    max_orphans: usize,
+    max_age: Duration,
+    next_sequence: u64,
+}
+
+impl Default for OrphanPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_ORPHANS, DEFAULT_MAX_ORPHAN_AGE)
+    }
+}
+
+impl OrphanPool {
+    pub fn new(max_orphans: usize, max_age: Duration) -> Self {
+        Self {
+            orphans: HashMap::new(),
+            by_parent: HashMap::new(),
+            max_orphans,
+            max_age,
+            next_sequence: 0,
+        }
+    }
+
+    /// 暂存一个孤块，已存在时不重复加入
+    ///
+    /// 插入前先清理过期孤块，数量仍达到上限时淘汰最早收到的孤块
+    pub fn insert(&mut self, hash: Hash256, block: Block) {
+        if self.orphans.contains_key(&hash) || self.max_orphans == 0 {
+            return;
+        }
+
+        let now = Instant::now();
+        self.prune_expired(now);
+        while self.orphans.len() >= self.max_orphans {
+            let oldest = self
+                .orphans
+                .iter()
+                .min_by_key(|(_, orphan)| orphan.sequence)
+                .map(|(hash, _)| *hash);
+            match oldest {
+                Some(oldest) => {
+                    self.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+
+        self.by_parent
+            .entry(block.header.parent_hash)
+            .or_default()
+            .push(hash);
+        self.orphans.insert(
+            hash,
+            OrphanBlock {
+                block,
+                received: now,
+                sequence: self.next_sequence,
+            },
+        );
+        self.next_sequence += 1;
+    }
+
+    pub fn contains(&self, hash: &Hash256) -> bool {
+        self.orphans.contains_key(hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.orphans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orphans.is_empty()
+    }
+
+    /// 取出所有以 parent 为父区块的孤块，按收到的先后顺序返回
+    pub fn take_children(&mut self, parent: &Hash256) -> Vec<(Hash256, Block)> {
+        let Some(children) = self.by_parent.remove(parent) else {
+            return Vec::new();
+        };
+        let mut children: Vec<(Hash256, OrphanBlock)> = children
+            .into_iter()
+            .filter_map(|hash| self.orphans.remove(&hash).map(|orphan| (hash, orphan)))
+            .collect();
+        children.sort_by_key(|(_, orphan)| orphan.sequence);
+        children
+            .into_iter()
+            .map(|(hash, orphan)| (hash, orphan.block))
+            .collect()
+    }
+
+    /// 删除一个孤块及所有等待它的后代，返回被删除的数量
+    ///
+    /// 父区块被判定为无效时，它的后代也不可能再接入
+    pub fn remove_descendants(&mut self, hash: &Hash256) -> usize {
+        let mut removed = 0;
+        let mut pending = vec![*hash];
+        while let Some(current) = pending.pop() {
+            if self.remove(&current).is_some() {
+                removed += 1;
+            }
+            if let Some(children) = self.by_parent.remove(&current) {
+                pending.extend(children);
+            }
+        }
+        removed
+    }
+
+    /// 清理在 now 时已超过存活时间的孤块，返回被清理的数量
+    pub fn prune_expired(&mut self, now: Instant) -> usize {
+        let expired: Vec<Hash256> = self
+            .orphans
+            .iter()
+            .filter(|(_, orphan)| now.saturating_duration_since(orphan.received) > self.max_age)
+            .map(|(hash, _)| *hash)
+            .collect();
+        for hash in &expired {
+            self.remove(hash);
+        }
+        expired.len()
+    }
+
+    fn remove(&mut self, hash: &Hash256) -> Option<Block> {
+        let orphan = self.orphans.remove(hash)?;
+        let parent = orphan.block.header.parent_hash;
+        if let Some(children) = self.by_parent.get_mut(&parent) {
+            children.retain(|child| child != hash);
+            if children.is_empty() {
+                self.by_parent.remove(&parent);
+            }
+        }
+        Some(orphan.block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::BlockBuilder;
+
+    fn block(parent: u8, number: u64) -> Block {
+        let mut block = BlockBuilder::genesis().timestamp(number).build();
+        block.header.parent_hash = Hash256([parent; 32]);
+        block.header.number = number;
+        block
+    }
+
+    #[test]
+    fn test_take_children() {
+        let mut pool = OrphanPool::default();
+        pool.insert(Hash256([2; 32]), block(1, 2));
+        pool.insert(Hash256([3; 32]), block(2, 3));
+        pool.insert(Hash256([4; 32]), block(1, 2));
+
+        let children = pool.take_children(&Hash256([1; 32]));
+        let hashes: Vec<Hash256> = children.iter().map(|(hash, _)| *hash).collect();
+        assert_eq!(hashes, vec![Hash256([2; 32]), Hash256([4; 32])]);
+        assert_eq!(pool.len(), 1);
+        assert!(pool.take_children(&Hash256([1; 32])).is_empty());
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut pool = OrphanPool::new(2, Duration::from_secs(60));
+        pool.insert(Hash256([2; 32]), block(1, 2));
+        pool.insert(Hash256([3; 32]), block(1, 2));
+        pool.insert(Hash256([4; 32]), block(1, 2));
+
+        // 超过数量上限时淘汰最早收到的孤块
+        assert_eq!(pool.len(), 2);
+        assert!(!pool.contains(&Hash256([2; 32])));
+
+        // 超过存活时间的孤块全部清理
+        let later = Instant::now() + Duration::from_secs(61);
+        assert_eq!(pool.prune_expired(later), 2);
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn test_remove_descendants() {
+        let mut pool = OrphanPool::default();
+        pool.insert(Hash256([2; 32]), block(1, 2));
+        pool.insert(Hash256([3; 32]), block(2, 3));
+        pool.insert(Hash256([5; 32]), block(4, 3));
+
+        assert_eq!(pool.remove_descendants(&Hash256([2; 32])), 2);
+        assert!(pool.contains(&Hash256([5; 32])));
+    }
+}
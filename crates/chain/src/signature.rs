@@ -0,0 +1,193 @@
+//! 交易签名校验
+//!
+//! 区块中每笔交易的签名和发送方绑定（from 必须由签名公钥派生）都要校验。
+//! 签名校验是区块校验中最耗 CPU 的部分，按 CPU 核数分块并行执行。
+//!
+//! 校验通过的交易 hash 记录在 [`VerifiedTxCache`] 中，交易池和区块校验共用同一个缓存，
+//! 进入交易池时已经校验过的交易在区块校验时不再重复校验。
+//! 交易 hash 覆盖签名本身，签名被篡改的交易 hash 不同，不会命中缓存。
+
+use crate::canonical::CanonicalEncode;
+use crate::error::ChainError;
+use latte_primitives::hash::Hash256;
+use latte_types::transaction::Transaction;
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+use std::{panic, thread};
+
+pub const DEFAULT_VERIFIED_CACHE_SIZE: usize = 100_000;
+/// 少于这个数量的交易直接在当前线程校验，避免创建线程的开销
+const PARALLEL_THRESHOLD: usize = 16;
+
+#[derive(Default)]
+struct CacheInner {
+    hashes: HashSet<Hash256>,
+    // 插入顺序，超过容量时淘汰最早的
+    order: VecDeque<Hash256>,
+}
+
+/// 已校验签名的交易 hash，容量有限，超过容量时淘汰最早加入的
+pub struct VerifiedTxCache {
+    inner: Mutex<CacheInner>,
+    capacity: usize,
+}
+
+impl Default for VerifiedTxCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_VERIFIED_CACHE_SIZE)
+    }
+}
+
+impl VerifiedTxCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(CacheInner::default()),
+            capacity,
+        }
+    }
+
+    pub fn contains(&self, hash: &Hash256) -> bool {
+        self.inner
+            .lock()
+            .map(|inner| inner.hashes.contains(hash))
+            .unwrap_or(false)
+    }
+
+    pub fn insert(&self, hash: Hash256) {
+        self.extend([hash]);
+    }
+
+    pub fn extend(&self, hashes: impl IntoIterator<Item = Hash256>) {
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+        for hash in hashes {
+            if self.capacity == 0 || !inner.hashes.insert(hash) {
+                continue;
+            }
+            inner.order.push_back(hash);
+            while inner.order.len() > self.capacity {
+                if let Some(oldest) = inner.order.pop_front() {
+                    inner.hashes.remove(&oldest);
+                }
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().map(|inner| inner.hashes.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// 校验单笔交易的签名，结果记入缓存
+pub fn verify_transaction(tx: &Transaction, cache: &VerifiedTxCache) -> Result<Hash256, ChainError> {
+    let hash = tx.canonical_hash();
+    if !cache.contains(&hash) {
+        tx.verify_signatures()
+            .map_err(|e| ChainError::InvalidTransaction(e.to_string()))?;
+        cache.insert(hash);
+    }
+    Ok(hash)
+}
+
+/// 并行校验一组交易的签名
+///
+/// 缓存中已有的交易跳过；失败时返回下标最小的无效交易，全部通过后记入缓存
+pub fn verify_transactions(
+    transactions: &[Transaction],
+    cache: &VerifiedTxCache,
+) -> Result<(), ChainError> {
+    let pending: Vec<(usize, Hash256, &Transaction)> = transactions
+        .iter()
+        .enumerate()
+        .map(|(index, tx)| (index, tx.canonical_hash(), tx))
+        .filter(|(_, hash, _)| !cache.contains(hash))
+        .collect();
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let first_invalid = if pending.len() < PARALLEL_THRESHOLD {
+        first_invalid(&pending)
+    } else {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk_size = pending.len().div_ceil(threads);
+        thread::scope(|scope| {
+            let handles: Vec<_> = pending
+                .chunks(chunk_size)
+                .map(|chunk| scope.spawn(move || first_invalid(chunk)))
+                .collect();
+            // 工作线程 panic 说明校验代码本身有问题，不能当成签名无效，原样传给调用方
+            handles
+                .into_iter()
+                .filter_map(|handle| handle.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+                .min()
+        })
+    };
+
+    match first_invalid {
+        Some(index) => Err(ChainError::InvalidSignature { index }),
+        None => {
+            cache.extend(pending.into_iter().map(|(_, hash, _)| hash));
+            Ok(())
+        }
+    }
+}
+
+/// 一组交易中第一笔签名无效的交易下标
+fn first_invalid(chunk: &[(usize, Hash256, &Transaction)]) -> Option<usize> {
+    chunk
+        .iter()
+        .find(|(_, _, tx)| tx.verify_signatures().is_err())
+        .map(|(index, _, _)| *index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use latte_primitives::address::Address;
+    use latte_primitives::crypto::Keypair;
+    use latte_types::builder::TransactionBuilder;
+
+    fn signed(keypair: &Keypair, count: u64) -> Vec<Transaction> {
+        (0..count)
+            .map(|nonce| {
+                TransactionBuilder::new()
+                    .to(Address([1; 20]))
+                    .value(1)
+                    .nonce(nonce)
+                    .sign(keypair)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_verify_transactions_in_parallel() {
+        let keypair = Keypair::generate();
+        let cache = VerifiedTxCache::default();
+        let mut txs = signed(&keypair, 40);
+        verify_transactions(&txs, &cache).unwrap();
+        assert_eq!(cache.len(), 40);
+
+        // 篡改签名和冒充发送方都会被发现，报告下标最小的一笔
+        txs.push(signed(&keypair, 41).pop().unwrap());
+        txs[35].signature[0] ^= 1;
+        txs[20].from = Address([2; 20]);
+        let result = verify_transactions(&txs, &cache);
+        assert!(matches!(result, Err(ChainError::InvalidSignature { index: 20 })));
+        assert_eq!(cache.len(), 40);
+    }
+
+    #[test]
+    fn test_cache_capacity() {
+        let cache = VerifiedTxCache::new(2);
+        cache.extend([Hash256([1; 32]), Hash256([2; 32]), Hash256([3; 32])]);
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.contains(&Hash256([1; 32])));
+        assert!(cache.contains(&Hash256([3; 32])));
+    }
+}
@@ -0,0 +1,221 @@
+//! 协议升级计划
+//!
+//! 共识规则不再写死在校验和执行代码中，而是由 [`ChainSpec`] 按区块高度给出：
+//! 每个协议升级有一个名字和激活高度，从该高度（含）开始覆盖之前的规则。
+//! 校验、执行、gas 价格和 VM 指令集都通过 [`ChainSpec::rules_at`] 取得区块所在高度的规则，
+//! 因此旧区块始终按当时的规则校验，升级可以在约定的高度同时生效。
+//! 升级计划不参与创世 hash，上线后可以追加新的升级。
+//!
+//! ```json
+//! "spec": {
+//!   "upgrades": [
+//!     { "name": "gas-repricing", "height": 1000, "gas": { "store": 20 }, "intrinsic_gas": 5 },
+//!     { "name": "mod-opcode", "height": 2000, "instruction_set": "v2", "max_block_gas": 10000000 },
+//!     { "name": "longer-median", "height": 3000, "median_time_span": 21 }
+//!   ]
+//! }
+//! ```
+
+use crate::error::ChainError;
+use latte_state::executor::Executor;
+use latte_state::vm::VmEngine;
+use latte_vm::engine::ScriptVm;
+use latte_vm::gas::GasSchedule;
+use latte_vm::instruction::InstructionSet;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// 计算祖先时间戳中位数时默认使用的区块数量
+pub const DEFAULT_MEDIAN_TIME_SPAN: usize = 11;
+
+/// 某个高度生效的全部共识规则
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProtocolRules {
+    /// 交易签名中必须携带的链 id
+    pub chain_id: u64,
+    /// 区块 gas_used 的上限，None 表示不限制
+    pub max_block_gas: Option<u64>,
+    /// 每笔交易的固定开销
+    pub intrinsic_gas: u64,
+    pub gas: GasSchedule,
+    pub instruction_set: InstructionSet,
+    /// 区块时间戳必须大于最近 median_time_span 个祖先（含父区块）时间戳的中位数
+    pub median_time_span: usize,
+}
+
+impl Default for ProtocolRules {
+    fn default() -> Self {
+        Self {
+            chain_id: 0,
+            max_block_gas: None,
+            intrinsic_gas: 0,
+            gas: GasSchedule::default(),
+            instruction_set: InstructionSet::default(),
+            median_time_span: DEFAULT_MEDIAN_TIME_SPAN,
+        }
+    }
+}
+
+impl ProtocolRules {
+    /// 按当前规则配置的虚拟机
+    pub fn vm(&self) -> ScriptVm {
+        ScriptVm::with_rules(self.gas, self.instruction_set)
+    }
+
+    /// 按当前规则配置的交易执行器
+    pub fn executor<'a, V: VmEngine>(&self, vm: &'a V) -> Executor<'a, V> {
+        Executor::new(vm)
+            .with_intrinsic_gas(self.intrinsic_gas)
+            .with_chain_id(self.chain_id)
+    }
+}
+
+/// 一次协议升级，只写需要修改的规则
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtocolUpgrade {
+    pub name: String,
+    /// 激活高度，该高度的区块已经按新规则校验
+    pub height: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_block_gas: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub intrinsic_gas: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gas: Option<GasSchedule>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instruction_set: Option<InstructionSet>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub median_time_span: Option<usize>,
+}
+
+impl ProtocolUpgrade {
+    fn apply(&self, rules: &mut ProtocolRules) {
+        if let Some(max_block_gas) = self.max_block_gas {
+            rules.max_block_gas = Some(max_block_gas);
+        }
+        if let Some(intrinsic_gas) = self.intrinsic_gas {
+            rules.intrinsic_gas = intrinsic_gas;
+        }
+        if let Some(gas) = self.gas {
+            rules.gas = gas;
+        }
+        if let Some(instruction_set) = self.instruction_set {
+            rules.instruction_set = instruction_set;
+        }
+        if let Some(median_time_span) = self.median_time_span {
+            rules.median_time_span = median_time_span;
+        }
+    }
+}
+
+/// 按激活高度排列的协议升级
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainSpec {
+    /// 由创世配置的 chain_id 填入，不单独配置
+    #[serde(skip)]
+    pub chain_id: u64,
+    #[serde(default)]
+    pub upgrades: Vec<ProtocolUpgrade>,
+}
+
+impl ChainSpec {
+    /// 升级名字不能重复，激活高度不能递减
+    pub fn validate(&self) -> Result<(), ChainError> {
+        let mut names = HashSet::new();
+        for (index, upgrade) in self.upgrades.iter().enumerate() {
+            if !names.insert(upgrade.name.as_str()) {
+                return Err(ChainError::InvalidGenesis(format!(
+                    "duplicate upgrade {}",
+                    upgrade.name
+                )));
+            }
+            if index > 0 && upgrade.height < self.upgrades[index - 1].height {
+                return Err(ChainError::InvalidGenesis(format!(
+                    "upgrade {} activates before {}",
+                    upgrade.name,
+                    self.upgrades[index - 1].name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// 高度为 number 的区块适用的规则
+    pub fn rules_at(&self, number: u64) -> ProtocolRules {
+        let mut rules = ProtocolRules {
+            chain_id: self.chain_id,
+            ..ProtocolRules::default()
+        };
+        for upgrade in self.active_upgrades(number) {
+            upgrade.apply(&mut rules);
+        }
+        rules
+    }
+
+    /// 在高度 number 已经生效的升级
+    pub fn active_upgrades(&self, number: u64) -> impl Iterator<Item = &ProtocolUpgrade> {
+        self.upgrades
+            .iter()
+            .take_while(move |upgrade| upgrade.height <= number)
+    }
+
+    pub fn is_active(&self, name: &str, number: u64) -> bool {
+        self.active_upgrades(number)
+            .any(|upgrade| upgrade.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules_at() {
+        let spec: ChainSpec = serde_json::from_str(
+            r#"{ "upgrades": [
+                { "name": "repricing", "height": 10, "gas": { "store": 20 }, "intrinsic_gas": 5 },
+                { "name": "mod-opcode", "height": 20, "instruction_set": "v2" }
+            ] }"#,
+        )
+        .unwrap();
+        spec.validate().unwrap();
+
+        assert_eq!(spec.rules_at(9), ProtocolRules::default());
+        let rules = spec.rules_at(10);
+        assert_eq!(rules.intrinsic_gas, 5);
+        assert_eq!(rules.gas.store, 20);
+        assert_eq!(rules.gas.step, 1);
+        assert_eq!(rules.instruction_set, InstructionSet::V1);
+
+        // 后面的升级保留之前升级的修改
+        let rules = spec.rules_at(25);
+        assert_eq!(rules.instruction_set, InstructionSet::V2);
+        assert_eq!(rules.intrinsic_gas, 5);
+        assert!(spec.is_active("repricing", 25));
+        assert_eq!(rules.median_time_span, DEFAULT_MEDIAN_TIME_SPAN);
+        assert!(!spec.is_active("mod-opcode", 19));
+    }
+
+    #[test]
+    fn test_validate() {
+        let upgrade = |name: &str, height| ProtocolUpgrade {
+            name: name.to_string(),
+            height,
+            max_block_gas: None,
+            intrinsic_gas: None,
+            gas: None,
+            instruction_set: None,
+            median_time_span: None,
+        };
+        let spec = ChainSpec {
+            upgrades: vec![upgrade("a", 10), upgrade("b", 5)],
+            ..ChainSpec::default()
+        };
+        assert!(spec.validate().is_err());
+        let spec = ChainSpec {
+            upgrades: vec![upgrade("a", 10), upgrade("a", 20)],
+            ..ChainSpec::default()
+        };
+        assert!(spec.validate().is_err());
+    }
+}
@@ -0,0 +1,347 @@
+//! 区块存储
+//!
+//! 除了按 hash 保存区块外，还维护以下索引：
+//! - hash -> 高度：所有已保存的区块，包括侧链
+//! - 高度 -> hash：只记录主链（canonical）上的区块，链重组时随 head 一起更新
+//! - hash -> 累计难度：从创世区块到该区块（含）的累计工作量，分叉选择使用
+//! - 交易 hash -> 交易位置：只记录主链上的交易，与高度索引一起更新
+//!
+//! 最终确认的区块同样单独保存，与它冲突的侧链区块可以删除。
+//!
+//! 另外保存一个 head 指针，与切换 head 时的索引修改在同一批写入中更新，
+//! 节点崩溃后重启时 head 与主链索引总是一致的。
+//!
+//! 所有写操作都通过 [`StorageBatch`] 原子写入，避免重组到一半时索引与区块不一致
+
+use crate::bad_blocks::BadBlock;
+use crate::storage_error::StorageError;
+use latte_primitives::hash::Hash256;
+use latte_types::block::Block;
+use latte_types::receipt::Receipt;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+
+pub trait BlockStorage {
+    fn get_block(&self, hash: Hash256) -> Result<Option<Block>, StorageError>;
+
+    /// 已保存区块的高度
+    fn get_block_number(&self, hash: Hash256) -> Result<Option<u64>, StorageError>;
+
+    /// 已保存区块的累计难度
+    fn get_total_difficulty(&self, hash: Hash256) -> Result<Option<u128>, StorageError>;
+
+    /// 主链上指定高度的区块 hash
+    fn get_canonical_hash(&self, number: u64) -> Result<Option<Hash256>, StorageError>;
+
+    /// 区块中所有交易的回执，按交易顺序排列
+    fn get_receipts(&self, block_hash: Hash256) -> Result<Option<Vec<Receipt>>, StorageError>;
+
+    /// 主链上交易所在的区块和位置
+    fn get_tx_location(&self, tx_hash: Hash256) -> Result<Option<TxLocation>, StorageError>;
+
+    /// 持久化的 head 指针，新建的存储返回 None
+    fn get_head(&self) -> Result<Option<Hash256>, StorageError>;
+
+    /// 持久化的最终确认区块
+    fn get_finalized(&self) -> Result<Option<Hash256>, StorageError>;
+
+    /// 校验失败的区块记录
+    fn get_bad_block(&self, hash: Hash256) -> Result<Option<BadBlock>, StorageError>;
+
+    /// 所有无效区块记录，打开链时用来恢复记录的淘汰顺序
+    fn get_bad_blocks(&self) -> Result<Vec<BadBlock>, StorageError>;
+
+    /// 原子地写入一批修改，要么全部生效，要么全部不生效
+    fn write_batch(&self, batch: StorageBatch) -> Result<(), StorageError>;
+
+    fn put_block(&self, hash: Hash256, block: &Block) -> Result<(), StorageError> {
+        let mut batch = StorageBatch::new();
+        batch.put_block(hash, block.clone());
+        self.write_batch(batch)
+    }
+}
+
+/// 交易在主链上的位置
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TxLocation {
+    pub block_hash: Hash256,
+    // 交易在区块中的下标
+    pub index: usize,
+}
+
+/// 一次原子写入中的单个操作
+#[derive(Clone, Debug)]
+pub enum StorageOp {
+    /// 保存区块，同时写入 hash -> 高度索引
+    PutBlock(Hash256, Box<Block>),
+    SetTotalDifficulty(Hash256, u128),
+    SetCanonical(u64, Hash256),
+    RemoveCanonical(u64),
+    PutReceipts(Hash256, Vec<Receipt>),
+    SetTxLocation(Hash256, TxLocation),
+    RemoveTxLocation(Hash256),
+    SetHead(Hash256),
+    SetFinalized(Hash256),
+    /// 删除区块及其高度、累计难度和回执，用于清理与最终确认冲突的侧链
+    DeleteBlock(Hash256),
+    /// 记录校验失败的区块，与正常区块分开保存
+    PutBadBlock(Box<BadBlock>),
+    /// 删除被淘汰的无效区块记录
+    DeleteBadBlock(Hash256),
+}
+
+/// 按顺序执行的一批写操作
+#[derive(Clone, Debug, Default)]
+pub struct StorageBatch {
+    ops: Vec<StorageOp>,
+}
+
+impl StorageBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn put_block(&mut self, hash: Hash256, block: Block) {
+        self.ops.push(StorageOp::PutBlock(hash, Box::new(block)));
+    }
+
+    pub fn set_total_difficulty(&mut self, hash: Hash256, total_difficulty: u128) {
+        self.ops.push(StorageOp::SetTotalDifficulty(hash, total_difficulty));
+    }
+
+    pub fn set_canonical(&mut self, number: u64, hash: Hash256) {
+        self.ops.push(StorageOp::SetCanonical(number, hash));
+    }
+
+    pub fn remove_canonical(&mut self, number: u64) {
+        self.ops.push(StorageOp::RemoveCanonical(number));
+    }
+
+    pub fn put_receipts(&mut self, block_hash: Hash256, receipts: Vec<Receipt>) {
+        self.ops.push(StorageOp::PutReceipts(block_hash, receipts));
+    }
+
+    pub fn set_tx_location(&mut self, tx_hash: Hash256, location: TxLocation) {
+        self.ops.push(StorageOp::SetTxLocation(tx_hash, location));
+    }
+
+    pub fn remove_tx_location(&mut self, tx_hash: Hash256) {
+        self.ops.push(StorageOp::RemoveTxLocation(tx_hash));
+    }
+
+    pub fn set_head(&mut self, hash: Hash256) {
+        self.ops.push(StorageOp::SetHead(hash));
+    }
+
+    pub fn set_finalized(&mut self, hash: Hash256) {
+        self.ops.push(StorageOp::SetFinalized(hash));
+    }
+
+    pub fn delete_block(&mut self, hash: Hash256) {
+        self.ops.push(StorageOp::DeleteBlock(hash));
+    }
+
+    pub fn put_bad_block(&mut self, bad_block: BadBlock) {
+        self.ops.push(StorageOp::PutBadBlock(Box::new(bad_block)));
+    }
+
+    pub fn delete_bad_block(&mut self, hash: Hash256) {
+        self.ops.push(StorageOp::DeleteBadBlock(hash));
+    }
+
+    pub fn ops(&self) -> &[StorageOp] {
+        &self.ops
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    pub fn into_ops(self) -> Vec<StorageOp> {
+        self.ops
+    }
+}
+
+#[derive(Default)]
+struct MemoryTables {
+    blocks: HashMap<Hash256, Block>,
+    numbers: HashMap<Hash256, u64>,
+    total_difficulties: HashMap<Hash256, u128>,
+    canonical: BTreeMap<u64, Hash256>,
+    receipts: HashMap<Hash256, Vec<Receipt>>,
+    tx_locations: HashMap<Hash256, TxLocation>,
+    head: Option<Hash256>,
+    finalized: Option<Hash256>,
+    bad_blocks: HashMap<Hash256, BadBlock>,
+}
+
+/// 内存中的区块存储，用于测试和不需要持久化的节点
+#[derive(Default)]
+pub struct MemoryStorage {
+    tables: RwLock<MemoryTables>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl BlockStorage for MemoryStorage {
+    fn get_block(&self, hash: Hash256) -> Result<Option<Block>, StorageError> {
+        let tables = self.tables.read().map_err(|e| StorageError::Db(e.to_string()))?;
+        Ok(tables.blocks.get(&hash).cloned())
+    }
+
+    fn get_block_number(&self, hash: Hash256) -> Result<Option<u64>, StorageError> {
+        let tables = self.tables.read().map_err(|e| StorageError::Db(e.to_string()))?;
+        Ok(tables.numbers.get(&hash).copied())
+    }
+
+    fn get_total_difficulty(&self, hash: Hash256) -> Result<Option<u128>, StorageError> {
+        let tables = self.tables.read().map_err(|e| StorageError::Db(e.to_string()))?;
+        Ok(tables.total_difficulties.get(&hash).copied())
+    }
+
+    fn get_canonical_hash(&self, number: u64) -> Result<Option<Hash256>, StorageError> {
+        let tables = self.tables.read().map_err(|e| StorageError::Db(e.to_string()))?;
+        Ok(tables.canonical.get(&number).copied())
+    }
+
+    fn get_receipts(&self, block_hash: Hash256) -> Result<Option<Vec<Receipt>>, StorageError> {
+        let tables = self.tables.read().map_err(|e| StorageError::Db(e.to_string()))?;
+        Ok(tables.receipts.get(&block_hash).cloned())
+    }
+
+    fn get_tx_location(&self, tx_hash: Hash256) -> Result<Option<TxLocation>, StorageError> {
+        let tables = self.tables.read().map_err(|e| StorageError::Db(e.to_string()))?;
+        Ok(tables.tx_locations.get(&tx_hash).copied())
+    }
+
+    fn get_head(&self) -> Result<Option<Hash256>, StorageError> {
+        let tables = self.tables.read().map_err(|e| StorageError::Db(e.to_string()))?;
+        Ok(tables.head)
+    }
+
+    fn get_finalized(&self) -> Result<Option<Hash256>, StorageError> {
+        let tables = self.tables.read().map_err(|e| StorageError::Db(e.to_string()))?;
+        Ok(tables.finalized)
+    }
+
+    fn get_bad_block(&self, hash: Hash256) -> Result<Option<BadBlock>, StorageError> {
+        let tables = self.tables.read().map_err(|e| StorageError::Db(e.to_string()))?;
+        Ok(tables.bad_blocks.get(&hash).cloned())
+    }
+
+    fn get_bad_blocks(&self) -> Result<Vec<BadBlock>, StorageError> {
+        let tables = self.tables.read().map_err(|e| StorageError::Db(e.to_string()))?;
+        Ok(tables.bad_blocks.values().cloned().collect())
+    }
+
+    fn write_batch(&self, batch: StorageBatch) -> Result<(), StorageError> {
+        // 持有写锁期间应用整批操作，其他读者看不到中间状态
+        let mut tables = self.tables.write().map_err(|e| StorageError::Db(e.to_string()))?;
+        for op in batch.into_ops() {
+            match op {
+                StorageOp::PutBlock(hash, block) => {
+                    tables.numbers.insert(hash, block.header.number);
+                    tables.blocks.insert(hash, *block);
+                }
+                StorageOp::SetTotalDifficulty(hash, total_difficulty) => {
+                    tables.total_difficulties.insert(hash, total_difficulty);
+                }
+                StorageOp::SetCanonical(number, hash) => {
+                    tables.canonical.insert(number, hash);
+                }
+                StorageOp::RemoveCanonical(number) => {
+                    tables.canonical.remove(&number);
+                }
+                StorageOp::PutReceipts(block_hash, receipts) => {
+                    tables.receipts.insert(block_hash, receipts);
+                }
+                StorageOp::SetTxLocation(tx_hash, location) => {
+                    tables.tx_locations.insert(tx_hash, location);
+                }
+                StorageOp::RemoveTxLocation(tx_hash) => {
+                    tables.tx_locations.remove(&tx_hash);
+                }
+                StorageOp::SetHead(hash) => {
+                    tables.head = Some(hash);
+                }
+                StorageOp::SetFinalized(hash) => {
+                    tables.finalized = Some(hash);
+                }
+                StorageOp::DeleteBlock(hash) => {
+                    tables.blocks.remove(&hash);
+                    tables.numbers.remove(&hash);
+                    tables.total_difficulties.remove(&hash);
+                    tables.receipts.remove(&hash);
+                }
+                StorageOp::PutBadBlock(bad_block) => {
+                    tables.bad_blocks.insert(bad_block.hash, *bad_block);
+                }
+                StorageOp::DeleteBadBlock(hash) => {
+                    tables.bad_blocks.remove(&hash);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::BlockBuilder;
+    use crate::error::ChainError;
+
+    #[test]
+    fn test_memory_storage_indexes() {
+        let storage = MemoryStorage::new();
+        let block = BlockBuilder::genesis().timestamp(1).build();
+        let (a, b) = (Hash256([1; 32]), Hash256([2; 32]));
+
+        let mut batch = StorageBatch::new();
+        batch.put_block(a, block);
+        batch.set_canonical(0, a);
+        batch.set_canonical(1, b);
+        batch.set_head(b);
+        storage.write_batch(batch).unwrap();
+
+        assert_eq!(storage.get_block_number(a).unwrap(), Some(0));
+        assert_eq!(storage.get_block(a).unwrap().unwrap().header.timestamp, 1);
+        assert_eq!(storage.get_canonical_hash(1).unwrap(), Some(b));
+        assert_eq!(storage.get_head().unwrap(), Some(b));
+
+        let mut batch = StorageBatch::new();
+        batch.remove_canonical(1);
+        batch.delete_block(a);
+        storage.write_batch(batch).unwrap();
+        assert_eq!(storage.get_canonical_hash(1).unwrap(), None);
+        assert!(storage.get_block(b).unwrap().is_none());
+        assert!(storage.get_block(a).unwrap().is_none());
+        assert_eq!(storage.get_block_number(a).unwrap(), None);
+    }
+
+    #[test]
+    fn test_bad_block_survives_delete() {
+        let storage = MemoryStorage::new();
+        let block = BlockBuilder::genesis().timestamp(1).build();
+        let hash = Hash256([1; 32]);
+        let mut batch = StorageBatch::new();
+        let reason = ChainError::InvalidParent;
+        batch.put_bad_block(BadBlock::new(hash, &block.header, &reason, 5));
+        batch.delete_block(hash);
+        storage.write_batch(batch).unwrap();
+
+        let bad = storage.get_bad_block(hash).unwrap().unwrap();
+        assert_eq!((bad.header.number, bad.detected_at), (0, 5));
+        assert_eq!(bad.reason, reason.to_string());
+        assert_eq!(storage.get_bad_blocks().unwrap().len(), 1);
+
+        let mut batch = StorageBatch::new();
+        batch.delete_bad_block(hash);
+        storage.write_batch(batch).unwrap();
+        assert!(storage.get_bad_block(hash).unwrap().is_none());
+    }
+}
@@ -0,0 +1,217 @@
+//! 测试辅助
+// 基于 postcard 的编码器、固定时间的创世配置，以及在创世状态上重放区块出块的工具
+
+use crate::bad_blocks::BadBlock;
+use crate::block_executor::BlockExecutor;
+use crate::blockchain::Blockchain;
+use crate::builder::BlockBuilder;
+use crate::clock::MockClock;
+use crate::error::ChainError;
+use crate::genesis::GenesisSpec;
+use crate::storage::{BlockStorage, MemoryStorage, StorageBatch, TxLocation};
+use crate::storage_error::StorageError;
+use latte_codec::codec::Codec;
+use latte_primitives::address::Address;
+use latte_primitives::crypto::Keypair;
+use latte_primitives::hash::Hash256;
+use latte_state::state::WorldState;
+use latte_types::block::Block;
+use latte_types::builder::TransactionBuilder;
+use latte_types::receipt::Receipt;
+use latte_types::transaction::Transaction;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub(crate) const CHAIN_ID: u64 = 1;
+pub(crate) const GENESIS_TIME: u64 = 1_700_000_000;
+pub(crate) const INITIAL_BALANCE: u128 = 100_000;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct TestCodec;
+
+impl Codec for TestCodec {
+    fn encode<T: ?Sized + serde::Serialize>(&self, v: &T) -> Result<Vec<u8>, String> {
+        postcard::to_allocvec(v).map_err(|e| e.to_string())
+    }
+
+    fn decode<T: serde::de::DeserializeOwned>(&self, bytes: &Vec<u8>) -> Result<T, String> {
+        postcard::from_bytes(bytes).map_err(|e| e.to_string())
+    }
+}
+
+/// 一个账户持有全部初始余额的 PoA 测试链
+pub(crate) struct Fixture {
+    pub keypair: Keypair,
+    pub spec: GenesisSpec,
+    pub genesis: Block,
+    pub clock: Arc<MockClock>,
+}
+
+impl Fixture {
+    pub fn new() -> Self {
+        Self::with_spec(|_| {})
+    }
+
+    /// 在默认创世配置上做修改，例如共识参数或协议升级
+    pub fn with_spec(customize: impl FnOnce(&mut GenesisSpec)) -> Self {
+        let keypair = Keypair::generate();
+        let json = format!(
+            r#"{{
+                "chain_id": {CHAIN_ID},
+                "timestamp": {GENESIS_TIME},
+                "alloc": [{{ "address": "0x{}", "balance": "{INITIAL_BALANCE}" }}],
+                "consensus": {{ "engine": "proof_of_authority", "block_time": 10 }}
+            }}"#,
+            hex::encode(address_of(&keypair).0)
+        );
+        let mut spec = GenesisSpec::from_json(&json).unwrap();
+        customize(&mut spec);
+        let genesis = spec.build().unwrap().block;
+        Self {
+            keypair,
+            spec,
+            genesis,
+            // 本地时钟在创世之后足够久，测试中的区块时间戳都不会超前
+            clock: Arc::new(MockClock::new(GENESIS_TIME + 100_000)),
+        }
+    }
+
+    pub fn chain(&self) -> Blockchain<TestCodec> {
+        self.chain_with(Box::new(MemoryStorage::new()))
+    }
+
+    pub fn chain_with(&self, storage: Box<dyn BlockStorage>) -> Blockchain<TestCodec> {
+        Blockchain::new(self.spec.build().unwrap(), storage, TestCodec)
+            .unwrap()
+            .with_clock(self.clock.clone())
+    }
+
+    pub fn open(&self, storage: Box<dyn BlockStorage>) -> Result<Blockchain<TestCodec>, ChainError> {
+        Ok(Blockchain::open(self.spec.build().unwrap(), storage, TestCodec)?
+            .with_clock(self.clock.clone()))
+    }
+
+    /// 从创世状态依次执行 path 上的区块后的状态
+    pub fn state_after(&self, path: &[&Block]) -> WorldState {
+        let genesis = self.spec.build().unwrap();
+        let mut state = genesis.state;
+        for block in path {
+            let rules = genesis.spec.rules_at(block.header.number);
+            let vm = rules.vm();
+            let executor = rules.executor(&vm);
+            let reward = genesis.consensus.issuance.reward_at(block.header.number);
+            BlockExecutor::new(&mut state, &executor)
+                .with_block_reward(reward)
+                .apply_block(block)
+                .unwrap();
+        }
+        state
+    }
+
+    /// 在 path 末端（path 为空时为创世区块）之上出块
+    ///
+    /// 时间戳为父区块加 1 再加 offset，同一个父区块下可以用 difficulty 或 offset 得到不同的兄弟区块
+    pub fn child(
+        &self,
+        path: &[&Block],
+        transactions: Vec<Transaction>,
+        difficulty: u64,
+        offset: u64,
+    ) -> Block {
+        let parent = path.last().copied().unwrap_or(&self.genesis);
+        let number = parent.header.number + 1;
+        let genesis = self.spec.build().unwrap();
+        let rules = genesis.spec.rules_at(number);
+        let vm = rules.vm();
+        let executor = rules.executor(&vm);
+        let reward = genesis.consensus.issuance.reward_at(number);
+        let mut state = self.state_after(path);
+        BlockBuilder::child_of(&parent.header, &TestCodec)
+            .unwrap()
+            .difficulty(difficulty)
+            .timestamp(parent.header.timestamp + 1 + offset)
+            .transactions(transactions)
+            .execute(&mut BlockExecutor::new(&mut state, &executor).with_block_reward(reward))
+            .unwrap()
+            .build()
+    }
+
+    /// 向 Address([to; 20]) 转账 5
+    pub fn transfer(&self, nonce: u64, to: u8) -> Transaction {
+        TransactionBuilder::new()
+            .chain_id(CHAIN_ID)
+            .to(Address([to; 20]))
+            .value(5)
+            .nonce(nonce)
+            .gas_limit(1)
+            .gas_price(1)
+            .sign(&self.keypair)
+    }
+}
+
+pub(crate) fn address_of(keypair: &Keypair) -> Address {
+    Address::from_pubkey(keypair.verifying.as_bytes())
+}
+
+/// 共享同一个 MemoryStorage，用于重新打开链或在链外修改存储
+#[derive(Clone, Default)]
+pub(crate) struct SharedStorage {
+    pub inner: Arc<MemoryStorage>,
+    // 置位后所有写入都失败，模拟磁盘故障
+    pub fail_writes: Arc<AtomicBool>,
+}
+
+impl SharedStorage {
+    pub fn fail_writes(&self, fail: bool) {
+        self.fail_writes.store(fail, Ordering::Relaxed);
+    }
+}
+
+impl BlockStorage for SharedStorage {
+    fn get_block(&self, hash: Hash256) -> Result<Option<Block>, StorageError> {
+        self.inner.get_block(hash)
+    }
+
+    fn get_block_number(&self, hash: Hash256) -> Result<Option<u64>, StorageError> {
+        self.inner.get_block_number(hash)
+    }
+
+    fn get_total_difficulty(&self, hash: Hash256) -> Result<Option<u128>, StorageError> {
+        self.inner.get_total_difficulty(hash)
+    }
+
+    fn get_canonical_hash(&self, number: u64) -> Result<Option<Hash256>, StorageError> {
+        self.inner.get_canonical_hash(number)
+    }
+
+    fn get_receipts(&self, block_hash: Hash256) -> Result<Option<Vec<Receipt>>, StorageError> {
+        self.inner.get_receipts(block_hash)
+    }
+
+    fn get_tx_location(&self, tx_hash: Hash256) -> Result<Option<TxLocation>, StorageError> {
+        self.inner.get_tx_location(tx_hash)
+    }
+
+    fn get_head(&self) -> Result<Option<Hash256>, StorageError> {
+        self.inner.get_head()
+    }
+
+    fn get_finalized(&self) -> Result<Option<Hash256>, StorageError> {
+        self.inner.get_finalized()
+    }
+
+    fn get_bad_block(&self, hash: Hash256) -> Result<Option<BadBlock>, StorageError> {
+        self.inner.get_bad_block(hash)
+    }
+
+    fn get_bad_blocks(&self) -> Result<Vec<BadBlock>, StorageError> {
+        self.inner.get_bad_blocks()
+    }
+
+    fn write_batch(&self, batch: StorageBatch) -> Result<(), StorageError> {
+        if self.fail_writes.load(Ordering::Relaxed) {
+            return Err(StorageError::Db("write failed".into()));
+        }
+        self.inner.write_batch(batch)
+    }
+}
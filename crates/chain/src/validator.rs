@@ -1,7 +1,10 @@
+use crate::block_executor::{self, SimulatedBlock};
 use crate::blockchain::Blockchain;
+use crate::difficulty;
 use crate::error::ChainError;
+use crate::genesis::ConsensusEngine;
 use crate::merkle;
-use chrono::{Duration, Utc};
+use crate::signature;
 use latte_codec::codec::Codec;
 use latte_primitives::hash::Hash256;
 use latte_types::block::Block;
@@ -12,26 +15,53 @@ pub struct BlockValidator {}
 /// 校验一个新区块是否可以接入Ï
 ///
 /// - 链完整性校验: hash、高度连续性、时间戳校验
+/// - 最终确认校验：区块必须延伸最终确认的区块
 /// - 状态合法性校验：确保所有节点在执行相同的交易后，得到的“账本结果”是完全一致的；偏向金额等数据的正确性
-///     状态合法性校验要求实现一个**“只读模拟器”**。不能直接修改当前的数据库，而是要在内存中创建一个副本，跑完所有交易，计算根值，最后再抛弃这个副本
-/// - 共识规则校验：根据不同的共识算法做相应的校验，比如挖矿，检查hash是否满足难度要求，gas总和是否超过设定的gas_limit
+///     状态合法性校验要求实现一个**“只读模拟器”**。不能直接修改当前的数据库，而是在父状态之上的覆盖层中跑完所有交易，计算根值；校验通过后覆盖层中的修改交给调用方应用到链的状态上，区块不再重新执行
+/// - 共识规则校验：规则取自区块高度对应的协议升级；根据不同的共识算法做相应的校验，比如挖矿，检查hash是否满足难度要求，gas总和是否超过设定的gas_limit
 /// - 默克尔树根校验:证明某笔交易确实存在于该区块中;数据没有被篡改或者丢失，偏向数据的完整性
 ///
 /// 原则：校验应该从易到难，避免不必要的计算
 impl BlockValidator {
+    /// 延伸主链的区块返回模拟执行的结果，侧链区块返回 None
     pub fn verify_block<C: Codec>(
         &self,
         block: &Block,
         parent_hash256: Hash256,
         blockchain: &Blockchain<C>,
-    ) -> Result<(), ChainError> {
+    ) -> Result<Option<SimulatedBlock>, ChainError> {
         // 基础信息验证
         self.validate_basic_info(block, parent_hash256, blockchain)?;
-        // todo 状态验证，需要模拟执行
+        // 不允许在最终确认的区块之前分叉
+        self.validate_finality(parent_hash256, blockchain)?;
         // 共识规则 校验， pow,pos等
-        
+        self.validate_consensus(block, parent_hash256, blockchain)?;
+
         // 默克尔树 校验
         self.validate_tx_root(block)?;
+
+        // 签名校验，并行执行，交易池中校验过的交易直接跳过
+        signature::verify_transactions(&block.transactions, blockchain.verified_txs())?;
+
+        // 状态验证，需要模拟执行，放在最后
+        // 只有延伸主链的区块才有父状态可用；侧链区块在分叉选择切换到它时执行并校验
+        if parent_hash256 == blockchain.head() {
+            return self.validate_state_root(block, blockchain).map(Some);
+        }
+        Ok(None)
+    }
+
+    /// 父区块必须是最终确认区块或它的后代
+    fn validate_finality<C: Codec>(
+        &self,
+        parent_hash: Hash256,
+        blockchain: &Blockchain<C>,
+    ) -> Result<(), ChainError> {
+        if let Some(finalized) = blockchain.finalized()
+            && !blockchain.tree().is_ancestor(&finalized, &parent_hash)
+        {
+            return Err(ChainError::ConflictsWithFinalized(finalized));
+        }
         Ok(())
     }
 
@@ -49,23 +79,78 @@ impl BlockValidator {
         }
 
         // 校验高度
-        match blockchain.get_block(parent_hash) {
-            Some(parent_block) => {
-                if parent_block.header.number + 1 != header.number {
-                    return Err(ChainError::InvalidHeight);
+        match blockchain.get_header(parent_hash)? {
+            Some(parent) => {
+                if parent.number + 1 != header.number {
+                    return Err(ChainError::InvalidHeight {
+                        expected: parent.number + 1,
+                        actual: header.number,
+                    });
                 }
             }
             None => {
-                return Err(ChainError::InvalidHeight);
+                // 父区块尚未收到，交给孤块池等待
+                return Err(ChainError::UnknownParent(parent_hash));
             }
         }
 
-        // 时间校验
-        let now = Utc::now().timestamp();
-        let diff_time = (now - header.timestamp as i64).abs();
-        // 对比小时数转成的秒数 (i64)
-        if diff_time > Duration::hours(1).num_seconds() {
-            return Err(ChainError::TimeoutError);
+        // 时间校验：必须晚于祖先时间戳的中位数，防止时间戳倒退；
+        // 同时不能超前本地时钟太多
+        let median = blockchain
+            .median_time_past(parent_hash)?
+            .ok_or(ChainError::UnknownParent(parent_hash))?;
+        if header.timestamp <= median {
+            return Err(ChainError::TimestampTooOld {
+                timestamp: header.timestamp,
+                median,
+            });
+        }
+        let max = blockchain.clock().now() + blockchain.config().max_future_drift;
+        if header.timestamp > max {
+            return Err(ChainError::TimestampTooFarInFuture {
+                timestamp: header.timestamp,
+                max,
+            });
+        }
+        Ok(())
+    }
+
+    /// 工作量证明链的区块难度必须等于按父区块计算出的难度，且区块 hash 满足该难度
+    fn validate_consensus<C: Codec>(
+        &self,
+        block: &Block,
+        parent_hash: Hash256,
+        blockchain: &Blockchain<C>,
+    ) -> Result<(), ChainError> {
+        let rules = blockchain.spec().rules_at(block.header.number);
+        if let Some(limit) = rules.max_block_gas
+            && block.header.gas_used > limit
+        {
+            return Err(ChainError::BlockGasExceeded {
+                limit,
+                used: block.header.gas_used,
+            });
+        }
+
+        if blockchain.consensus().engine != ConsensusEngine::ProofOfWork {
+            return Ok(());
+        }
+        let expected = blockchain
+            .difficulty_for_child(parent_hash)?
+            .ok_or(ChainError::UnknownParent(parent_hash))?;
+        if block.header.difficulty != expected {
+            return Err(ChainError::InvalidDifficulty {
+                expected,
+                actual: block.header.difficulty,
+            });
+        }
+        let hash = block
+            .hash_with(blockchain.codec())
+            .map_err(ChainError::InvalidBlock)?;
+        if !difficulty::meets_target(&hash, block.header.difficulty) {
+            return Err(ChainError::InvalidProofOfWork {
+                difficulty: block.header.difficulty,
+            });
         }
         Ok(())
     }
@@ -77,37 +162,153 @@ impl BlockValidator {
         if real_hash256.0 == tx_root.0 {
             Ok(())
         } else {
-            Err(ChainError::TxRootMismatch)
+            Err(ChainError::TxRootMismatch {
+                expected: tx_root,
+                actual: real_hash256,
+            })
         }
     }
 
-    // fn validate_state_root(
-    //     &self,
-    //     block: &Block,
-    //     pre_state: &impl StateProvider
-    // ) -> Result<(), ChainError> {
-    //     // 1. 初始化覆盖层
-    //     let mut overlay = StateOverlay::new(pre_state);
-    //
-    //     // 2. 模拟执行
-    //     for tx in &block.transactions {
-    //         // 这里调用你的虚拟机逻辑
-    //         // 比如：执行 A -> B 转账，修改 overlay 中的余额
-    //         self.vm.execute_transaction(tx, &mut overlay)?;
-    //     }
-    //
-    //     // 3. 处理区块奖励 (矿工加钱)
-    //     self.apply_block_reward(&mut overlay, &block.header.proposer)?;
-    //
-    //     // 4. 计算最终根值
-    //     // 你需要将 overlay.modified_accounts 与 pre_state 合并计算 Trie Root
-    //     let calculated_root = self.trie_library.calculate_root(&overlay)?;
-    //
-    //     // 5. 比对
-    //     if calculated_root != block.header.state_root {
-    //         return Err(ChainError::InvalidStateRoot);
-    //     }
-    //
-    //     Ok(())
-    // }
+    /// 在 head 状态之上的覆盖层中模拟执行区块，比对 gas_used、receipt_root 和 state_root
+    ///
+    /// 模拟不修改链上的状态，状态转换错误的区块在接入前就被拒绝
+    fn validate_state_root<C: Codec>(
+        &self,
+        block: &Block,
+        blockchain: &Blockchain<C>,
+    ) -> Result<SimulatedBlock, ChainError> {
+        // 签名在前面的阶段已经校验过
+        let rules = blockchain.spec().rules_at(block.header.number);
+        let vm = rules.vm();
+        let executor = rules.executor(&vm).without_signature_check();
+        let reward = blockchain.consensus().issuance.reward_at(block.header.number);
+        block_executor::simulate_block(blockchain.state(), &executor, block, reward)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::difficulty;
+    use crate::error::ChainError;
+    use crate::genesis::ConsensusEngine;
+    use crate::test_utils::{Fixture, GENESIS_TIME, TestCodec};
+    use latte_types::block::Block;
+
+    fn pow_fixture() -> Fixture {
+        Fixture::with_spec(|spec| {
+            spec.consensus.engine = ConsensusEngine::ProofOfWork;
+            spec.consensus.initial_difficulty = 100;
+        })
+    }
+
+    fn mined(fixture: &Fixture, path: &[&Block], difficulty: u64) -> Block {
+        let mut block = fixture.child(path, vec![], difficulty, 0);
+        difficulty::seal(&mut block.header, &TestCodec).unwrap();
+        block
+    }
+
+    #[test]
+    fn test_pow_difficulty_retarget() {
+        let fixture = pow_fixture();
+        let mut chain = fixture.chain();
+        let mut blocks: Vec<Block> = Vec::new();
+        for _ in 0..3 {
+            let path: Vec<&Block> = blocks.iter().collect();
+            let block = mined(&fixture, &path, chain.get_next_difficulty().unwrap());
+            chain.append_block(block.clone()).unwrap();
+            blocks.push(block);
+        }
+        // 出块间隔 1 秒，远快于 10 秒的目标，难度上升
+        assert!(chain.get_next_difficulty().unwrap() > 100);
+        assert!(chain.total_difficulty(chain.head()).unwrap() > 400);
+
+        let path: Vec<&Block> = blocks.iter().collect();
+        let expected = chain.get_next_difficulty().unwrap();
+        assert!(matches!(
+            chain.append_block(mined(&fixture, &path, 1)),
+            Err(ChainError::InvalidDifficulty { expected: e, actual: 1 }) if e == expected
+        ));
+    }
+
+    #[test]
+    fn test_pow_rejects_hash_above_target() {
+        let fixture = pow_fixture();
+        let mut chain = fixture.chain();
+        let mut block = mined(&fixture, &[], 100);
+        // 找一个不满足难度的 nonce
+        while difficulty::meets_target(&block.hash_with(&TestCodec).unwrap(), 100) {
+            block.header.nonce += 1;
+        }
+        assert!(matches!(
+            chain.append_block(block),
+            Err(ChainError::InvalidProofOfWork { difficulty: 100 })
+        ));
+        assert_eq!(chain.height(), 0);
+
+        chain.append_block(mined(&fixture, &[], 100)).unwrap();
+        assert_eq!(chain.height(), 1);
+    }
+
+    #[test]
+    fn test_timestamp_too_far_in_future() {
+        let fixture = Fixture::new();
+        fixture.clock.set(GENESIS_TIME + 10);
+        let mut chain = fixture.chain();
+        let block = fixture.child(&[], vec![], 1, 30);
+        assert!(matches!(
+            chain.append_block(block.clone()),
+            Err(ChainError::TimestampTooFarInFuture { timestamp, max })
+                if timestamp == GENESIS_TIME + 31 && max == GENESIS_TIME + 25
+        ));
+
+        // 本地时钟追上之后同一个区块可以接入
+        fixture.clock.advance(20);
+        chain.append_block(block).unwrap();
+        assert_eq!(chain.height(), 1);
+    }
+
+    #[test]
+    fn test_timestamp_not_after_median() {
+        let fixture = Fixture::new();
+        let mut chain = fixture.chain();
+        let a1 = fixture.child(&[], vec![], 1, 0);
+        chain.append_block(a1.clone()).unwrap();
+
+        // 祖先时间戳为 [genesis, a1]，中位数是 a1 的时间戳
+        let mut a2 = fixture.child(&[&a1], vec![], 1, 0);
+        a2.header.timestamp = a1.header.timestamp;
+        assert!(matches!(
+            chain.append_block(a2),
+            Err(ChainError::TimestampTooOld { timestamp, median })
+                if timestamp == median && median == a1.header.timestamp
+        ));
+        assert_eq!(chain.height(), 1);
+    }
+
+    #[test]
+    fn test_median_time_span_follows_upgrade() {
+        let fixture = Fixture::with_spec(|spec| {
+            spec.spec = serde_json::from_str(
+                r#"{ "upgrades": [{ "name": "strict-median", "height": 3, "median_time_span": 1 }] }"#,
+            )
+            .unwrap();
+        });
+        let mut chain = fixture.chain();
+        let a1 = fixture.child(&[], vec![], 1, 0);
+        let a2 = fixture.child(&[&a1], vec![], 1, 0);
+        chain.append_block(a1.clone()).unwrap();
+        let a2_hash = chain.append_block(a2.clone()).unwrap();
+
+        // 高度 3 起只看父区块，默认的 11 个祖先下中位数会是 a1 的时间戳
+        assert_eq!(
+            chain.median_time_past(a2_hash).unwrap(),
+            Some(a2.header.timestamp)
+        );
+        let mut a3 = fixture.child(&[&a1, &a2], vec![], 1, 0);
+        a3.header.timestamp = a2.header.timestamp;
+        assert!(matches!(
+            chain.append_block(a3),
+            Err(ChainError::TimestampTooOld { median, .. }) if median == a2.header.timestamp
+        ));
+    }
 }
@@ -0,0 +1,87 @@
+//! 全链重放校验
+//!
+//! 从创世状态（或一个可信的状态快照）开始，用 [`crate::block_executor::BlockExecutor::apply_block`]
+//! 依次重新执行主链上的每个区块，与区块头中的各个根、存储中的回执和主链索引比对，
+//! 用来证明数据库没有损坏。校验在第一个出现分歧的区块处停止并报告原因，不修改链的状态。
+
+use crate::error::ChainError;
+use crate::genesis::Genesis;
+use latte_primitives::hash::Hash256;
+use latte_state::state::WorldState;
+
+/// 可信的状态快照：高度为 number 的主链区块执行后的状态
+pub struct StateSnapshot {
+    pub number: u64,
+    pub state: WorldState,
+}
+
+impl StateSnapshot {
+    pub fn new(number: u64, state: WorldState) -> Self {
+        Self { number, state }
+    }
+}
+
+/// 从创世状态开始重放
+impl From<Genesis> for StateSnapshot {
+    fn from(genesis: Genesis) -> Self {
+        Self::new(0, genesis.state)
+    }
+}
+
+/// 重新计算的结果与存储中的数据不一致的地方
+#[derive(Debug)]
+pub enum DivergenceKind {
+    /// 区块无法从存储中读出
+    Unreadable(String),
+    /// 存储中的主链索引与内存中的不一致
+    CanonicalIndex {
+        expected: Hash256,
+        actual: Option<Hash256>,
+    },
+    /// 区块内容计算出的 hash 与主链索引不一致
+    BlockHash {
+        expected: Hash256,
+        actual: Hash256,
+    },
+    /// 区块的父 hash 不是前一个主链区块
+    ParentHash {
+        expected: Hash256,
+        actual: Hash256,
+    },
+    /// 区块无法执行，或执行结果与区块头不一致，例如 [`ChainError::StateRootMismatch`]；
+    /// 起始快照与对应区块头的状态根不一致时同样报告为 StateRootMismatch
+    Block(ChainError),
+    /// 存储中的回执缺失或与重新执行得到的回执不一致
+    Receipts,
+    /// 重放到 head 后的状态与链当前的状态不一致
+    HeadState {
+        expected: Hash256,
+        actual: Hash256,
+    },
+}
+
+/// 第一个出现分歧的区块
+#[derive(Debug)]
+pub struct Divergence {
+    pub number: u64,
+    pub hash: Hash256,
+    pub kind: DivergenceKind,
+}
+
+/// 重放校验的结果
+#[derive(Debug)]
+pub struct VerificationReport {
+    /// 起始状态对应的高度，之后的区块被重新执行
+    pub start: u64,
+    /// 重新执行并通过比对的区块数
+    pub verified: u64,
+    /// 最后一个通过比对的区块执行后的状态根
+    pub state_root: Hash256,
+    pub divergence: Option<Divergence>,
+}
+
+impl VerificationReport {
+    pub fn is_ok(&self) -> bool {
+        self.divergence.is_none()
+    }
+}
@@ -5,6 +5,6 @@
 /// 它的所有方法都必须在 vtable 中是“确定签名”的，范型在编译后表示一系列方法，无法定位
 
 pub trait Codec: Send + Sync {
-    fn encode<T: ?Sized>(&self, v: &T) -> Result<Vec<u8>, String>;
-    fn decode<T>(&self, bytes: &Vec<u8>) -> Result<T, String>;
+    fn encode<T: ?Sized + serde::Serialize>(&self, v: &T) -> Result<Vec<u8>, String>;
+    fn decode<T: serde::de::DeserializeOwned>(&self, bytes: &Vec<u8>) -> Result<T, String>;
 }
@@ -1,4 +1,6 @@
-#[derive(Clone,Debug,PartialEq,Eq,Hash)]
+use serde::{Serialize, Deserialize};
+
+#[derive(Clone,Debug,PartialEq,Eq,Hash, Serialize, Deserialize)]
 pub struct Bytes(pub Vec<u8>);
 
 impl Bytes {
@@ -42,4 +42,26 @@ pub fn verify(
     sig: &Signature,
 ) -> bool {
     pubkey.verify(msg, sig).is_ok()
-}
\ No newline at end of file
+}
+
+/// 以原始字节形式校验签名，供交易等只持有 `Vec<u8>` 的结构使用
+///
+/// # Arguments
+/// * `pubkey` - 32 字节的 Ed25519 公钥
+/// * `msg` - 被签名的原始消息
+/// * `sig` - 64 字节的签名
+///
+/// # Returns
+/// 公钥或签名长度不合法、签名校验失败时返回 false
+pub fn verify_bytes(pubkey: &[u8], msg: &[u8], sig: &[u8]) -> bool {
+    let Ok(pubkey_bytes) = <[u8; 32]>::try_from(pubkey) else {
+        return false;
+    };
+    let Ok(pubkey) = VerifyingKey::from_bytes(&pubkey_bytes) else {
+        return false;
+    };
+    let Ok(sig) = Signature::from_slice(sig) else {
+        return false;
+    };
+    verify(&pubkey, msg, &sig)
+}
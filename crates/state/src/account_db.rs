@@ -1,10 +1,47 @@
+use crate::journal::Checkpoint;
 use latte_primitives::address::Address;
+use latte_primitives::hash::Hash256;
 use latte_types::account::Account;
 
 pub trait AccountReader {
     fn get(&self, addr: &Address) -> Option<&Account>;
+
+    /// 读取账户存储中的一个槽位
+    fn get_storage(&self, addr: &Address, key: &[u8]) -> Option<&[u8]>;
+
+    /// 按 code_hash 读取合约代码
+    fn get_code(&self, code_hash: &Hash256) -> Option<&[u8]>;
 }
 
 pub trait AccountWriter {
     fn get_mut(&mut self, addr: &Address) -> Option<&mut Account>;
+
+    /// 写入账户存储中的一个槽位
+    fn set_storage(&mut self, addr: &Address, key: Vec<u8>, value: Vec<u8>);
+}
+
+/// 执行交易所需的完整状态接口
+///
+/// [`crate::state::WorldState`] 直接修改状态；[`crate::overlay::StateOverlay`] 把修改记录在父状态之上的覆盖层中，
+/// 用于校验区块时的只读模拟
+pub trait AccountDb: AccountReader + AccountWriter {
+    /// 获取账户，不存在时创建一个空账户
+    fn get_or_create(&mut self, addr: &Address) -> &mut Account;
+
+    /// 部署合约代码，返回代码 hash
+    fn set_code(&mut self, addr: &Address, code: Vec<u8>) -> Hash256;
+
+    /// 当前状态的状态根，包括尚未提交 storage_root 的存储修改
+    fn state_root(&self) -> Hash256;
+
+    /// 创建检查点，之后的修改可以通过 [`AccountDb::revert_to`] 撤销
+    ///
+    /// 检查点可以嵌套，必须按创建的相反顺序提交或回滚
+    fn checkpoint(&mut self) -> Checkpoint;
+
+    /// 撤销检查点之后的所有修改
+    fn revert_to(&mut self, checkpoint: Checkpoint);
+
+    /// 保留检查点之后的修改
+    fn commit(&mut self, checkpoint: Checkpoint);
 }
@@ -0,0 +1,43 @@
+//! 单个账户的存储
+// 与账户本身分开维护，账户中只保存 storage_root
+
+use latte_primitives::hash::{Hash256, sha256};
+use std::collections::BTreeMap;
+
+#[derive(Default, Clone, Debug)]
+pub struct AccountStorage {
+    // BTreeMap 保证了遍历顺序，计算根时不需要额外排序
+    slots: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl AccountStorage {
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.slots.get(key).map(|value| value.as_slice())
+    }
+
+    pub fn set(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        self.slots.insert(key, value);
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        self.slots.remove(key)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// 存储根：对有序的 key/value 序列求 hash，空存储等于 `account::empty_hash()`
+    ///
+    /// 与状态根一样，后续替换为 Merkle Patricia Tree
+    pub fn root(&self) -> Hash256 {
+        let mut out = Vec::new();
+        for (key, value) in &self.slots {
+            out.extend((key.len() as u32).to_be_bytes());
+            out.extend(key);
+            out.extend((value.len() as u32).to_be_bytes());
+            out.extend(value);
+        }
+        sha256(&out)
+    }
+}
@@ -0,0 +1,34 @@
+//! 合约代码存储
+// 按内容寻址：key 为代码的 sha256，相同代码只保存一份
+
+use latte_primitives::hash::{Hash256, sha256};
+use std::collections::HashMap;
+
+#[derive(Default, Clone, Debug)]
+pub struct CodeStore {
+    codes: HashMap<Hash256, Vec<u8>>,
+}
+
+impl CodeStore {
+    /// 保存代码，返回代码 hash
+    pub fn insert(&mut self, code: Vec<u8>) -> Hash256 {
+        let hash = sha256(&code);
+        self.codes.entry(hash).or_insert(code);
+        hash
+    }
+
+    pub fn get(&self, code_hash: &Hash256) -> Option<&[u8]> {
+        self.codes.get(code_hash).map(|code| code.as_slice())
+    }
+
+    pub fn contains(&self, code_hash: &Hash256) -> bool {
+        self.codes.contains_key(code_hash)
+    }
+
+    /// 并入另一个 code store 中的代码
+    pub fn merge(&mut self, other: CodeStore) {
+        for (hash, code) in other.codes {
+            self.codes.entry(hash).or_insert(code);
+        }
+    }
+}
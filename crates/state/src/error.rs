@@ -5,6 +5,16 @@ pub enum StateError {
     InvalidNonce,
     #[error("insufficient balance")]
     InsufficientBalance,
+    #[error("insufficient balance to pay fee")]
+    InsufficientFee,
+    #[error("invalid transaction signature")]
+    InvalidSignature,
+    #[error("account not found")]
+    AccountNotFound,
+    #[error("transaction chain id {actual} does not match {expected}")]
+    InvalidChainId { expected: u64, actual: u64 },
+    #[error("gas limit below intrinsic gas")]
+    IntrinsicGasTooLow,
     #[error("vm execution failed")]
     VmExecutionFailed,
-}
\ No newline at end of file
+}
@@ -1,38 +1,326 @@
+use crate::account_db::AccountDb;
 use crate::error::StateError;
-use crate::state::WorldState;
 use crate::vm::VmEngine;
+use latte_primitives::address::Address;
 use latte_types::transaction::Transaction;
 
 pub struct Executor<'a, V: VmEngine> {
     vm: &'a V,
+    // 签名已经在区块校验阶段统一（并行）校验过时可以关闭
+    verify_signatures: bool,
+    // 每笔交易的固定开销，gas_limit 低于它的交易无效
+    intrinsic_gas: u64,
+    // 交易必须签给这条链，防止跨链重放
+    chain_id: u64,
 }
 
 // 提交交易，扣款和加钱
 impl<'a, V: VmEngine> Executor<'a, V> {
-    pub fn apply_tx(&self, state: &mut WorldState, tx: &Transaction) -> Result<(), StateError> {
+    pub fn new(vm: &'a V) -> Self {
+        Self {
+            vm,
+            verify_signatures: true,
+            intrinsic_gas: 0,
+            chain_id: 0,
+        }
+    }
+
+    /// 执行时跳过签名校验，调用方必须保证交易签名已经校验过
+    pub fn without_signature_check(mut self) -> Self {
+        self.verify_signatures = false;
+        self
+    }
+
+    pub fn with_intrinsic_gas(mut self, intrinsic_gas: u64) -> Self {
+        self.intrinsic_gas = intrinsic_gas;
+        self
+    }
+
+    pub fn with_chain_id(mut self, chain_id: u64) -> Self {
+        self.chain_id = chain_id;
+        self
+    }
+
+    /// 执行一笔交易
+    ///
+    /// - 普通交易：from 支付 value 和手续费 gas_limit * gas_price
+    /// - 代付交易：from 提供 nonce 和 value，手续费由 fee_payer 支付
+    ///
+    /// 状态修改在检查点内进行，返回错误时（包括 VM 执行失败）状态保持执行前的样子
+    ///
+    /// state 可以是 WorldState，也可以是校验区块时使用的覆盖层
+    pub fn apply_tx<S: AccountDb>(&self, state: &mut S, tx: &Transaction) -> Result<(), StateError> {
         let from = tx.from;
-        let sender = state.get_account_mut(&from).unwrap();
+        let payer = tx.payer();
+        let value = tx.value as u128;
+        let fee = tx.max_fee();
+
+        // 1. 校验签名，代付交易需要双方签名
+        if self.verify_signatures {
+            tx.verify_signatures()
+                .map_err(|_| StateError::InvalidSignature)?;
+        }
+
+        if tx.chain_id != self.chain_id {
+            return Err(StateError::InvalidChainId {
+                expected: self.chain_id,
+                actual: tx.chain_id,
+            });
+        }
+
+        if tx.gas_limit < self.intrinsic_gas {
+            return Err(StateError::IntrinsicGasTooLow);
+        }
 
-        // 1. 校验nonce
+        // 2. 校验nonce
+        let sender = state.get(&from).ok_or(StateError::AccountNotFound)?;
         if sender.nonce != tx.nonce {
             return Err(StateError::InvalidNonce);
         }
-        // 2. 校验balance
-        if sender.balance < tx.value as u128 {
+
+        // 3. 校验balance，付费方与发送方相同时需要同时覆盖 value 和手续费
+        if sender.balance < value {
             return Err(StateError::InsufficientBalance);
         }
-        // 3. 扣款
-        sender.balance -= tx.value as u128;
-        sender.nonce += 1;
-        // 4. 收款
-        if let Some(to) = tx.to {
-            let receiver = state.get_account_mut(&to).unwrap();
-            receiver.balance += tx.value as u128;
+        let payer_balance = if payer == from {
+            sender.balance - value
+        } else {
+            state.get(&payer).map_or(0, |a| a.balance)
+        };
+        if payer_balance < fee {
+            return Err(StateError::InsufficientFee);
+        }
+
+        let checkpoint = state.checkpoint();
+        match self.transfer(state, tx, fee) {
+            Ok(()) => {
+                state.commit(checkpoint);
+                Ok(())
+            }
+            Err(e) => {
+                state.revert_to(checkpoint);
+                Err(e)
+            }
         }
-        if !tx.data.is_empty() {
-            // 调用抽象的vm
-            self.vm.execute(state, from, tx)?;
+    }
+
+    /// 校验通过后修改状态，VM 执行失败时由调用方回滚
+    fn transfer<S: AccountDb>(
+        &self,
+        state: &mut S,
+        tx: &Transaction,
+        fee: u128,
+    ) -> Result<(), StateError> {
+        let from = tx.from;
+        let value = tx.value as u128;
+
+        // 4. 扣手续费
+        state
+            .get_mut(&tx.payer())
+            .ok_or(StateError::AccountNotFound)?
+            .balance -= fee;
+
+        // 5. 扣款
+        let sender = state.get_mut(&from).ok_or(StateError::AccountNotFound)?;
+        sender.balance -= value;
+        sender.nonce += 1;
+
+        // 6. 收款
+        match tx.to {
+            Some(to) => {
+                let receiver = state.get_or_create(&to);
+                receiver.balance += value;
+                // 调用合约，或执行 data 中携带的脚本
+                if receiver.is_contract() || !tx.data.is_empty() {
+                    self.vm.execute(state, from, tx)?;
+                }
+            }
+            None if !tx.data.is_empty() => {
+                // 创建合约：data 即合约代码
+                let contract = contract_address(&from, tx.nonce);
+                state.get_or_create(&contract).balance += value;
+                state.set_code(&contract, tx.data.clone());
+            }
+            None => {}
         }
         Ok(())
     }
 }
+
+/// 合约地址由创建者地址和创建时的 nonce 派生，同一账户每次创建得到不同地址
+pub fn contract_address(creator: &Address, nonce: u64) -> Address {
+    Address::from_pubkey(&[&creator.0[..], &nonce.to_be_bytes()[..]].concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::WorldState;
+    use latte_primitives::crypto::Keypair;
+    use latte_types::builder::TransactionBuilder;
+
+    struct NoopVm;
+
+    impl VmEngine for NoopVm {
+        fn execute<S: AccountDb>(
+            &self,
+            _: &mut S,
+            _: Address,
+            _: &Transaction,
+        ) -> Result<(), StateError> {
+            Ok(())
+        }
+    }
+
+    fn address_of(keypair: &Keypair) -> Address {
+        Address::from_pubkey(keypair.verifying.as_bytes())
+    }
+
+    fn sponsored_tx(sender: &Keypair, payer: &Keypair, to: Address) -> Transaction {
+        TransactionBuilder::new()
+            .to(to)
+            .value(10)
+            .gas_limit(100)
+            .gas_price(2)
+            .sign_sponsored(sender, payer)
+    }
+
+    #[test]
+    fn test_sponsored_tx_charges_fee_payer() {
+        let sender = Keypair::generate();
+        let payer = Keypair::generate();
+        let to = Address([9; 20]);
+
+        let mut state = WorldState::default();
+        state
+            .get_or_create_account_mut(&address_of(&sender))
+            .balance = 10;
+        state.get_or_create_account_mut(&address_of(&payer)).balance = 1_000;
+
+        let vm = NoopVm;
+        let executor = Executor::new(&vm);
+        executor
+            .apply_tx(&mut state, &sponsored_tx(&sender, &payer, to))
+            .expect("sponsored tx should apply");
+
+        let sender_account = state.get_account(&address_of(&sender)).unwrap();
+        assert_eq!(sender_account.balance, 0);
+        assert_eq!(sender_account.nonce, 1);
+        assert_eq!(state.get_account(&address_of(&payer)).unwrap().balance, 800);
+        assert_eq!(state.get_account(&to).unwrap().balance, 10);
+    }
+
+    #[test]
+    fn test_sponsored_tx_rejects_bad_payer_signature() {
+        let sender = Keypair::generate();
+        let payer = Keypair::generate();
+
+        let mut state = WorldState::default();
+        state
+            .get_or_create_account_mut(&address_of(&sender))
+            .balance = 10;
+        state.get_or_create_account_mut(&address_of(&payer)).balance = 1_000;
+
+        let mut tx = sponsored_tx(&sender, &payer, Address([9; 20]));
+        tx.fee_payer.as_mut().unwrap().signature[0] ^= 1;
+
+        let vm = NoopVm;
+        let result = Executor::new(&vm).apply_tx(&mut state, &tx);
+        assert!(matches!(result, Err(StateError::InvalidSignature)));
+        assert_eq!(
+            state.get_account(&address_of(&payer)).unwrap().balance,
+            1_000
+        );
+    }
+
+    struct FailingVm;
+
+    impl VmEngine for FailingVm {
+        fn execute<S: AccountDb>(
+            &self,
+            _: &mut S,
+            _: Address,
+            _: &Transaction,
+        ) -> Result<(), StateError> {
+            Err(StateError::VmExecutionFailed)
+        }
+    }
+
+    #[test]
+    fn test_vm_failure_leaves_no_partial_change() {
+        let sender = Keypair::generate();
+        let to = Address([9; 20]);
+        let mut state = WorldState::default();
+        state.get_or_create_account_mut(&address_of(&sender)).balance = 1_000;
+        let before = state.state_root();
+
+        let tx = TransactionBuilder::new()
+            .to(to)
+            .value(10)
+            .gas_limit(10)
+            .gas_price(1)
+            .data(vec![1])
+            .sign(&sender);
+        let vm = FailingVm;
+        let result = Executor::new(&vm).apply_tx(&mut state, &tx);
+        assert!(matches!(result, Err(StateError::VmExecutionFailed)));
+        assert_eq!(state.state_root(), before);
+        assert!(state.get_account(&to).is_none());
+    }
+
+    #[test]
+    fn test_rejects_other_chain_id() {
+        let sender = Keypair::generate();
+        let mut state = WorldState::default();
+        state.get_or_create_account_mut(&address_of(&sender)).balance = 1_000;
+
+        let tx = TransactionBuilder::new()
+            .chain_id(2)
+            .to(Address([9; 20]))
+            .value(10)
+            .sign(&sender);
+        let vm = NoopVm;
+        let result = Executor::new(&vm)
+            .with_chain_id(1)
+            .apply_tx(&mut state, &tx);
+        assert!(matches!(
+            result,
+            Err(StateError::InvalidChainId {
+                expected: 1,
+                actual: 2
+            })
+        ));
+        assert!(
+            Executor::new(&vm)
+                .with_chain_id(2)
+                .apply_tx(&mut state, &tx)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_create_contract_stores_code() {
+        let sender = Keypair::generate();
+        let mut state = WorldState::default();
+        state.get_or_create_account_mut(&address_of(&sender)).balance = 1_000;
+
+        let code = vec![0x0C, 0x0E];
+        let tx = TransactionBuilder::new()
+            .value(7)
+            .gas_limit(10)
+            .gas_price(1)
+            .data(code.clone())
+            .sign(&sender);
+
+        let vm = NoopVm;
+        Executor::new(&vm).apply_tx(&mut state, &tx).unwrap();
+
+        let contract = state
+            .get_account(&contract_address(&address_of(&sender), 0))
+            .cloned()
+            .expect("contract account should exist");
+        assert!(contract.is_contract());
+        assert_eq!(contract.balance, 7);
+        assert_eq!(state.get_code(&contract.code_hash), Some(code.as_slice()));
+    }
+}
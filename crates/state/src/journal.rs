@@ -0,0 +1,46 @@
+//! 状态修改日志
+// 记录每次修改前的旧值，用于回滚到检查点
+
+use latte_primitives::address::Address;
+use latte_types::account::Account;
+
+/// 一次修改前的旧值
+#[derive(Clone, Debug)]
+pub enum JournalEntry {
+    /// 账户修改前的值，None 表示账户原本不存在
+    Account {
+        addr: Address,
+        prev: Option<Account>,
+    },
+    /// 存储槽位修改前的值，None 表示槽位原本不存在
+    Storage {
+        addr: Address,
+        key: Vec<u8>,
+        prev: Option<Vec<u8>>,
+    },
+}
+
+/// 检查点，记录创建时日志的长度
+///
+/// 不可复制，保证每个检查点只能被提交或回滚一次
+#[derive(Debug)]
+pub struct Checkpoint(pub(crate) usize);
+
+/// 一段已提交修改的撤销日志，例如一个区块对状态的全部修改
+///
+/// 通过 [`crate::state::WorldState::apply_undo`] 可以把状态恢复到这段修改之前，
+/// 链重组时用它回退到共同祖先
+#[derive(Clone, Debug, Default)]
+pub struct StateUndo {
+    pub(crate) entries: Vec<JournalEntry>,
+}
+
+impl StateUndo {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
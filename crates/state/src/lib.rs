@@ -1,6 +1,10 @@
 pub mod state;
 pub mod account_db;
+pub mod account_storage;
+pub mod code_store;
 pub mod context;
 pub mod executor;
+pub mod journal;
+pub mod overlay;
 pub mod error;
 pub mod vm;
@@ -0,0 +1,285 @@
+//! 状态覆盖层
+// 在父状态之上记录修改，读取时优先读覆盖层，父状态本身不会被修改。
+// 用于校验区块：在覆盖层中模拟执行区块并计算状态根，校验通过后把修改应用到父状态上，不必重新执行
+
+use crate::account_db::{AccountDb, AccountReader, AccountWriter};
+use crate::code_store::CodeStore;
+use crate::journal::{Checkpoint, JournalEntry};
+use crate::state::{WorldState, root_of_sorted};
+use latte_primitives::address::Address;
+use latte_primitives::hash::Hash256;
+use latte_types::account::Account;
+use std::collections::{BTreeMap, HashMap};
+
+/// 覆盖层中的全部修改，通过 [`WorldState::apply_changes`] 应用到父状态上
+#[derive(Clone, Debug, Default)]
+pub struct StateChanges {
+    pub(crate) accounts: HashMap<Address, Account>,
+    pub(crate) storages: HashMap<Address, BTreeMap<Vec<u8>, Vec<u8>>>,
+    pub(crate) code: CodeStore,
+}
+
+#[derive(Clone)]
+pub struct StateOverlay<'a> {
+    base: &'a WorldState,
+    // 被修改过的账户的完整副本
+    accounts: HashMap<Address, Account>,
+    // 被写入的存储槽位，未写入的槽位读父状态
+    storages: HashMap<Address, BTreeMap<Vec<u8>, Vec<u8>>>,
+    code: CodeStore,
+    // 存在检查点时，记录覆盖层中每次修改前的旧值（None 表示覆盖层中原本没有）
+    journal: Vec<JournalEntry>,
+    checkpoints: usize,
+}
+
+impl<'a> StateOverlay<'a> {
+    pub fn new(base: &'a WorldState) -> Self {
+        Self {
+            base,
+            accounts: HashMap::new(),
+            storages: HashMap::new(),
+            code: CodeStore::default(),
+            journal: Vec::new(),
+            checkpoints: 0,
+        }
+    }
+
+    pub fn base(&self) -> &WorldState {
+        self.base
+    }
+
+    /// 被修改过的账户数量
+    pub fn modified_accounts(&self) -> usize {
+        self.accounts.len()
+    }
+
+    /// 取出覆盖层中的修改，结束对父状态的借用
+    pub fn into_changes(self) -> StateChanges {
+        StateChanges {
+            accounts: self.accounts,
+            storages: self.storages,
+            code: self.code,
+        }
+    }
+
+    fn record_account(&mut self, addr: &Address) {
+        if self.checkpoints > 0 {
+            self.journal.push(JournalEntry::Account {
+                addr: *addr,
+                prev: self.accounts.get(addr).cloned(),
+            });
+        }
+    }
+
+    /// 合并父状态与覆盖层中的写入后的存储根
+    fn storage_root(&self, addr: &Address, account: &Account) -> Hash256 {
+        match self.storages.get(addr) {
+            Some(slots) => {
+                let mut storage = self
+                    .base
+                    .account_storage(addr)
+                    .cloned()
+                    .unwrap_or_default();
+                for (key, value) in slots {
+                    storage.set(key.clone(), value.clone());
+                }
+                storage.root()
+            }
+            None => match self.base.get_account(addr) {
+                Some(base_account) => self.base.current_storage_root(addr, base_account),
+                None => account.storage_root,
+            },
+        }
+    }
+}
+
+impl AccountReader for StateOverlay<'_> {
+    fn get(&self, addr: &Address) -> Option<&Account> {
+        self.accounts
+            .get(addr)
+            .or_else(|| self.base.get_account(addr))
+    }
+
+    fn get_storage(&self, addr: &Address, key: &[u8]) -> Option<&[u8]> {
+        match self.storages.get(addr).and_then(|slots| slots.get(key)) {
+            Some(value) => Some(value.as_slice()),
+            None => self.base.get_storage(addr, key),
+        }
+    }
+
+    fn get_code(&self, code_hash: &Hash256) -> Option<&[u8]> {
+        self.code
+            .get(code_hash)
+            .or_else(|| self.base.get_code(code_hash))
+    }
+}
+
+impl AccountWriter for StateOverlay<'_> {
+    fn get_mut(&mut self, addr: &Address) -> Option<&mut Account> {
+        // 写时复制：第一次修改时把父状态中的账户复制到覆盖层
+        if !self.accounts.contains_key(addr) {
+            let account = self.base.get_account(addr)?.clone();
+            self.record_account(addr);
+            self.accounts.insert(*addr, account);
+        } else {
+            self.record_account(addr);
+        }
+        self.accounts.get_mut(addr)
+    }
+
+    fn set_storage(&mut self, addr: &Address, key: Vec<u8>, value: Vec<u8>) {
+        let slots = self.storages.entry(*addr).or_default();
+        let prev = slots.insert(key.clone(), value);
+        if self.checkpoints > 0 {
+            self.journal.push(JournalEntry::Storage {
+                addr: *addr,
+                key,
+                prev,
+            });
+        }
+    }
+}
+
+impl AccountDb for StateOverlay<'_> {
+    fn get_or_create(&mut self, addr: &Address) -> &mut Account {
+        self.record_account(addr);
+        if !self.accounts.contains_key(addr) {
+            let account = self
+                .base
+                .get_account(addr)
+                .cloned()
+                .unwrap_or_else(Account::empty);
+            self.accounts.insert(*addr, account);
+        }
+        self.accounts.get_mut(addr).expect("account inserted above")
+    }
+
+    fn set_code(&mut self, addr: &Address, code: Vec<u8>) -> Hash256 {
+        let code_hash = self.code.insert(code);
+        self.get_or_create(addr).code_hash = code_hash;
+        code_hash
+    }
+
+    /// 父状态的账户被覆盖层中的副本替换后计算状态根，结果与把同样的修改直接应用到父状态上一致
+    fn state_root(&self) -> Hash256 {
+        let mut accounts: Vec<(&Address, &Account)> = self
+            .base
+            .accounts()
+            .filter(|(addr, _)| !self.accounts.contains_key(addr))
+            .chain(self.accounts.iter())
+            .collect();
+        accounts.sort_by_key(|(addr, _)| addr.0);
+        root_of_sorted(
+            accounts
+                .into_iter()
+                .map(|(addr, account)| (addr, account, self.storage_root(addr, account))),
+        )
+    }
+
+    fn checkpoint(&mut self) -> Checkpoint {
+        self.checkpoints += 1;
+        Checkpoint(self.journal.len())
+    }
+
+    /// 撤销覆盖层中检查点之后的修改，父状态从未被修改，不需要处理
+    fn revert_to(&mut self, checkpoint: Checkpoint) {
+        for entry in self.journal.split_off(checkpoint.0).into_iter().rev() {
+            match entry {
+                JournalEntry::Account { addr, prev } => match prev {
+                    Some(account) => {
+                        self.accounts.insert(addr, account);
+                    }
+                    None => {
+                        self.accounts.remove(&addr);
+                    }
+                },
+                JournalEntry::Storage { addr, key, prev } => {
+                    let slots = self.storages.entry(addr).or_default();
+                    match prev {
+                        Some(value) => {
+                            slots.insert(key, value);
+                        }
+                        None => {
+                            slots.remove(&key);
+                        }
+                    }
+                    if slots.is_empty() {
+                        self.storages.remove(&addr);
+                    }
+                }
+            }
+        }
+        self.checkpoints -= 1;
+    }
+
+    fn commit(&mut self, _checkpoint: Checkpoint) {
+        self.checkpoints -= 1;
+        if self.checkpoints == 0 {
+            self.journal.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlay_matches_direct_writes() {
+        let (a, b) = (Address([1; 20]), Address([2; 20]));
+        let mut base = WorldState::default();
+        base.get_or_create_account_mut(&a).balance = 10;
+        base.set_storage(&a, vec![1], vec![1]);
+        base.commit_storage_roots();
+        let base_root = base.state_root();
+
+        let mut overlay = StateOverlay::new(&base);
+        overlay.get_mut(&a).unwrap().balance = 7;
+        overlay.set_storage(&a, vec![2], vec![2]);
+        overlay.get_or_create(&b).balance = 3;
+        let overlay_root = overlay.state_root();
+        assert_eq!(overlay.get_storage(&a, &[1]), Some(&[1u8][..]));
+
+        // 父状态不受影响
+        assert_eq!(base.state_root(), base_root);
+        assert_eq!(base.get_account(&a).unwrap().balance, 10);
+
+        let mut direct = WorldState::default();
+        direct.get_or_create_account_mut(&a).balance = 7;
+        direct.set_storage(&a, vec![1], vec![1]);
+        direct.set_storage(&a, vec![2], vec![2]);
+        direct.get_or_create_account_mut(&b).balance = 3;
+        assert_eq!(direct.state_root(), overlay_root);
+
+        // 把修改应用到父状态上得到同样的状态根，并且可以回滚
+        let changes = overlay.into_changes();
+        let checkpoint = base.checkpoint();
+        base.apply_changes(changes);
+        base.commit_storage_roots();
+        assert_eq!(base.state_root(), overlay_root);
+        base.revert_to(checkpoint);
+        assert_eq!(base.state_root(), base_root);
+    }
+
+    #[test]
+    fn test_overlay_revert_to_checkpoint() {
+        let (a, b) = (Address([1; 20]), Address([2; 20]));
+        let mut base = WorldState::default();
+        base.get_or_create_account_mut(&a).balance = 10;
+
+        let mut overlay = StateOverlay::new(&base);
+        overlay.get_mut(&a).unwrap().balance = 9;
+        let before = overlay.state_root();
+
+        let checkpoint = overlay.checkpoint();
+        overlay.get_mut(&a).unwrap().balance = 1;
+        overlay.get_or_create(&b).balance = 3;
+        overlay.set_storage(&a, vec![1], vec![1]);
+        overlay.revert_to(checkpoint);
+
+        assert_eq!(overlay.get(&a).unwrap().balance, 9);
+        assert!(overlay.get(&b).is_none());
+        assert!(overlay.get_storage(&a, &[1]).is_none());
+        assert_eq!(overlay.state_root(), before);
+    }
+}
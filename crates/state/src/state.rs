@@ -2,13 +2,27 @@
 // 维护账号的状态
 
 use latte_primitives::address::Address;
+use latte_primitives::hash::{Hash256, sha256};
 use latte_types::account::Account;
-use std::collections::HashMap;
-use crate::account_db::{AccountReader, AccountWriter};
+use std::collections::{HashMap, HashSet};
+use crate::account_db::{AccountDb, AccountReader, AccountWriter};
+use crate::account_storage::AccountStorage;
+use crate::code_store::CodeStore;
+use crate::journal::{Checkpoint, JournalEntry, StateUndo};
+use crate::overlay::StateChanges;
 
 #[derive(Default)]
 pub struct WorldState {
     accounts: HashMap<Address, Account>,
+    // 每个账户的存储单独维护，账户中只记录 storage_root
+    storages: HashMap<Address, AccountStorage>,
+    code: CodeStore,
+    // 存储被修改、storage_root 尚未更新的账户
+    dirty_storages: HashSet<Address>,
+    // 存在检查点时，记录每次修改前的旧值
+    journal: Vec<JournalEntry>,
+    // 尚未提交或回滚的检查点数量
+    checkpoints: usize,
 }
 
 impl WorldState {
@@ -17,8 +31,245 @@ impl WorldState {
     }
 
     pub fn get_account_mut(&mut self, addr: &Address) -> Option<&mut Account> {
+        if self.accounts.contains_key(addr) {
+            self.record_account(addr);
+        }
         self.accounts.get_mut(addr)
     }
+
+    /// 获取账户，不存在时创建一个空账户（例如首次收款）
+    pub fn get_or_create_account_mut(&mut self, addr: &Address) -> &mut Account {
+        self.record_account(addr);
+        self.accounts.entry(*addr).or_insert_with(Account::empty)
+    }
+
+    pub fn get_storage(&self, addr: &Address, key: &[u8]) -> Option<&[u8]> {
+        self.storages.get(addr).and_then(|storage| storage.get(key))
+    }
+
+    /// 写入存储槽位，storage_root 延迟到 [`WorldState::commit_storage_roots`] 时更新
+    pub fn set_storage(&mut self, addr: &Address, key: Vec<u8>, value: Vec<u8>) {
+        if self.checkpoints > 0 {
+            let prev = self.get_storage(addr, &key).map(|v| v.to_vec());
+            self.journal.push(JournalEntry::Storage {
+                addr: *addr,
+                key: key.clone(),
+                prev,
+            });
+        }
+        self.storages.entry(*addr).or_default().set(key, value);
+        self.dirty_storages.insert(*addr);
+    }
+
+    pub fn get_code(&self, code_hash: &Hash256) -> Option<&[u8]> {
+        self.code.get(code_hash)
+    }
+
+    /// 部署合约代码：代码存入 code store，账户记录 code_hash
+    ///
+    /// 回滚只撤销账户上的 code_hash，code store 按内容寻址，多余的代码不影响状态根
+    pub fn set_code(&mut self, addr: &Address, code: Vec<u8>) -> Hash256 {
+        let code_hash = self.code.insert(code);
+        self.get_or_create_account_mut(addr).code_hash = code_hash;
+        code_hash
+    }
+
+    /// 应用覆盖层中的修改，与直接在本状态上执行同样的操作等价
+    ///
+    /// 修改同样记入日志，可以通过检查点回滚；storage_root 仍然延迟到 [`WorldState::commit_storage_roots`] 时更新
+    pub fn apply_changes(&mut self, changes: StateChanges) {
+        for (addr, account) in changes.accounts {
+            *self.get_or_create_account_mut(&addr) = account;
+        }
+        for (addr, slots) in changes.storages {
+            for (key, value) in slots {
+                self.set_storage(&addr, key, value);
+            }
+        }
+        self.code.merge(changes.code);
+    }
+
+    /// 创建检查点，之后的所有修改都可以通过 [`WorldState::revert_to`] 撤销
+    ///
+    /// 检查点可以嵌套，必须按创建的相反顺序提交或回滚
+    pub fn checkpoint(&mut self) -> Checkpoint {
+        self.checkpoints += 1;
+        Checkpoint(self.journal.len())
+    }
+
+    /// 撤销检查点之后的所有修改
+    pub fn revert_to(&mut self, checkpoint: Checkpoint) {
+        while self.journal.len() > checkpoint.0 {
+            match self.journal.pop() {
+                Some(entry) => self.undo_entry(entry),
+                None => break,
+            }
+        }
+        self.checkpoints -= 1;
+    }
+
+    /// 保留检查点之后的修改
+    ///
+    /// 外层检查点仍可能回滚，所以只有最外层提交时才清空日志
+    pub fn commit(&mut self, _checkpoint: Checkpoint) {
+        self.checkpoints -= 1;
+        if self.checkpoints == 0 {
+            self.journal.clear();
+        }
+    }
+
+    /// 提交检查点，并取出检查点之后的修改作为撤销日志
+    ///
+    /// 与 [`WorldState::commit`] 不同，取出的修改不再属于外层检查点
+    pub fn commit_with_undo(&mut self, checkpoint: Checkpoint) -> StateUndo {
+        let entries = self.journal.split_off(checkpoint.0);
+        self.checkpoints -= 1;
+        StateUndo { entries }
+    }
+
+    /// 按相反顺序撤销一段已提交的修改
+    pub fn apply_undo(&mut self, undo: StateUndo) {
+        for entry in undo.entries.into_iter().rev() {
+            self.undo_entry(entry);
+        }
+    }
+
+    fn undo_entry(&mut self, entry: JournalEntry) {
+        match entry {
+            JournalEntry::Account { addr, prev } => match prev {
+                Some(account) => {
+                    self.accounts.insert(addr, account);
+                }
+                None => {
+                    self.accounts.remove(&addr);
+                }
+            },
+            JournalEntry::Storage { addr, key, prev } => {
+                let storage = self.storages.entry(addr).or_default();
+                match prev {
+                    Some(value) => storage.set(key, value),
+                    None => {
+                        storage.remove(&key);
+                    }
+                }
+                // storage_root 需要按回滚后的存储重新计算
+                self.dirty_storages.insert(addr);
+            }
+        }
+    }
+
+    fn record_account(&mut self, addr: &Address) {
+        if self.checkpoints > 0 {
+            self.journal.push(JournalEntry::Account {
+                addr: *addr,
+                prev: self.accounts.get(addr).cloned(),
+            });
+        }
+    }
+
+    /// 把被修改过的存储根写回账户
+    ///
+    /// 账户已经不存在时（例如创建账户的修改被回滚）跳过，不会为此凭空创建账户
+    pub fn commit_storage_roots(&mut self) {
+        for addr in std::mem::take(&mut self.dirty_storages) {
+            let root = self.storage_root_of(&addr);
+            if let Some(account) = self.get_account_mut(&addr) {
+                account.storage_root = root;
+            }
+        }
+    }
+
+    /// 账户的存储，没有写入过存储的账户返回 None
+    pub(crate) fn account_storage(&self, addr: &Address) -> Option<&AccountStorage> {
+        self.storages.get(addr)
+    }
+
+    /// 账户当前的存储根，存储被修改过时按修改后的存储计算
+    pub(crate) fn current_storage_root(&self, addr: &Address, account: &Account) -> Hash256 {
+        if self.dirty_storages.contains(addr) {
+            self.storage_root_of(addr)
+        } else {
+            account.storage_root
+        }
+    }
+
+    /// 所有账户，顺序不确定
+    pub(crate) fn accounts(&self) -> impl Iterator<Item = (&Address, &Account)> {
+        self.accounts.iter()
+    }
+
+    fn storage_root_of(&self, addr: &Address) -> Hash256 {
+        match self.storages.get(addr) {
+            Some(storage) => storage.root(),
+            None => latte_types::account::empty_hash(),
+        }
+    }
+
+    /// 所有账户余额之和，即当前的总供应量，超出 u128 时返回 None
+    pub fn total_balance(&self) -> Option<u128> {
+        self.accounts
+            .values()
+            .try_fold(0u128, |sum, account| sum.checked_add(account.balance))
+    }
+
+    /// 一段已提交修改中余额的变化：(增加之和, 减少之和)，超出 u128 时返回 None
+    ///
+    /// 只比较被修改过的账户在修改前后的余额，不必重新累加所有账户
+    pub fn balance_change(&self, undo: &StateUndo) -> Option<(u128, u128)> {
+        let mut seen = HashSet::new();
+        let (mut increase, mut decrease) = (0u128, 0u128);
+        for entry in &undo.entries {
+            let JournalEntry::Account { addr, prev } = entry else {
+                continue;
+            };
+            // 同一账户可能被修改多次，第一条记录才是修改前的余额
+            if !seen.insert(*addr) {
+                continue;
+            }
+            let before = prev.as_ref().map_or(0, |account| account.balance);
+            let after = self.accounts.get(addr).map_or(0, |account| account.balance);
+            if after >= before {
+                increase = increase.checked_add(after - before)?;
+            } else {
+                decrease = decrease.checked_add(before - after)?;
+            }
+        }
+        Some((increase, decrease))
+    }
+
+    /// 计算状态根
+    ///
+    /// 目前是一个扁平的承诺：按地址排序后对每个账户求摘要，再对摘要序列整体求 hash，
+    /// 后续替换为 Merkle Patricia Tree。
+    /// 存储被修改过的账户会重新计算 storage_root，其余账户直接使用已提交的值
+    pub fn state_root(&self) -> Hash256 {
+        let mut accounts: Vec<(&Address, &Account)> = self.accounts.iter().collect();
+        accounts.sort_by_key(|(addr, _)| addr.0);
+        root_of_sorted(accounts.into_iter().map(|(addr, account)| {
+            (addr, account, self.current_storage_root(addr, account))
+        }))
+    }
+}
+
+/// 按地址有序的账户摘要序列求状态根，与 [`WorldState::state_root`] 的计算方式一致
+pub(crate) fn root_of_sorted<'a>(
+    accounts: impl Iterator<Item = (&'a Address, &'a Account, Hash256)>,
+) -> Hash256 {
+    let mut out = Vec::new();
+    for (addr, account, storage_root) in accounts {
+        out.extend(account_digest(addr, account, &storage_root).0);
+    }
+    sha256(&out)
+}
+
+fn account_digest(addr: &Address, account: &Account, storage_root: &Hash256) -> Hash256 {
+    let mut out = Vec::new();
+    out.extend(addr.0);
+    out.extend(account.nonce.to_be_bytes());
+    out.extend(account.balance.to_be_bytes());
+    out.extend(account.code_hash.0);
+    out.extend(storage_root.0);
+    sha256(&out)
 }
 
 
@@ -26,10 +277,134 @@ impl AccountReader for WorldState {
     fn get(&self, addr: &Address) -> Option<&Account> {
         self.get_account(addr)
     }
+
+    fn get_storage(&self, addr: &Address, key: &[u8]) -> Option<&[u8]> {
+        WorldState::get_storage(self, addr, key)
+    }
+
+    fn get_code(&self, code_hash: &Hash256) -> Option<&[u8]> {
+        WorldState::get_code(self, code_hash)
+    }
 }
 
 impl AccountWriter for WorldState {
     fn get_mut(&mut self, addr: &Address) -> Option<&mut Account> {
         self.get_account_mut(addr)
     }
-}
\ No newline at end of file
+
+    fn set_storage(&mut self, addr: &Address, key: Vec<u8>, value: Vec<u8>) {
+        WorldState::set_storage(self, addr, key, value)
+    }
+}
+
+impl AccountDb for WorldState {
+    fn get_or_create(&mut self, addr: &Address) -> &mut Account {
+        self.get_or_create_account_mut(addr)
+    }
+
+    fn set_code(&mut self, addr: &Address, code: Vec<u8>) -> Hash256 {
+        WorldState::set_code(self, addr, code)
+    }
+
+    fn state_root(&self) -> Hash256 {
+        WorldState::state_root(self)
+    }
+
+    fn checkpoint(&mut self) -> Checkpoint {
+        WorldState::checkpoint(self)
+    }
+
+    fn revert_to(&mut self, checkpoint: Checkpoint) {
+        WorldState::revert_to(self, checkpoint)
+    }
+
+    fn commit(&mut self, checkpoint: Checkpoint) {
+        WorldState::commit(self, checkpoint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commit_storage_roots_skips_missing_account() {
+        let addr = Address([1; 20]);
+        let mut state = WorldState::default();
+        let empty_root = state.state_root();
+
+        // 账户的创建被回滚，但存储仍被标记为已修改
+        let checkpoint = state.checkpoint();
+        state.get_or_create_account_mut(&addr);
+        state.set_storage(&addr, vec![1], vec![2]);
+        state.revert_to(checkpoint);
+        state.commit_storage_roots();
+
+        assert!(state.get_account(&addr).is_none());
+        assert_eq!(state.state_root(), empty_root);
+    }
+
+    #[test]
+    fn test_storage_root_committed_lazily() {
+        let addr = Address([1; 20]);
+        let mut state = WorldState::default();
+        state.get_or_create_account_mut(&addr);
+        let empty_root = state.state_root();
+
+        state.set_storage(&addr, vec![1], vec![2]);
+        let dirty_root = state.state_root();
+        assert_ne!(empty_root, dirty_root);
+        // 未提交前账户中的 storage_root 仍是旧值，但状态根已经反映了修改
+        assert_eq!(state.get_account(&addr).unwrap().storage_root, latte_types::account::empty_hash());
+
+        state.commit_storage_roots();
+        assert_ne!(state.get_account(&addr).unwrap().storage_root, latte_types::account::empty_hash());
+        assert_eq!(state.state_root(), dirty_root);
+    }
+
+    #[test]
+    fn test_balance_change_of_committed_block() {
+        let (a, b, c) = (Address([1; 20]), Address([2; 20]), Address([3; 20]));
+        let mut state = WorldState::default();
+        state.get_or_create_account_mut(&a).balance = 100;
+        state.get_or_create_account_mut(&b).balance = 50;
+
+        let checkpoint = state.checkpoint();
+        state.get_account_mut(&a).unwrap().balance = 70;
+        state.get_account_mut(&a).unwrap().balance = 60;
+        state.get_account_mut(&b).unwrap().balance = 80;
+        state.get_or_create_account_mut(&c).balance = 15;
+        let undo = state.commit_with_undo(checkpoint);
+
+        // a 减少 40，b 增加 30，新账户 c 增加 15
+        assert_eq!(state.balance_change(&undo), Some((45, 40)));
+        assert_eq!(state.total_balance(), Some(155));
+
+        state.get_account_mut(&b).unwrap().balance = u128::MAX;
+        assert_eq!(state.total_balance(), None);
+    }
+
+    #[test]
+    fn test_revert_to_checkpoint() {
+        let addr = Address([1; 20]);
+        let mut state = WorldState::default();
+        state.get_or_create_account_mut(&addr).balance = 10;
+        state.set_storage(&addr, vec![1], vec![1]);
+        state.commit_storage_roots();
+        let root = state.state_root();
+
+        let outer = state.checkpoint();
+        state.get_account_mut(&addr).unwrap().balance = 20;
+        let inner = state.checkpoint();
+        state.set_storage(&addr, vec![1], vec![2]);
+        state.get_or_create_account_mut(&Address([2; 20])).balance = 5;
+        state.commit(inner);
+        state.commit_storage_roots();
+        state.revert_to(outer);
+
+        assert_eq!(state.get_account(&addr).unwrap().balance, 10);
+        assert_eq!(state.get_storage(&addr, &[1]), Some(&[1u8][..]));
+        assert!(state.get_account(&Address([2; 20])).is_none());
+        assert_eq!(state.state_root(), root);
+    }
+}
@@ -1,12 +1,12 @@
+use crate::account_db::AccountDb;
 use crate::error::StateError;
-use crate::state::WorldState;
 use latte_primitives::address::Address;
 use latte_types::transaction::Transaction;
 
 pub trait VmEngine {
-    fn execute(
+    fn execute<S: AccountDb>(
         &self,
-        state: &mut WorldState,
+        state: &mut S,
         caller: Address,
         tx: &Transaction,
     ) -> Result<(), StateError>;
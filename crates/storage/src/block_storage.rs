@@ -1,19 +1,70 @@
-use latte_chain::storage::BlockStorage;
+use latte_chain::bad_blocks::BadBlock;
+use latte_chain::storage::{BlockStorage, StorageBatch, StorageOp, TxLocation};
 use latte_chain::storage_error::StorageError;
 use latte_codec::codec::Codec;
+use latte_primitives::hash::Hash256;
 use latte_types::block::Block;
-use rocksdb::{DB, Options, Error};
+use latte_types::receipt::Receipt;
+use rocksdb::{DB, Direction, IteratorMode, Options, WriteBatch};
 
-struct RocksDbBlockStorage<C: Codec> {
+// key 前缀，不同的表共用一个 DB
+// - b + hash -> 区块
+// - n + hash -> 高度
+// - d + hash -> 累计难度（大端）
+// - c + 高度（大端）-> 主链区块 hash，大端保证按高度有序
+// - r + 区块 hash -> 回执列表
+// - t + 交易 hash -> 区块 hash + 下标（大端）
+// - h -> head 区块 hash
+// - f -> 最终确认的区块 hash
+// - x + 区块 hash -> 无效区块记录
+const BLOCK_PREFIX: u8 = b'b';
+const NUMBER_PREFIX: u8 = b'n';
+const TOTAL_DIFFICULTY_PREFIX: u8 = b'd';
+const CANONICAL_PREFIX: u8 = b'c';
+const RECEIPTS_PREFIX: u8 = b'r';
+const TX_LOCATION_PREFIX: u8 = b't';
+const HEAD_KEY: &[u8] = b"h";
+const FINALIZED_KEY: &[u8] = b"f";
+const BAD_BLOCK_PREFIX: u8 = b'x';
+
+fn block_key(hash: &Hash256) -> Vec<u8> {
+    [&[BLOCK_PREFIX][..], &hash.0[..]].concat()
+}
+
+fn number_key(hash: &Hash256) -> Vec<u8> {
+    [&[NUMBER_PREFIX][..], &hash.0[..]].concat()
+}
+
+fn total_difficulty_key(hash: &Hash256) -> Vec<u8> {
+    [&[TOTAL_DIFFICULTY_PREFIX][..], &hash.0[..]].concat()
+}
+
+fn canonical_key(number: u64) -> Vec<u8> {
+    [&[CANONICAL_PREFIX][..], &number.to_be_bytes()[..]].concat()
+}
+
+fn receipts_key(hash: &Hash256) -> Vec<u8> {
+    [&[RECEIPTS_PREFIX][..], &hash.0[..]].concat()
+}
+
+fn tx_location_key(hash: &Hash256) -> Vec<u8> {
+    [&[TX_LOCATION_PREFIX][..], &hash.0[..]].concat()
+}
+
+fn bad_block_key(hash: &Hash256) -> Vec<u8> {
+    [&[BAD_BLOCK_PREFIX][..], &hash.0[..]].concat()
+}
+
+pub struct RocksDbBlockStorage<C: Codec> {
     db: rocksdb::DB,
     codec: C,
 }
 
 impl<C: Codec> RocksDbBlockStorage<C> {
-    fn new(path: &str, c: C) -> Self {
+    pub fn new(path: &str, c: C) -> Self {
         let mut opts = Options::default();
         opts.create_if_missing(true);
-        let db_result = DB::open(&opts, "./block_db");
+        let db_result = DB::open(&opts, path);
         match db_result {
             Ok(db) => Self { db, codec: c },
             Err(e) => {
@@ -21,46 +72,209 @@ impl<C: Codec> RocksDbBlockStorage<C> {
             }
         }
     }
+
+    fn get_raw(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        self.db.get(key).map_err(|e| StorageError::Db(e.to_string()))
+    }
+
+    fn get_hash(&self, key: &[u8]) -> Result<Option<Hash256>, StorageError> {
+        match self.get_raw(key)? {
+            Some(value) => {
+                let bytes: [u8; 32] = value
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| StorageError::CorruptedData)?;
+                Ok(Some(Hash256(bytes)))
+            }
+            None => Ok(None),
+        }
+    }
 }
 
 impl<C: Codec> BlockStorage for RocksDbBlockStorage<C> {
-    fn get_block(
-        &self,
-        block_hash: latte_primitives::hash::Hash256,
-    ) -> Result<Option<Block>, StorageError> {
-        let result = self.db.get(block_hash.0);
-        match result {
-            Ok(Some(value)) => {
-                let block: Result<Block, String> = self.codec.decode(&value.to_vec());
+    fn get_block(&self, block_hash: Hash256) -> Result<Option<Block>, StorageError> {
+        match self.get_raw(&block_key(&block_hash))? {
+            Some(value) => {
+                let block: Result<Block, String> = self.codec.decode(&value);
                 match block {
                     Ok(block) => Ok(Some(block)),
                     Err(e) => Err(StorageError::BlockGetFailed(e.to_string())),
                 }
             }
-            Ok(None) => Err(StorageError::BlockNotFound),
-            Err(e) => Err(StorageError::Db(e.to_string()).into()),
+            None => Ok(None),
+        }
+    }
+
+    fn get_block_number(&self, hash: Hash256) -> Result<Option<u64>, StorageError> {
+        match self.get_raw(&number_key(&hash))? {
+            Some(value) => {
+                let bytes: [u8; 8] = value
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| StorageError::CorruptedData)?;
+                Ok(Some(u64::from_be_bytes(bytes)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn get_total_difficulty(&self, hash: Hash256) -> Result<Option<u128>, StorageError> {
+        match self.get_raw(&total_difficulty_key(&hash))? {
+            Some(value) => {
+                let bytes: [u8; 16] = value
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| StorageError::CorruptedData)?;
+                Ok(Some(u128::from_be_bytes(bytes)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn get_canonical_hash(&self, number: u64) -> Result<Option<Hash256>, StorageError> {
+        match self.get_raw(&canonical_key(number))? {
+            Some(value) => {
+                let bytes: [u8; 32] = value
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| StorageError::CorruptedData)?;
+                Ok(Some(Hash256(bytes)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn get_receipts(&self, block_hash: Hash256) -> Result<Option<Vec<Receipt>>, StorageError> {
+        match self.get_raw(&receipts_key(&block_hash))? {
+            Some(value) => {
+                let receipts: Result<Vec<Receipt>, String> = self.codec.decode(&value);
+                receipts
+                    .map(Some)
+                    .map_err(|e| StorageError::BlockGetFailed(e.to_string()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn get_tx_location(&self, tx_hash: Hash256) -> Result<Option<TxLocation>, StorageError> {
+        match self.get_raw(&tx_location_key(&tx_hash))? {
+            Some(value) => {
+                if value.len() != 40 {
+                    return Err(StorageError::CorruptedData);
+                }
+                let (hash, index) = value.split_at(32);
+                let hash: [u8; 32] = hash.try_into().map_err(|_| StorageError::CorruptedData)?;
+                let index: [u8; 8] = index.try_into().map_err(|_| StorageError::CorruptedData)?;
+                Ok(Some(TxLocation {
+                    block_hash: Hash256(hash),
+                    index: u64::from_be_bytes(index) as usize,
+                }))
+            }
+            None => Ok(None),
         }
     }
 
-    fn put_block(&self, block: &Block) -> Result<(), StorageError> {
-        let hash_byte;
-        let block_byte;
+    fn get_head(&self) -> Result<Option<Hash256>, StorageError> {
+        self.get_hash(HEAD_KEY)
+    }
+
+    fn get_finalized(&self) -> Result<Option<Hash256>, StorageError> {
+        self.get_hash(FINALIZED_KEY)
+    }
 
-        match self.codec.encode(&block.header.number) {
-            Ok(data) => {hash_byte = data;}
-            Err(e) => return Err(StorageError::Db(e).into()),
+    fn get_bad_block(&self, hash: Hash256) -> Result<Option<BadBlock>, StorageError> {
+        match self.get_raw(&bad_block_key(&hash))? {
+            Some(value) => {
+                let bad_block: Result<BadBlock, String> = self.codec.decode(&value);
+                bad_block
+                    .map(Some)
+                    .map_err(|e| StorageError::BlockGetFailed(e.to_string()))
+            }
+            None => Ok(None),
         }
+    }
 
-        match self.codec.encode(&block) {
-            Ok(data) => {block_byte = data;}
-            Err(e) => return Err(StorageError::Db(e).into()),
+    fn get_bad_blocks(&self) -> Result<Vec<BadBlock>, StorageError> {
+        let prefix = [BAD_BLOCK_PREFIX];
+        let mut bad_blocks = Vec::new();
+        // 没有配置前缀提取器，迭代到前缀之外的 key 时停止
+        for entry in self
+            .db
+            .iterator(IteratorMode::From(&prefix, Direction::Forward))
+        {
+            let (key, value) = entry.map_err(|e| StorageError::Db(e.to_string()))?;
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            let bad_block: Result<BadBlock, String> = self.codec.decode(&value.into_vec());
+            bad_blocks.push(bad_block.map_err(|e| StorageError::BlockGetFailed(e.to_string()))?);
         }
+        Ok(bad_blocks)
+    }
 
-        let result = self.db.put(hash_byte,block_byte);
-        match result {
-            Ok(_) => {Ok(())}
-            Err(e) => Err(StorageError::Db(e.to_string()).into()),
+    fn write_batch(&self, batch: StorageBatch) -> Result<(), StorageError> {
+        let mut write_batch = WriteBatch::default();
+        for op in batch.into_ops() {
+            match op {
+                StorageOp::PutBlock(hash, block) => {
+                    let block_byte = self
+                        .codec
+                        .encode(block.as_ref())
+                        .map_err(StorageError::BlockSaveFailed)?;
+                    write_batch.put(block_key(&hash), block_byte);
+                    write_batch.put(number_key(&hash), block.header.number.to_be_bytes());
+                }
+                StorageOp::SetTotalDifficulty(hash, total_difficulty) => {
+                    write_batch.put(total_difficulty_key(&hash), total_difficulty.to_be_bytes());
+                }
+                StorageOp::SetCanonical(number, hash) => {
+                    write_batch.put(canonical_key(number), hash.0);
+                }
+                StorageOp::RemoveCanonical(number) => {
+                    write_batch.delete(canonical_key(number));
+                }
+                StorageOp::PutReceipts(block_hash, receipts) => {
+                    let receipts_byte = self
+                        .codec
+                        .encode(&receipts)
+                        .map_err(StorageError::BlockSaveFailed)?;
+                    write_batch.put(receipts_key(&block_hash), receipts_byte);
+                }
+                StorageOp::SetTxLocation(tx_hash, location) => {
+                    let index = (location.index as u64).to_be_bytes();
+                    let value = [&location.block_hash.0[..], &index[..]].concat();
+                    write_batch.put(tx_location_key(&tx_hash), value);
+                }
+                StorageOp::RemoveTxLocation(tx_hash) => {
+                    write_batch.delete(tx_location_key(&tx_hash));
+                }
+                StorageOp::SetHead(hash) => {
+                    write_batch.put(HEAD_KEY, hash.0);
+                }
+                StorageOp::SetFinalized(hash) => {
+                    write_batch.put(FINALIZED_KEY, hash.0);
+                }
+                StorageOp::DeleteBlock(hash) => {
+                    write_batch.delete(block_key(&hash));
+                    write_batch.delete(number_key(&hash));
+                    write_batch.delete(total_difficulty_key(&hash));
+                    write_batch.delete(receipts_key(&hash));
+                }
+                StorageOp::PutBadBlock(bad_block) => {
+                    let value = self
+                        .codec
+                        .encode(bad_block.as_ref())
+                        .map_err(StorageError::BlockSaveFailed)?;
+                    write_batch.put(bad_block_key(&bad_block.hash), value);
+                }
+                StorageOp::DeleteBadBlock(hash) => {
+                    write_batch.delete(bad_block_key(&hash));
+                }
+            }
         }
 
+        self.db
+            .write(write_batch)
+            .map_err(|e| StorageError::Db(e.to_string()))
     }
 }
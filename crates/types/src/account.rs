@@ -1,11 +1,19 @@
+use latte_primitives::hash::{Hash256, sha256};
 use serde::{Serialize, Deserialize};
-use std::collections::BTreeMap;
 
-#[derive(Serialize, Deserialize,Clone, Debug)]
+/// 账户
+///
+/// 合约代码和合约存储不再内联在账户中，账户只保存它们的承诺：
+/// - code_hash：代码的 sha256，代码本身按内容寻址存放在 code store 中
+/// - storage_root：账户存储的根，存储本身单独维护
+///
+/// 这样读取账户时不会把大合约的全部存储一起反序列化
+#[derive(Serialize, Deserialize,Clone, Debug, PartialEq, Eq)]
 pub struct Account {
     pub nonce: u64,
     pub balance: u128,
-    pub storage: BTreeMap<Vec<u8>, Vec<u8>>,
+    pub code_hash: Hash256,
+    pub storage_root: Hash256,
 }
 
 impl Account {
@@ -13,7 +21,18 @@ impl Account {
         Account{
             nonce:0,
             balance:0,
-            storage: BTreeMap::new(),
+            code_hash: empty_hash(),
+            storage_root: empty_hash(),
         }
     }
+
+    /// 有代码的账户即合约账户
+    pub fn is_contract(&self) -> bool {
+        self.code_hash != empty_hash()
+    }
+}
+
+/// 空代码的 code_hash，同时也是空存储的 storage_root
+pub fn empty_hash() -> Hash256 {
+    sha256(&[])
 }
@@ -0,0 +1,151 @@
+use crate::transaction::{FeePayer, Transaction};
+use latte_primitives::address::Address;
+use latte_primitives::crypto::Keypair;
+
+/// 交易构造器
+///
+/// 发送方地址和公钥在签名时由密钥对推导，避免手动填写导致地址与公钥不一致
+///
+/// ```ignore
+/// let tx = TransactionBuilder::new()
+///     .to(receiver)
+///     .value(100)
+///     .nonce(0)
+///     .gas_limit(21_000)
+///     .gas_price(1)
+///     .sign(&keypair);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TransactionBuilder {
+    chain_id: u64,
+    to: Option<Address>,
+    value: u64,
+    nonce: u64,
+    gas_limit: u64,
+    gas_price: u64,
+    data: Vec<u8>,
+}
+
+impl TransactionBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn chain_id(mut self, chain_id: u64) -> Self {
+        self.chain_id = chain_id;
+        self
+    }
+
+    pub fn to(mut self, to: Address) -> Self {
+        self.to = Some(to);
+        self
+    }
+
+    pub fn value(mut self, value: u64) -> Self {
+        self.value = value;
+        self
+    }
+
+    pub fn nonce(mut self, nonce: u64) -> Self {
+        self.nonce = nonce;
+        self
+    }
+
+    pub fn gas_limit(mut self, gas_limit: u64) -> Self {
+        self.gas_limit = gas_limit;
+        self
+    }
+
+    pub fn gas_price(mut self, gas_price: u64) -> Self {
+        self.gas_price = gas_price;
+        self
+    }
+
+    pub fn data(mut self, data: Vec<u8>) -> Self {
+        self.data = data;
+        self
+    }
+
+    /// 由发送方签名，生成普通交易
+    pub fn sign(self, sender: &Keypair) -> Transaction {
+        let mut tx = self.unsigned(sender, None);
+        tx.signature = sender.sign(&tx.signing_bytes()).to_bytes().to_vec();
+        tx
+    }
+
+    /// 由发送方和付费方共同签名，生成代付交易
+    pub fn sign_sponsored(self, sender: &Keypair, fee_payer: &Keypair) -> Transaction {
+        let mut tx = self.unsigned(sender, Some(fee_payer));
+        let msg = tx.signing_bytes();
+        tx.signature = sender.sign(&msg).to_bytes().to_vec();
+        if let Some(payer) = tx.fee_payer.as_mut() {
+            payer.signature = fee_payer.sign(&msg).to_bytes().to_vec();
+        }
+        tx
+    }
+
+    fn unsigned(self, sender: &Keypair, fee_payer: Option<&Keypair>) -> Transaction {
+        let public_key = sender.verifying.as_bytes().to_vec();
+        Transaction {
+            chain_id: self.chain_id,
+            from: Address::from_pubkey(&public_key),
+            to: self.to,
+            value: self.value,
+            nonce: self.nonce,
+            gas_limit: self.gas_limit,
+            gas_price: self.gas_price,
+            data: self.data,
+            public_key,
+            signature: Vec::new(),
+            fee_payer: fee_payer.map(|payer| {
+                let public_key = payer.verifying.as_bytes().to_vec();
+                FeePayer {
+                    address: Address::from_pubkey(&public_key),
+                    public_key,
+                    signature: Vec::new(),
+                }
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signed_transactions_verify() {
+        let sender = Keypair::generate();
+        let payer = Keypair::generate();
+
+        let tx = TransactionBuilder::new()
+            .to(Address([1; 20]))
+            .value(5)
+            .gas_limit(10)
+            .gas_price(1)
+            .sign(&sender);
+        assert!(tx.verify_signatures().is_ok());
+
+        let sponsored = TransactionBuilder::new()
+            .to(Address([1; 20]))
+            .value(5)
+            .sign_sponsored(&sender, &payer);
+        assert!(sponsored.verify_signatures().is_ok());
+        assert_eq!(sponsored.payer(), Address::from_pubkey(payer.verifying.as_bytes()));
+    }
+
+    #[test]
+    fn test_signature_commits_to_chain_id() {
+        let sender = Keypair::generate();
+        let mut tx = TransactionBuilder::new()
+            .chain_id(1)
+            .to(Address([1; 20]))
+            .value(5)
+            .sign(&sender);
+        assert!(tx.verify_signatures().is_ok());
+
+        // 改成其他链的 id 后签名失效
+        tx.chain_id = 2;
+        assert!(tx.verify_signatures().is_err());
+    }
+}
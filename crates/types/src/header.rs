@@ -1,4 +1,5 @@
 use latte_codec::codec::Codec;
+use latte_primitives::address::Address;
 use latte_primitives::hash::{Hash256, sha256};
 use serde::{Deserialize, Serialize};
 use std::hash::Hash;
@@ -23,8 +24,16 @@ pub struct BlockHeader {
     pub state_root: Hash256,
     // 保证：输入数据没有被改动；Merkle Root。它确保了区块体里的交易列表是完整且顺序正确
     pub tx_root: Hash256,
+    // 交易回执的 Merkle Root，保证执行结果（状态、gas 消耗）没有被改动
+    pub receipt_root: Hash256,
     pub number: u64, // 高度，与height是一个东西
     pub timestamp: u64, // second
+    pub gas_used: u64, // 区块内所有交易消耗的 gas 总和
+    pub beneficiary: Address, // 出块者，接收区块奖励和交易手续费
+    pub difficulty: u64, // 出块难度，分叉选择按累计难度选出最重的链
+    pub nonce: u64, // 工作量证明的随机数，使区块 hash 不超过难度对应的目标值
+    // 附加数据，创世区块用它承诺链 id、初始验证者等不在状态中的参数
+    pub extra_data: Vec<u8>,
 }
 
 impl BlockHeader {
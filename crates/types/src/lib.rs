@@ -3,3 +3,4 @@ pub mod transaction;
 pub mod header;
 pub mod account;
 pub mod receipt;
+pub mod builder;
@@ -1,6 +1,12 @@
 use latte_primitives::bytes::Bytes;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+/// 交易执行成功
+pub const STATUS_SUCCESS: u8 = 1;
+/// 交易执行失败
+pub const STATUS_FAILED: u8 = 0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Receipt {
     pub transaction_hash: Bytes,
     pub status: Bytes,
@@ -17,4 +23,8 @@ impl Receipt {
             logs
         }
     }
-}
\ No newline at end of file
+
+    pub fn is_success(&self) -> bool {
+        self.status.as_slice() == [STATUS_SUCCESS]
+    }
+}
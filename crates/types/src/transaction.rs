@@ -1,8 +1,12 @@
-use latte_primitives::{address::Address, hash::Hash256};
+use latte_primitives::address::Address;
+use latte_primitives::crypto;
+use latte_primitives::error::BlockchainError;
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Transaction {
+    // 链 id，签名覆盖该字段，防止交易被重放到使用相同密钥的其他链上
+    pub chain_id: u64,
     pub from: Address,
     pub to: Option<Address>,
     pub value: u64,
@@ -10,5 +14,99 @@ pub struct Transaction {
     pub gas_limit: u64,
     pub gas_price: u64,
     pub data: Vec<u8>,
+    // 发送方公钥，Ed25519 无法从签名中恢复公钥，需要随交易携带，并与 from 地址绑定
+    pub public_key: Vec<u8>,
     pub signature: Vec<u8>,
+    // 代付方：存在时由代付方支付 gas_limit * gas_price，from 只提供 nonce 和 value
+    pub fee_payer: Option<FeePayer>,
+}
+
+/// 代付交易中的付费方
+///
+/// 付费方与发送方签署同一份消息（见 [`Transaction::signing_bytes`]），
+/// 消息中包含付费方地址，因此发送方也确认了由谁代付。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FeePayer {
+    pub address: Address,
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl Transaction {
+    /// 需要签名的消息：除签名以外的全部字段，包括链 id
+    ///
+    /// data 带长度前缀，避免与后续字段拼接时产生歧义
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend(self.chain_id.to_be_bytes());
+        out.extend(self.from.0);
+
+        match &self.to {
+            Some(addr) => out.extend(addr.0),
+            None => out.extend([0u8; 20]), // 约定 None = 20 个 0
+        }
+
+        out.extend(self.value.to_be_bytes());
+        out.extend(self.nonce.to_be_bytes());
+        out.extend(self.gas_limit.to_be_bytes());
+        out.extend(self.gas_price.to_be_bytes());
+        out.extend((self.data.len() as u32).to_be_bytes());
+        out.extend(&self.data);
+
+        match &self.fee_payer {
+            Some(payer) => {
+                out.push(1);
+                out.extend(payer.address.0);
+            }
+            None => out.push(0),
+        }
+        out
+    }
+
+    /// 实际支付手续费的账户
+    pub fn payer(&self) -> Address {
+        match &self.fee_payer {
+            Some(payer) => payer.address,
+            None => self.from,
+        }
+    }
+
+    pub fn is_sponsored(&self) -> bool {
+        self.fee_payer.is_some()
+    }
+
+    /// 交易最多需要支付的手续费：gas_limit * gas_price
+    pub fn max_fee(&self) -> u128 {
+        self.gas_limit as u128 * self.gas_price as u128
+    }
+
+    /// 校验发送方签名，代付交易还需校验付费方签名
+    ///
+    /// 每一方都要满足：
+    /// - 公钥派生出的地址与声明的地址一致（地址绑定）
+    /// - 签名是该公钥对 [`Transaction::signing_bytes`] 的有效签名
+    pub fn verify_signatures(&self) -> Result<(), BlockchainError> {
+        let msg = self.signing_bytes();
+        verify_party(&self.from, &self.public_key, &msg, &self.signature)?;
+
+        if let Some(payer) = &self.fee_payer {
+            verify_party(&payer.address, &payer.public_key, &msg, &payer.signature)?;
+        }
+        Ok(())
+    }
+}
+
+fn verify_party(
+    address: &Address,
+    public_key: &[u8],
+    msg: &[u8],
+    signature: &[u8],
+) -> Result<(), BlockchainError> {
+    if Address::from_pubkey(public_key) != *address {
+        return Err(BlockchainError::InvalidSignature);
+    }
+    if !crypto::verify_bytes(public_key, msg, signature) {
+        return Err(BlockchainError::InvalidSignature);
+    }
+    Ok(())
 }
@@ -1,9 +1,9 @@
-use crate::gas::GasMeter;
-use crate::instruction::Instruction;
+use crate::gas::{GasMeter, GasSchedule};
+use crate::instruction::{Instruction, InstructionSet};
 use crate::interpreter::Interpreter;
 use latte_primitives::address::Address;
+use latte_state::account_db::AccountDb;
 use latte_state::error::StateError;
-use latte_state::state::WorldState;
 use latte_state::vm::VmEngine;
 use latte_types::transaction::Transaction;
 
@@ -15,7 +15,7 @@ use latte_types::transaction::Transaction;
 /// 在 Latte 区块链上，每个交易都会被解码为一个 `Transaction` 对象，
 /// 然后通过 `ScriptVm` 的 `execute` 方法执行。
 ///
-/// `execute` 方法接受一个 `WorldState` 对象和一个 `Transaction` 对象作为参数，
+/// `execute` 方法接受一个实现了 `AccountDb` 的状态（`WorldState` 或状态覆盖层）和一个 `Transaction` 对象作为参数，
 /// 并返回一个 `Result`，其中 `Ok` 表示执行成功，`Err` 表示执行失败。
 ///
 /// 在执行过程中，`ScriptVm` 会解码交易的 bytecode，创建一个解释器，
@@ -23,34 +23,56 @@ use latte_types::transaction::Transaction;
 ///
 /// 如果执行成功，`execute` 方法返回 `Ok(())`；如果执行失败，`execute` 方法返回
 /// `Err(StateError::VmExecutionFailed)`。
-pub struct ScriptVm;
+///
+/// gas 价格和可用的指令集由协议规则决定，`new` 使用最初的规则。
+#[derive(Clone, Debug, Default)]
+pub struct ScriptVm {
+    schedule: GasSchedule,
+    instructions: InstructionSet,
+}
 
 impl ScriptVm {
     pub fn new() -> Self {
-        ScriptVm
+        Self::default()
+    }
+
+    pub fn with_rules(schedule: GasSchedule, instructions: InstructionSet) -> Self {
+        Self {
+            schedule,
+            instructions,
+        }
     }
 }
 
 impl VmEngine for ScriptVm {
-    fn execute(
+    fn execute<S: AccountDb>(
         &self,
-        state: &mut WorldState,
+        state: &mut S,
         caller: Address,
         tx: &Transaction,
     ) -> Result<(), StateError> {
-        // 1. 解码 bytecode
-        let code = decode_instructions(&tx.data).map_err(|_| StateError::VmExecutionFailed)?;
+        // 1. 确定要执行的代码：调用合约时执行合约代码，否则执行交易携带的脚本
+        let (address, bytecode) = match contract_code(state, tx.to) {
+            Some((contract, code)) => (contract, code),
+            None => (caller, tx.data.clone()),
+        };
+
+        // 2. 解码 bytecode
+        let code = decode_instructions(&bytecode, self.instructions)
+            .map_err(|_| StateError::VmExecutionFailed)?;
 
-        // 2. 创建解释器
+        // 3. 创建解释器
         let mut interpreter = Interpreter {
             state,
             caller,
+            address,
             pc: 0,
             stack: Default::default(),
             gas: GasMeter::new(tx.gas_limit),
+            schedule: self.schedule,
         };
 
-        // 3. 执行
+        // 4. 执行
         interpreter
             .execute(&code)
             .map_err(|_| StateError::VmExecutionFailed)?;
@@ -59,22 +81,33 @@ impl VmEngine for ScriptVm {
     }
 }
 
+/// 目标账户是合约时，返回合约地址和合约代码
+fn contract_code<S: AccountDb>(state: &S, to: Option<Address>) -> Option<(Address, Vec<u8>)> {
+    let to = to?;
+    let account = state.get(&to).filter(|account| account.is_contract())?;
+    let code = state.get_code(&account.code_hash)?;
+    Some((to, code.to_vec()))
+}
+
 /// `decode_instructions` 是一个用于解码 bytecode 的函数。
 ///
 /// 输入是一个 `Vec<u8>` 类型的 bytecode，输出是一个 `Result<Vec<Instruction>, ()>` 类型的解码后的指令序列。
 ///
 /// 该函数会遍历 bytecode 中的每个字节，并根据字节的值解析为一个 `Instruction` 对象，
 /// 并将其添加到结果序列中。如果解析过程中遇到错误，比如 bytecode 格式错误、数据不足等，
-/// 会返回一个 `Err(())` 结果。
+/// 会返回一个 `Err(())` 结果。不在 instructions 指令集中的操作码同样视为错误。
 ///
 /// 注意，解码过程中会修改输入的字节数组，因此输入参数是不可变引用，但会在解码过程中修改。
 #[allow(dead_code)]
-fn decode_instructions(data: &[u8]) -> Result<Vec<Instruction>, ()> {
+fn decode_instructions(data: &[u8], set: InstructionSet) -> Result<Vec<Instruction>, ()> {
     let mut instructions = Vec::new();
     let mut i = 0;
     while i < data.len() {
         let op_code = data[i];
         i += 1;
+        if !set.supports(op_code) {
+            return Err(());
+        }
 
         let instruction = match op_code {
             // Push 指令
@@ -108,6 +141,7 @@ fn decode_instructions(data: &[u8]) -> Result<Vec<Instruction>, ()> {
             0x0C => Instruction::Dup,
             0x0D => Instruction::Pop,
             0x0E => Instruction::Return,
+            0x0F => Instruction::Mod,
             _ => return Err(()),
         };
         instructions.push(instruction);
@@ -123,7 +157,8 @@ mod tests {
     fn test_decode_instructions() {
         // 测试数据：Add(0x01), Eq(0x05), Load(0x08), Dup(0x0C), Return(0x0E)
         let data = [0x01, 0x05, 0x08, 0x0C, 0x0E];
-        let instructions = decode_instructions(&data).expect("decode instructions failed");
+        let instructions =
+            decode_instructions(&data, InstructionSet::V1).expect("decode instructions failed");
         assert_eq!(instructions.len(), 5);
         assert_eq!(instructions[0], Instruction::Add);
         assert_eq!(instructions[1], Instruction::Eq);
@@ -131,4 +166,12 @@ mod tests {
         assert_eq!(instructions[3], Instruction::Dup);
         assert_eq!(instructions[4], Instruction::Return);
     }
+
+    #[test]
+    fn test_instruction_set() {
+        let data = [0x00, 0, 0, 0, 0, 0, 0, 0, 7, 0x0F];
+        assert!(decode_instructions(&data, InstructionSet::V1).is_err());
+        let instructions = decode_instructions(&data, InstructionSet::V2).unwrap();
+        assert_eq!(instructions[1], Instruction::Mod);
+    }
 }
@@ -13,4 +13,7 @@ pub enum VMError {
     #[error("divide by zero")]
     DivideByZero,
 
+    #[error("arithmetic overflow")]
+    ArithmeticOverflow,
+
 }
\ No newline at end of file
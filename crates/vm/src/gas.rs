@@ -1,4 +1,6 @@
 use crate::error::VMError;
+use crate::instruction::Instruction;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug)]
 pub struct GasMeter {
@@ -18,3 +20,33 @@ impl GasMeter {
         Ok(())
     }
 }
+
+/// 每条指令的 gas 价格，可以随协议升级调整
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GasSchedule {
+    // 除存储读写外的普通指令
+    pub step: u64,
+    pub load: u64,
+    pub store: u64,
+}
+
+impl Default for GasSchedule {
+    fn default() -> Self {
+        Self {
+            step: 1,
+            load: 1,
+            store: 1,
+        }
+    }
+}
+
+impl GasSchedule {
+    pub fn cost(&self, instruction: &Instruction) -> u64 {
+        match instruction {
+            Instruction::Load => self.load,
+            Instruction::Store => self.store,
+            _ => self.step,
+        }
+    }
+}
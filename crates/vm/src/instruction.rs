@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 
 /// `Instruction` 是 Latte 虚拟机的指令枚举类型。
 ///
@@ -10,6 +11,7 @@
 /// - `Sub`：将栈顶的两个数值相减并将结果压入栈顶。
 /// - `Mul`：将栈顶的两个数值相乘并将结果压入栈顶。
 /// - `Div`：将栈顶的两个数值相除并将结果压入栈顶。
+/// - `Mod`：将栈顶的两个数值取余并将结果压入栈顶，从 [`InstructionSet::V2`] 开始可用。
 /// - `Eq`：将栈顶的两个数值进行相等性比较，并将结果压入栈顶。
 /// - `Gt`：将栈顶的两个数值进行大于性比较，并将结果压入栈顶。
 /// - `Lt`：将栈顶的两个数值进行小于性比较，并将结果压入栈顶。
@@ -24,13 +26,14 @@ pub enum Instruction {
     Sub,
     Mul,
     Div,
+    Mod,
 
     Eq,
     Gt,
     Lt,
 
-    Load,  // 从 account.storage 读
-    Store, // 写入 account.storage
+    Load,  // 从当前账户的存储读
+    Store, // 写入当前账户的存储
 
     Jump(usize),
     JumpIf(usize),
@@ -40,3 +43,23 @@ pub enum Instruction {
 
     Return,
 }
+
+/// 可用的指令集，新的指令随协议升级启用，旧区块仍按当时的指令集执行
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstructionSet {
+    /// 0x00 ~ 0x0E
+    #[default]
+    V1,
+    /// 增加 Mod（0x0F）
+    V2,
+}
+
+impl InstructionSet {
+    pub fn supports(&self, op_code: u8) -> bool {
+        match self {
+            InstructionSet::V1 => op_code <= 0x0E,
+            InstructionSet::V2 => op_code <= 0x0F,
+        }
+    }
+}
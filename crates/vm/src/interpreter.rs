@@ -1,23 +1,26 @@
 use crate::error::VMError;
-use crate::gas::GasMeter;
+use crate::gas::{GasMeter, GasSchedule};
 use crate::instruction::Instruction;
 use crate::stack::Stack;
 use latte_primitives::address::Address;
-use latte_state::account_db::AccountWriter;
+use latte_state::account_db::{AccountReader, AccountWriter};
 
-pub struct Interpreter<'a, S: AccountWriter> {
+pub struct Interpreter<'a, S: AccountReader + AccountWriter> {
     pub state: &'a mut S,
     pub caller: Address,
+    // 当前执行上下文所属的账户，Load/Store 读写它的存储；调用合约时为合约地址
+    pub address: Address,
     pub pc: usize,
     pub stack: Stack,
     pub gas: GasMeter,
+    pub schedule: GasSchedule,
 }
 
 ///
 /// 对pc的检查，放置越界
 /// 除0的异常
 /// 相似的重复代码抽取为宏
-impl<'a, S: AccountWriter> Interpreter<'a, S> {
+impl<'a, S: AccountReader + AccountWriter> Interpreter<'a, S> {
     pub fn execute(&mut self, code: &[Instruction]) -> Result<(), VMError> {
         while self.pc < code.len() {
             // 获取当前指令并提前增加 PC，Jump 指令会覆盖它
@@ -25,7 +28,7 @@ impl<'a, S: AccountWriter> Interpreter<'a, S> {
             self.pc += 1;
 
             // 扣费
-            self.gas.charge(1)?;
+            self.gas.charge(self.schedule.cost(instruction))?;
             match instruction {
                 Instruction::Push(v) => {
                     self.stack.push(*v);
@@ -51,7 +54,19 @@ impl<'a, S: AccountWriter> Interpreter<'a, S> {
                         return Err(VMError::DivideByZero);
                     }
                     let a = self.stack.pop()?;
-                    self.stack.push(a / b);
+                    // i64::MIN / -1 溢出
+                    let value = a.checked_div(b).ok_or(VMError::ArithmeticOverflow)?;
+                    self.stack.push(value);
+                }
+                Instruction::Mod => {
+                    let b = self.stack.pop()?;
+                    if b == 0 {
+                        return Err(VMError::DivideByZero);
+                    }
+                    let a = self.stack.pop()?;
+                    // i64::MIN % -1 溢出
+                    let value = a.checked_rem(b).ok_or(VMError::ArithmeticOverflow)?;
+                    self.stack.push(value);
                 }
                 Instruction::Eq => {
                     let b = self.stack.pop()?;
@@ -70,25 +85,24 @@ impl<'a, S: AccountWriter> Interpreter<'a, S> {
                 }
                 Instruction::Load => {
                     let key = self.stack.pop()? as u64;
-                    if let Some(account) = self.state.get_mut(&self.caller) {
-                        // 从站定取出账号的key，然后根据caller从state获取账户，从account中根据key获取值，如果获取不成功，则取默认值0
-                        let value = account
-                            .storage
-                            .get(&key.to_be_bytes().to_vec())
-                            .map(|v| i64::from_be_bytes(v.clone().try_into().unwrap()))
-                            .unwrap_or(0);
-                        // 和其他指令一样，将值写入栈
-                        self.stack.push(value);
-                    }
+                    // 从栈顶取出key，从当前账户的存储中获取值，如果获取不成功，则取默认值0
+                    let value = self
+                        .state
+                        .get_storage(&self.address, &key.to_be_bytes())
+                        .and_then(|v| v.try_into().ok())
+                        .map(i64::from_be_bytes)
+                        .unwrap_or(0);
+                    // 和其他指令一样，将值写入栈
+                    self.stack.push(value);
                 }
                 Instruction::Store => {
                     let key = self.stack.pop()? as u64;
                     let val = self.stack.pop()?;
-                    if let Some(account) = self.state.get_mut(&self.caller) {
-                        account
-                            .storage
-                            .insert(key.to_be_bytes().to_vec(), val.to_be_bytes().to_vec());
-                    }
+                    self.state.set_storage(
+                        &self.address,
+                        key.to_be_bytes().to_vec(),
+                        val.to_be_bytes().to_vec(),
+                    );
                 }
                 Instruction::Jump(target) => {
                     if *target >= code.len() {
@@ -121,3 +135,33 @@ impl<'a, S: AccountWriter> Interpreter<'a, S> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use latte_state::state::WorldState;
+
+    fn run(code: &[Instruction]) -> Result<(), VMError> {
+        let mut state = WorldState::default();
+        let mut interpreter = Interpreter {
+            state: &mut state,
+            caller: Address([1; 20]),
+            address: Address([2; 20]),
+            pc: 0,
+            stack: Default::default(),
+            gas: GasMeter::new(1_000),
+            schedule: GasSchedule::default(),
+        };
+        interpreter.execute(code)
+    }
+
+    #[test]
+    fn test_min_div_and_mod_by_minus_one_overflow() {
+        use Instruction::{Div, Mod, Push};
+        for op in [Mod, Div] {
+            let code = [Push(i64::MIN), Push(-1), op];
+            assert!(matches!(run(&code), Err(VMError::ArithmeticOverflow)));
+        }
+        assert!(run(&[Push(-7), Push(2), Mod]).is_ok());
+    }
+}